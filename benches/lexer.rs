@@ -0,0 +1,50 @@
+//! Measures lexer throughput on a large generated input.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SNIPPET: &str = r#"# 1 "bench.c"
+/* A function that exercises most kinds of tokens. */
+static unsigned long counter_17 = 0x1Ful;
+
+int compute_value(int first_argument, long second_argument) {
+    // Line comments are skipped as well.
+    double ratio = 1.5e-3 + .25f;
+    char letter = '\n';
+    const char *message = "hello, world\t" "and more";
+    for (int index = 0; index < 1000; index++) {
+        if (first_argument >= second_argument && !(index % 7)) {
+            first_argument += index << 2 | (second_argument >> 1);
+        } else {
+            second_argument -= first_argument ^ 017;
+        }
+    }
+    return first_argument ? first_argument : -1;
+}
+"#;
+
+fn main() {
+    let target_size = 8 * 1024 * 1024;
+    let source = SNIPPET.repeat(target_size / SNIPPET.len() + 1);
+    let megabytes = source.len() as f64 / (1024.0 * 1024.0);
+
+    // Warm up caches and the allocator before measuring.
+    black_box(myc::lex(&source).count());
+
+    let runs = 10;
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..runs {
+        let start = Instant::now();
+        tokens = black_box(myc::lex(black_box(&source)).count());
+        best = best.min(start.elapsed());
+    }
+
+    println!(
+        "lexed {megabytes:.1} MiB ({tokens} tokens) in {:.1} ms: {:.1} MiB/s (best of {runs})",
+        best.as_secs_f64() * 1000.0,
+        megabytes / best.as_secs_f64(),
+    );
+}
@@ -1,37 +1,121 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
+use crate::ast::{Const, Type};
+use crate::semantic::{IdentifierAttributes, SymbolTable};
 use crate::tacky;
 
+#[derive(Debug, PartialEq, Eq)]
 pub struct Program {
-    pub function: Function,
+    pub functions: Vec<Function>,
+    pub static_variables: Vec<StaticVariable>,
 }
 
 pub type Identifier = String;
+#[derive(Debug, PartialEq, Eq)]
 pub struct Function {
     pub name: Identifier,
+    pub global: bool,
     pub instructions: Vec<Instruction>,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct StaticVariable {
+    pub name: Identifier,
+    pub global: bool,
+    pub init: Const,
+    /// Alignment in bytes.
+    pub alignment: u32,
+}
+
+/// The size of an operand: `l` for 4 and `q` for 8 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssemblyType {
+    Longword,
+    Quadword,
+}
+
+impl AssemblyType {
+    /// The suffix of an instruction operating on operands of this size.
+    pub fn suffix(&self) -> char {
+        match self {
+            AssemblyType::Longword => 'l',
+            AssemblyType::Quadword => 'q',
+        }
+    }
+}
+
+impl From<&Type> for AssemblyType {
+    fn from(ty: &Type) -> Self {
+        match ty.size() {
+            4 => AssemblyType::Longword,
+            _ => AssemblyType::Quadword,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum Instruction {
     Mov {
+        ty: AssemblyType,
+        src: Operand,
+        dst: Operand,
+    },
+    /// Sign extends a longword to a quadword.
+    Movsx {
+        src: Operand,
+        dst: Operand,
+    },
+    /// Zero extends a longword to a quadword. Replaced by plain moves while
+    /// fixing up, as writing a longword register clears its upper half.
+    MovZeroExtend {
         src: Operand,
         dst: Operand,
     },
     Unary {
         operator: UnaryOperator,
+        ty: AssemblyType,
         operand: Operand,
     },
     Binary {
         operator: BinaryOperator,
+        ty: AssemblyType,
         src: Operand,
         dst: Operand,
     },
-    Idiv(Operand),
-    Cdq,
+    Cmp {
+        ty: AssemblyType,
+        src: Operand,
+        dst: Operand,
+    },
+    Idiv(AssemblyType, Operand),
+    /// Unsigned division of `%edx:%eax` or `%rdx:%rax`.
+    Div(AssemblyType, Operand),
+    /// Sign extends `%eax` into `%edx`, or `%rax` into `%rdx` (`cqo`).
+    Cdq(AssemblyType),
+    Jmp(Identifier),
+    JmpCC(CondCode, Identifier),
+    SetCC(CondCode, Operand),
+    Label(Identifier),
+    /// Jumps to `targets[%eax]` through a table of offsets in `.rodata`.
+    JumpTable {
+        table: Identifier,
+        targets: Vec<Identifier>,
+    },
     AllocateStack(u32),
+    DeallocateStack(u32),
+    Push(Operand),
+    /// Calls `name`, through the PLT if it is not defined in this program.
+    Call {
+        name: Identifier,
+        external: bool,
+    },
     Ret,
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum UnaryOperator {
     Neg,
     Not,
@@ -42,6 +126,7 @@ impl From<tacky::UnaryOperator> for UnaryOperator {
         match value {
             tacky::UnaryOperator::Complement => Self::Not,
             tacky::UnaryOperator::Negate => Self::Neg,
+            tacky::UnaryOperator::Not => unreachable!("logical not is lowered to a comparison"),
         }
     }
 }
@@ -49,17 +134,30 @@ impl From<tacky::UnaryOperator> for UnaryOperator {
 impl Display for UnaryOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let out = match self {
-            UnaryOperator::Neg => "negl",
-            UnaryOperator::Not => "notl",
+            UnaryOperator::Neg => "neg",
+            UnaryOperator::Not => "not",
         };
         write!(f, "{out}")
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum BinaryOperator {
     Add,
     Sub,
     Mult,
+    And,
+    Or,
+    Xor,
+    Sal,
+    Sar,
+    Shr,
+}
+
+impl BinaryOperator {
+    pub fn is_shift(&self) -> bool {
+        matches!(self, Self::Sal | Self::Sar | Self::Shr)
+    }
 }
 
 impl TryFrom<tacky::BinaryOperator> for BinaryOperator {
@@ -70,9 +168,16 @@ impl TryFrom<tacky::BinaryOperator> for BinaryOperator {
             tacky::BinaryOperator::Add => Ok(Self::Add),
             tacky::BinaryOperator::Subtract => Ok(Self::Sub),
             tacky::BinaryOperator::Multiply => Ok(Self::Mult),
-            // TODO: Fix blub strings
-            tacky::BinaryOperator::Divide => Err("blub".into()),
-            tacky::BinaryOperator::Remainder => Err("blub".into()),
+            tacky::BinaryOperator::BitwiseAnd => Ok(Self::And),
+            tacky::BinaryOperator::BitwiseOr => Ok(Self::Or),
+            tacky::BinaryOperator::BitwiseXor => Ok(Self::Xor),
+            tacky::BinaryOperator::ShiftLeft => Ok(Self::Sal),
+            // Shifts of unsigned values are replaced by `shr` when lowering.
+            tacky::BinaryOperator::ShiftRight => Ok(Self::Sar),
+            op @ (tacky::BinaryOperator::Divide | tacky::BinaryOperator::Remainder) => {
+                Err(format!("{op:?} is lowered to idiv"))
+            }
+            op => Err(format!("{op:?} is lowered to a comparison")),
         }
     }
 }
@@ -80,26 +185,102 @@ impl TryFrom<tacky::BinaryOperator> for BinaryOperator {
 impl Display for BinaryOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let out = match self {
-            BinaryOperator::Add => "addl",
-            BinaryOperator::Sub => "subl",
-            BinaryOperator::Mult => "imull",
+            BinaryOperator::Add => "add",
+            BinaryOperator::Sub => "sub",
+            BinaryOperator::Mult => "imul",
+            BinaryOperator::And => "and",
+            BinaryOperator::Or => "or",
+            BinaryOperator::Xor => "xor",
+            BinaryOperator::Sal => "sal",
+            BinaryOperator::Sar => "sar",
+            BinaryOperator::Shr => "shr",
+        };
+        write!(f, "{out}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CondCode {
+    E,
+    NE,
+    G,
+    GE,
+    L,
+    LE,
+    A,
+    AE,
+    B,
+    BE,
+}
+
+impl CondCode {
+    /// The condition code of the comparison `operator`, which compares signed
+    /// or unsigned operands.
+    fn from_comparison(operator: &tacky::BinaryOperator, signed: bool) -> Option<Self> {
+        Some(match (operator, signed) {
+            (tacky::BinaryOperator::Equal, _) => Self::E,
+            (tacky::BinaryOperator::NotEqual, _) => Self::NE,
+            (tacky::BinaryOperator::LessThan, true) => Self::L,
+            (tacky::BinaryOperator::LessOrEqual, true) => Self::LE,
+            (tacky::BinaryOperator::GreaterThan, true) => Self::G,
+            (tacky::BinaryOperator::GreaterOrEqual, true) => Self::GE,
+            (tacky::BinaryOperator::LessThan, false) => Self::B,
+            (tacky::BinaryOperator::LessOrEqual, false) => Self::BE,
+            (tacky::BinaryOperator::GreaterThan, false) => Self::A,
+            (tacky::BinaryOperator::GreaterOrEqual, false) => Self::AE,
+            _ => return None,
+        })
+    }
+}
+
+impl Display for CondCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            CondCode::E => "e",
+            CondCode::NE => "ne",
+            CondCode::G => "g",
+            CondCode::GE => "ge",
+            CondCode::L => "l",
+            CondCode::LE => "le",
+            CondCode::A => "a",
+            CondCode::AE => "ae",
+            CondCode::B => "b",
+            CondCode::BE => "be",
         };
         write!(f, "{out}")
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Operand {
-    Imm(i32),
+    Imm(i64),
     Register(Register),
     Pseudo(Identifier),
-    Stack(u32),
+    /// Memory at the given offset from `%rbp`.
+    Stack(i32),
+    /// A variable with static storage duration, addressed relative to `%rip`.
+    Data(Identifier),
+}
+
+impl Operand {
+    fn is_memory(&self) -> bool {
+        matches!(self, Operand::Stack(_) | Operand::Data(_))
+    }
+
+    /// Whether this is an immediate that does not fit the 32 bits most
+    /// instructions accept.
+    fn is_large_immediate(&self) -> bool {
+        matches!(self, Operand::Imm(n) if i32::try_from(*n).is_err())
+    }
 }
 
 impl From<tacky::Value> for Operand {
     fn from(value: tacky::Value) -> Self {
         match value {
-            tacky::Value::Constant(n) => Operand::Imm(n),
+            // An `unsigned int` is stored as the `int` with the same bits, so
+            // every longword immediate fits into 32 bits.
+            tacky::Value::Constant(Const::UInt(u)) => Operand::Imm(u as i32 as i64),
+            tacky::Value::Constant(c) => Operand::Imm(c.bits() as i64),
             tacky::Value::Var(s) => Operand::Pseudo(s),
         }
     }
@@ -110,26 +291,47 @@ impl Display for Operand {
         let out = match self {
             Operand::Imm(n) => format!("${n}"),
             Operand::Register(r) => format!("{r}"),
-            Operand::Pseudo(i) => format!("{i}"),
-            Operand::Stack(i) => format!("-{i}(%rbp)"),
+            Operand::Pseudo(i) => i.to_string(),
+            Operand::Stack(i) => format!("{i}(%rbp)"),
+            Operand::Data(name) => format!("{name}(%rip)"),
         };
         write!(f, "{out}")
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Register {
     AX,
+    CX,
     DX,
+    DI,
+    SI,
+    R8,
+    R9,
     R10,
     R11,
 }
 
+/// Registers holding the first integer arguments of a call.
+const ARG_REGISTERS: [Register; 6] = [
+    Register::DI,
+    Register::SI,
+    Register::DX,
+    Register::CX,
+    Register::R8,
+    Register::R9,
+];
+
 impl Display for Register {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let out = match self {
             Register::AX => "%eax",
+            Register::CX => "%ecx",
             Register::DX => "%edx",
+            Register::DI => "%edi",
+            Register::SI => "%esi",
+            Register::R8 => "%r8d",
+            Register::R9 => "%r9d",
             Register::R10 => "%r10d",
             Register::R11 => "%r11d",
         };
@@ -138,82 +340,219 @@ impl Display for Register {
     }
 }
 
-pub fn assembly(program: tacky::Program) -> Program {
-    let p = Program {
-        function: function_definition(program.function),
-    };
+impl Register {
+    /// Name of the register's lowest byte, as written by `setCC`.
+    pub fn byte_name(&self) -> &'static str {
+        match self {
+            Register::AX => "%al",
+            Register::CX => "%cl",
+            Register::DX => "%dl",
+            Register::DI => "%dil",
+            Register::SI => "%sil",
+            Register::R8 => "%r8b",
+            Register::R9 => "%r9b",
+            Register::R10 => "%r10b",
+            Register::R11 => "%r11b",
+        }
+    }
 
-    let (p, offset) = replace_pseudo_registers(p);
-    fixing_up(p, offset)
+    /// Name of the whole 64-bit register.
+    pub fn quad_name(&self) -> &'static str {
+        match self {
+            Register::AX => "%rax",
+            Register::CX => "%rcx",
+            Register::DX => "%rdx",
+            Register::DI => "%rdi",
+            Register::SI => "%rsi",
+            Register::R8 => "%r8",
+            Register::R9 => "%r9",
+            Register::R10 => "%r10",
+            Register::R11 => "%r11",
+        }
+    }
 }
 
-fn function_definition(function: tacky::Function) -> Function {
+pub fn assembly(program: tacky::Program, symbols: &SymbolTable) -> Program {
+    let defined = program
+        .functions
+        .iter()
+        .map(|f| f.name.clone())
+        .collect::<HashSet<_>>();
+    let functions = program
+        .functions
+        .into_iter()
+        .map(|f| {
+            let f = function_definition(f, &defined, symbols);
+            let (f, offset) = replace_pseudo_registers(f, symbols);
+            fixing_up(f, offset)
+        })
+        .collect();
+    let static_variables = program
+        .static_variables
+        .into_iter()
+        .map(|variable| StaticVariable {
+            name: variable.name,
+            global: variable.global,
+            init: variable.init,
+            alignment: variable.init.ty().size() as u32,
+        })
+        .collect();
+    Program {
+        functions,
+        static_variables,
+    }
+}
+
+fn function_definition(
+    function: tacky::Function,
+    defined: &HashSet<Identifier>,
+    symbols: &SymbolTable,
+) -> Function {
+    // Copy the parameters out of the registers and the caller's frame, where
+    // they start after the saved `%rbp` and the return address.
+    let params = function
+        .params
+        .into_iter()
+        .enumerate()
+        .map(|(i, param)| Instruction::Mov {
+            ty: AssemblyType::from(&symbols[&param].ty),
+            src: match ARG_REGISTERS.get(i) {
+                Some(register) => Operand::Register(register.clone()),
+                None => Operand::Stack(16 + 8 * (i - ARG_REGISTERS.len()) as i32),
+            },
+            dst: Operand::Pseudo(param),
+        })
+        .collect::<Vec<_>>();
     Function {
         name: function.name,
-        instructions: instructions(function.body),
+        global: function.global,
+        instructions: params
+            .into_iter()
+            .chain(instructions(function.body, defined, symbols))
+            .collect(),
+    }
+}
+
+fn value_type(value: &tacky::Value, symbols: &SymbolTable) -> Type {
+    match value {
+        tacky::Value::Constant(c) => c.ty(),
+        tacky::Value::Var(name) => symbols[name].ty.clone(),
     }
 }
 
-fn instructions(is: Vec<tacky::Instruction>) -> Vec<Instruction> {
+fn assembly_type(value: &tacky::Value, symbols: &SymbolTable) -> AssemblyType {
+    AssemblyType::from(&value_type(value, symbols))
+}
+
+fn instructions(
+    is: Vec<tacky::Instruction>,
+    defined: &HashSet<Identifier>,
+    symbols: &SymbolTable,
+) -> Vec<Instruction> {
     is.into_iter()
         .flat_map(|i| match i {
             tacky::Instruction::Return(v) => vec![
                 Instruction::Mov {
+                    ty: assembly_type(&v, symbols),
                     src: v.into(),
                     dst: Operand::Register(Register::AX),
                 },
                 Instruction::Ret,
             ],
-            tacky::Instruction::Unary { operator, src, dst } => {
+            tacky::Instruction::Copy { src, dst } => vec![Instruction::Mov {
+                ty: assembly_type(&src, symbols),
+                src: src.into(),
+                dst: dst.into(),
+            }],
+            tacky::Instruction::SignExtend { src, dst } => vec![Instruction::Movsx {
+                src: src.into(),
+                dst: dst.into(),
+            }],
+            tacky::Instruction::ZeroExtend { src, dst } => vec![Instruction::MovZeroExtend {
+                src: src.into(),
+                dst: dst.into(),
+            }],
+            // Reading the low half of a quadword in little endian memory or a
+            // register drops the high bits.
+            tacky::Instruction::Truncate { src, dst } => vec![Instruction::Mov {
+                ty: AssemblyType::Longword,
+                src: src.into(),
+                dst: dst.into(),
+            }],
+            tacky::Instruction::Unary {
+                operator: tacky::UnaryOperator::Not,
+                src,
+                dst,
+            } => {
+                let ty = assembly_type(&dst, symbols);
                 let dst: Operand = dst.into();
                 vec![
+                    Instruction::Cmp {
+                        ty: assembly_type(&src, symbols),
+                        src: Operand::Imm(0),
+                        dst: src.into(),
+                    },
                     Instruction::Mov {
-                        src: src.into(),
+                        ty,
+                        src: Operand::Imm(0),
                         dst: dst.clone(),
                     },
-                    Instruction::Unary {
-                        operator: operator.into(),
-                        operand: dst,
-                    },
+                    Instruction::SetCC(CondCode::E, dst),
                 ]
             }
-            tacky::Instruction::Binary {
-                operator: tacky::BinaryOperator::Divide,
-                left,
-                right,
-                dst,
-            } => {
+            tacky::Instruction::Unary { operator, src, dst } => {
+                let ty = assembly_type(&src, symbols);
+                let dst: Operand = dst.into();
                 vec![
                     Instruction::Mov {
-                        src: left.into(),
-                        dst: Operand::Register(Register::AX),
+                        ty,
+                        src: src.into(),
+                        dst: dst.clone(),
                     },
-                    Instruction::Cdq,
-                    Instruction::Idiv(right.into()),
-                    Instruction::Mov {
-                        src: Operand::Register(Register::AX),
-                        dst: dst.into(),
+                    Instruction::Unary {
+                        operator: operator.into(),
+                        ty,
+                        operand: dst,
                     },
                 ]
             }
             tacky::Instruction::Binary {
-                operator: tacky::BinaryOperator::Remainder,
+                operator:
+                    operator @ (tacky::BinaryOperator::Divide | tacky::BinaryOperator::Remainder),
                 left,
                 right,
                 dst,
             } => {
-                vec![
-                    Instruction::Mov {
-                        src: left.into(),
-                        dst: Operand::Register(Register::AX),
-                    },
-                    Instruction::Cdq,
-                    Instruction::Idiv(right.into()),
-                    Instruction::Mov {
-                        src: Operand::Register(Register::DX),
-                        dst: dst.into(),
-                    },
-                ]
+                let left_type = value_type(&left, symbols);
+                let ty = AssemblyType::from(&left_type);
+                // The quotient ends up in `%eax` and the remainder in `%edx`.
+                let result = match operator {
+                    tacky::BinaryOperator::Divide => Register::AX,
+                    _ => Register::DX,
+                };
+                let mut instructions = vec![Instruction::Mov {
+                    ty,
+                    src: left.into(),
+                    dst: Operand::Register(Register::AX),
+                }];
+                match left_type.is_signed() {
+                    true => instructions
+                        .extend([Instruction::Cdq(ty), Instruction::Idiv(ty, right.into())]),
+                    false => instructions.extend([
+                        Instruction::Mov {
+                            ty,
+                            src: Operand::Imm(0),
+                            dst: Operand::Register(Register::DX),
+                        },
+                        Instruction::Div(ty, right.into()),
+                    ]),
+                }
+                instructions.push(Instruction::Mov {
+                    ty,
+                    src: Operand::Register(result),
+                    dst: dst.into(),
+                });
+                instructions
             }
             tacky::Instruction::Binary {
                 operator,
@@ -221,144 +560,1219 @@ fn instructions(is: Vec<tacky::Instruction>) -> Vec<Instruction> {
                 right,
                 dst,
             } => {
+                let left_type = value_type(&left, symbols);
+                let ty = AssemblyType::from(&left_type);
+                let signed = left_type.is_signed();
+                if let Some(cc) = CondCode::from_comparison(&operator, signed) {
+                    let dst: Operand = dst.into();
+                    return vec![
+                        Instruction::Cmp {
+                            ty,
+                            src: right.into(),
+                            dst: left.into(),
+                        },
+                        Instruction::Mov {
+                            ty: AssemblyType::Longword,
+                            src: Operand::Imm(0),
+                            dst: dst.clone(),
+                        },
+                        Instruction::SetCC(cc, dst),
+                    ];
+                }
+
+                let operator = match operator
+                    .try_into()
+                    .expect("invalid binary operators already processed")
+                {
+                    // Unsigned values shift in zeros instead of their sign bit.
+                    BinaryOperator::Sar if !signed => BinaryOperator::Shr,
+                    operator => operator,
+                };
                 let dst: Operand = dst.into();
                 vec![
                     Instruction::Mov {
+                        ty,
                         src: left.into(),
                         dst: dst.clone(),
                     },
                     Instruction::Binary {
-                        operator: operator
-                            .try_into()
-                            .expect("invalid binary operators already processed"),
+                        operator,
+                        ty,
                         src: right.into(),
-                        dst: dst,
+                        dst,
                     },
                 ]
             }
+            tacky::Instruction::Jump(target) => vec![Instruction::Jmp(target)],
+            tacky::Instruction::JumpIfZero { condition, target } => vec![
+                Instruction::Cmp {
+                    ty: assembly_type(&condition, symbols),
+                    src: Operand::Imm(0),
+                    dst: condition.into(),
+                },
+                Instruction::JmpCC(CondCode::E, target),
+            ],
+            tacky::Instruction::JumpIfNotZero { condition, target } => vec![
+                Instruction::Cmp {
+                    ty: assembly_type(&condition, symbols),
+                    src: Operand::Imm(0),
+                    dst: condition.into(),
+                },
+                Instruction::JmpCC(CondCode::NE, target),
+            ],
+            tacky::Instruction::Label(label) => vec![Instruction::Label(label)],
+            tacky::Instruction::Switch {
+                condition,
+                cases,
+                default,
+            } => switch(
+                assembly_type(&condition, symbols),
+                condition.into(),
+                cases,
+                default,
+            ),
+            tacky::Instruction::FunCall { name, args, dst } => {
+                let external = !defined.contains(&name);
+                call(name, external, args, dst, symbols)
+            }
         })
         .collect()
 }
 
-fn replace_pseudo_registers(mut program: Program) -> (Program, u32) {
+/// Lowers a call following the System V ABI: the first six arguments are
+/// passed in registers, the rest on the stack, which has to be 16-byte aligned
+/// at the call.
+fn call(
+    name: Identifier,
+    external: bool,
+    args: Vec<tacky::Value>,
+    dst: tacky::Value,
+    symbols: &SymbolTable,
+) -> Vec<Instruction> {
+    let mut args = args
+        .into_iter()
+        .map(|arg| (assembly_type(&arg, symbols), Operand::from(arg)))
+        .collect::<Vec<_>>();
+    let stack_args = args.split_off(args.len().min(ARG_REGISTERS.len()));
+    let padding = 8 * (stack_args.len() as u32 % 2);
+
+    let mut instructions = vec![];
+    if padding != 0 {
+        instructions.push(Instruction::AllocateStack(padding));
+    }
+    for ((ty, arg), register) in args.into_iter().zip(ARG_REGISTERS) {
+        instructions.push(Instruction::Mov {
+            ty,
+            src: arg,
+            dst: Operand::Register(register),
+        });
+    }
+    let stack_size = 8 * stack_args.len() as u32 + padding;
+    for (ty, arg) in stack_args.into_iter().rev() {
+        match (ty, arg) {
+            (_, arg @ (Operand::Imm(_) | Operand::Register(_))) | (AssemblyType::Quadword, arg) => {
+                instructions.push(Instruction::Push(arg))
+            }
+            // Pushing a 4-byte variable directly would read past its end.
+            (AssemblyType::Longword, arg) => instructions.extend([
+                Instruction::Mov {
+                    ty,
+                    src: arg,
+                    dst: Operand::Register(Register::AX),
+                },
+                Instruction::Push(Operand::Register(Register::AX)),
+            ]),
+        }
+    }
+    instructions.push(Instruction::Call { name, external });
+    if stack_size != 0 {
+        instructions.push(Instruction::DeallocateStack(stack_size));
+    }
+    instructions.push(Instruction::Mov {
+        ty: assembly_type(&dst, symbols),
+        src: Operand::Register(Register::AX),
+        dst: dst.into(),
+    });
+    instructions
+}
+
+/// Lowers a switch on a `condition` of size `ty` to a jump table if its cases
+/// are dense enough, and to a chain of comparisons otherwise.
+fn switch(
+    ty: AssemblyType,
+    condition: Operand,
+    cases: Vec<(Const, Identifier)>,
+    default: Identifier,
+) -> Vec<Instruction> {
+    let min = cases.iter().map(|(c, _)| *c).min_by_key(|c| c.value());
+    let max = cases.iter().map(|(c, _)| *c).max_by_key(|c| c.value());
+    let (Some(min), Some(max)) = (min, max) else {
+        return vec![Instruction::Jmp(default)];
+    };
+    let range = max.value() - min.value() + 1;
+    let dense = cases.len() >= JUMP_TABLE_MIN_CASES
+        && range <= JUMP_TABLE_MAX_SIZE
+        && range <= 3 * cases.len() as i128;
+
+    if !dense {
+        let mut instructions = cases
+            .into_iter()
+            .flat_map(|(c, label)| {
+                [
+                    Instruction::Cmp {
+                        ty,
+                        src: tacky::Value::Constant(c).into(),
+                        dst: condition.clone(),
+                    },
+                    Instruction::JmpCC(CondCode::E, label),
+                ]
+            })
+            .collect::<Vec<_>>();
+        instructions.push(Instruction::Jmp(default));
+        return instructions;
+    }
+
+    let mut targets = vec![default.clone(); range as usize];
+    for (c, label) in cases {
+        targets[(c.value() - min.value()) as usize] = label;
+    }
+    vec![
+        Instruction::Mov {
+            ty,
+            src: condition,
+            dst: Operand::Register(Register::AX),
+        },
+        Instruction::Binary {
+            operator: BinaryOperator::Sub,
+            ty,
+            src: tacky::Value::Constant(min).into(),
+            dst: Operand::Register(Register::AX),
+        },
+        // Values below `min` wrap around to large unsigned numbers.
+        Instruction::Cmp {
+            ty,
+            src: Operand::Imm((range - 1) as i64),
+            dst: Operand::Register(Register::AX),
+        },
+        Instruction::JmpCC(CondCode::A, default.clone()),
+        Instruction::JumpTable {
+            table: format!("table_{default}"),
+            targets,
+        },
+    ]
+}
+
+/// A switch needs at least this many cases to use a jump table.
+const JUMP_TABLE_MIN_CASES: usize = 4;
+/// Maximum number of entries in a jump table.
+const JUMP_TABLE_MAX_SIZE: i128 = 1024;
+
+/// Replaces every pseudo register with a stack slot, or with a reference to
+/// the data section if it names a variable with static storage duration.
+fn replace_pseudo_registers(mut function: Function, symbols: &SymbolTable) -> (Function, u32) {
     let mut map: HashMap<String, u32> = HashMap::new();
     let mut offset = 0;
-    program.function.instructions = program
-        .function
+    let mut replace = |op| {
+        let (op, of) = stack_offset(op, &mut map, offset, symbols);
+        offset = of;
+        op
+    };
+    function.instructions = function
         .instructions
         .into_iter()
         .map(|i| match i {
-            Instruction::Mov { src, dst } => {
-                let (src, of) = stack_offset(src, &mut map, offset);
-                let (dst, of) = stack_offset(dst, &mut map, of);
-                offset = of;
-                Instruction::Mov { src, dst }
-            }
-            Instruction::Unary { operator, operand } => {
-                let (operand, of) = stack_offset(operand, &mut map, offset);
-                offset = of;
-                Instruction::Unary { operator, operand }
-            }
-            Instruction::Binary { operator, src, dst } => {
-                let (src, of) = stack_offset(src, &mut map, offset);
-                let (dst, of) = stack_offset(dst, &mut map, of);
-                offset = of;
-                Instruction::Binary { operator, src, dst }
-            }
-            Instruction::Idiv(op) => {
-                let (op, of) = stack_offset(op, &mut map, offset);
-                offset = of;
-                Instruction::Idiv(op)
-            }
-            i @ (Instruction::AllocateStack(_) | Instruction::Ret | Instruction::Cdq) => i,
+            Instruction::Mov { ty, src, dst } => Instruction::Mov {
+                ty,
+                src: replace(src),
+                dst: replace(dst),
+            },
+            Instruction::Movsx { src, dst } => Instruction::Movsx {
+                src: replace(src),
+                dst: replace(dst),
+            },
+            Instruction::MovZeroExtend { src, dst } => Instruction::MovZeroExtend {
+                src: replace(src),
+                dst: replace(dst),
+            },
+            Instruction::Unary {
+                operator,
+                ty,
+                operand,
+            } => Instruction::Unary {
+                operator,
+                ty,
+                operand: replace(operand),
+            },
+            Instruction::Binary {
+                operator,
+                ty,
+                src,
+                dst,
+            } => Instruction::Binary {
+                operator,
+                ty,
+                src: replace(src),
+                dst: replace(dst),
+            },
+            Instruction::Cmp { ty, src, dst } => Instruction::Cmp {
+                ty,
+                src: replace(src),
+                dst: replace(dst),
+            },
+            Instruction::Idiv(ty, op) => Instruction::Idiv(ty, replace(op)),
+            Instruction::Div(ty, op) => Instruction::Div(ty, replace(op)),
+            Instruction::SetCC(cc, op) => Instruction::SetCC(cc, replace(op)),
+            Instruction::Push(op) => Instruction::Push(replace(op)),
+            i @ (Instruction::AllocateStack(_)
+            | Instruction::DeallocateStack(_)
+            | Instruction::Call { .. }
+            | Instruction::Ret
+            | Instruction::Cdq(_)
+            | Instruction::Jmp(_)
+            | Instruction::JmpCC(..)
+            | Instruction::Label(_)
+            | Instruction::JumpTable { .. }) => i,
         })
         .collect::<Vec<_>>();
-    (program, offset)
+    (function, offset)
 }
 
-fn stack_offset(op: Operand, map: &mut HashMap<String, u32>, offset: u32) -> (Operand, u32) {
+/// Returns the operand replacing `op` and the new size of the stack frame.
+/// Every slot is aligned to the size of its variable.
+fn stack_offset(
+    op: Operand,
+    map: &mut HashMap<String, u32>,
+    offset: u32,
+    symbols: &SymbolTable,
+) -> (Operand, u32) {
     if let Operand::Pseudo(i) = op {
-        let e = map.entry(i);
-        let offset = *e.or_insert(offset + 4);
-        return (Operand::Stack(offset), offset);
+        let symbol = &symbols[&i];
+        if matches!(symbol.attributes, IdentifierAttributes::Static { .. }) {
+            return (Operand::Data(i), offset);
+        }
+        let size = symbol.ty.size() as u32;
+        let slot = *map
+            .entry(i)
+            .or_insert((offset + size).next_multiple_of(size));
+        return (Operand::Stack(-(slot as i32)), offset.max(slot));
     }
     (op, offset)
 }
 
-fn fixing_up(mut program: Program, stack_size: u32) -> Program {
-    program
-        .function
-        .instructions
-        .insert(0, Instruction::AllocateStack(stack_size));
-    program.function.instructions = program
-        .function
+fn fixing_up(mut function: Function, stack_size: u32) -> Function {
+    // Keep `%rsp` 16-byte aligned for calls.
+    function.instructions.insert(
+        0,
+        Instruction::AllocateStack(stack_size.next_multiple_of(16)),
+    );
+    function.instructions = function
         .instructions
         .into_iter()
         .flat_map(|i| match i {
-            Instruction::Mov {
-                src: src @ Operand::Stack(_),
-                dst: dst @ Operand::Stack(_),
-            } => {
-                vec![
-                    Instruction::Mov {
-                        src,
-                        dst: Operand::Register(Register::R10),
-                    },
-                    Instruction::Mov {
-                        src: Operand::Register(Register::R10),
-                        dst,
-                    },
-                ]
+            Instruction::Mov { ty, src, dst } => fix_mov(ty, src, dst),
+            Instruction::Movsx { src, dst } => {
+                let mut instructions = vec![];
+                let src = match src {
+                    src @ Operand::Imm(_) => {
+                        instructions.push(Instruction::Mov {
+                            ty: AssemblyType::Longword,
+                            src,
+                            dst: Operand::Register(Register::R10),
+                        });
+                        Operand::Register(Register::R10)
+                    }
+                    src => src,
+                };
+                match dst.is_memory() {
+                    true => instructions.extend([
+                        Instruction::Movsx {
+                            src,
+                            dst: Operand::Register(Register::R11),
+                        },
+                        Instruction::Mov {
+                            ty: AssemblyType::Quadword,
+                            src: Operand::Register(Register::R11),
+                            dst,
+                        },
+                    ]),
+                    false => instructions.push(Instruction::Movsx { src, dst }),
+                }
+                instructions
             }
+            Instruction::MovZeroExtend { src, dst } if dst.is_memory() => vec![
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src,
+                    dst: Operand::Register(Register::R11),
+                },
+                Instruction::Mov {
+                    ty: AssemblyType::Quadword,
+                    src: Operand::Register(Register::R11),
+                    dst,
+                },
+            ],
+            Instruction::MovZeroExtend { src, dst } => vec![Instruction::Mov {
+                ty: AssemblyType::Longword,
+                src,
+                dst,
+            }],
             Instruction::Binary {
-                operator: operator @ (BinaryOperator::Add | BinaryOperator::Sub),
-                src: src @ Operand::Stack(_),
-                dst: dst @ Operand::Stack(_),
-            } => {
-                vec![
-                    Instruction::Mov {
-                        src,
-                        dst: Operand::Register(Register::R10),
-                    },
-                    Instruction::Binary {
-                        operator,
-                        src: Operand::Register(Register::R10),
-                        dst,
-                    },
-                ]
-            }
+                operator,
+                ty,
+                src,
+                dst,
+            } => fix_binary(operator, ty, src, dst),
+            Instruction::Cmp { ty, src, dst } => fix_cmp(ty, src, dst),
+            Instruction::Idiv(ty, op @ Operand::Imm(_)) => vec![
+                Instruction::Mov {
+                    ty,
+                    src: op,
+                    dst: Operand::Register(Register::R10),
+                },
+                Instruction::Idiv(ty, Operand::Register(Register::R10)),
+            ],
+            Instruction::Div(ty, op @ Operand::Imm(_)) => vec![
+                Instruction::Mov {
+                    ty,
+                    src: op,
+                    dst: Operand::Register(Register::R10),
+                },
+                Instruction::Div(ty, Operand::Register(Register::R10)),
+            ],
+            Instruction::Push(op) if op.is_large_immediate() => vec![
+                Instruction::Mov {
+                    ty: AssemblyType::Quadword,
+                    src: op,
+                    dst: Operand::Register(Register::R10),
+                },
+                Instruction::Push(Operand::Register(Register::R10)),
+            ],
+            i => vec![i],
+        })
+        .collect();
+    function
+}
+
+/// Rewrites a move whose operands x86 does not accept: at most one operand
+/// may be in memory and only a register can receive a 64-bit immediate.
+fn fix_mov(ty: AssemblyType, src: Operand, dst: Operand) -> Vec<Instruction> {
+    match (ty, src) {
+        // Only the low bits of a truncated constant are kept.
+        (AssemblyType::Longword, Operand::Imm(n)) => vec![Instruction::Mov {
+            ty,
+            src: Operand::Imm(n as i32 as i64),
+            dst,
+        }],
+        (ty, src) if (src.is_memory() || src.is_large_immediate()) && dst.is_memory() => vec![
+            Instruction::Mov {
+                ty,
+                src,
+                dst: Operand::Register(Register::R10),
+            },
+            Instruction::Mov {
+                ty,
+                src: Operand::Register(Register::R10),
+                dst,
+            },
+        ],
+        (ty, src) => vec![Instruction::Mov { ty, src, dst }],
+    }
+}
+
+/// Rewrites a binary instruction whose operands x86 does not accept: at most
+/// one operand may be in memory, `imul` can't write to memory, the count of a
+/// shift must be an immediate or `%cl` and immediates have at most 32 bits.
+fn fix_binary(
+    operator: BinaryOperator,
+    ty: AssemblyType,
+    src: Operand,
+    dst: Operand,
+) -> Vec<Instruction> {
+    match (operator, src, dst) {
+        // Shift counts are encoded in a byte, of which the CPU only uses the
+        // low 5 or 6 bits anyway.
+        (operator, Operand::Imm(count), dst) if operator.is_shift() => {
+            vec![Instruction::Binary {
+                operator,
+                ty,
+                src: Operand::Imm(count & 0xff),
+                dst,
+            }]
+        }
+        (operator, src, dst) if operator.is_shift() && src != Operand::Register(Register::CX) => {
+            vec![
+                Instruction::Mov {
+                    ty,
+                    src,
+                    dst: Operand::Register(Register::CX),
+                },
+                Instruction::Binary {
+                    operator,
+                    ty,
+                    src: Operand::Register(Register::CX),
+                    dst,
+                },
+            ]
+        }
+        (operator, src, dst) if src.is_large_immediate() => {
+            let mut instructions = vec![Instruction::Mov {
+                ty,
+                src,
+                dst: Operand::Register(Register::R10),
+            }];
+            instructions.extend(fix_binary(
+                operator,
+                ty,
+                Operand::Register(Register::R10),
+                dst,
+            ));
+            instructions
+        }
+        (operator @ BinaryOperator::Mult, src, dst) if dst.is_memory() => vec![
+            Instruction::Mov {
+                ty,
+                src: dst.clone(),
+                dst: Operand::Register(Register::R11),
+            },
             Instruction::Binary {
-                operator: operator @ BinaryOperator::Mult,
-                src: src @ Operand::Stack(_),
-                dst: dst @ Operand::Stack(_),
-            } => {
-                vec![
-                    Instruction::Mov {
-                        src: dst.clone(),
-                        dst: Operand::Register(Register::R11),
-                    },
-                    Instruction::Binary {
-                        operator,
-                        src,
-                        dst: Operand::Register(Register::R11),
-                    },
-                    Instruction::Mov {
-                        src: Operand::Register(Register::R11),
-                        dst,
-                    },
-                ]
+                operator,
+                ty,
+                src,
+                dst: Operand::Register(Register::R11),
+            },
+            Instruction::Mov {
+                ty,
+                src: Operand::Register(Register::R11),
+                dst,
+            },
+        ],
+        (operator, src, dst) if src.is_memory() && dst.is_memory() => vec![
+            Instruction::Mov {
+                ty,
+                src,
+                dst: Operand::Register(Register::R10),
+            },
+            Instruction::Binary {
+                operator,
+                ty,
+                src: Operand::Register(Register::R10),
+                dst,
+            },
+        ],
+        (operator, src, dst) => vec![Instruction::Binary {
+            operator,
+            ty,
+            src,
+            dst,
+        }],
+    }
+}
+
+/// Rewrites a comparison whose operands x86 does not accept: at most one
+/// operand may be in memory, the second can't be an immediate and the first
+/// has at most 32 bits.
+fn fix_cmp(ty: AssemblyType, src: Operand, dst: Operand) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let src = match src {
+        src if src.is_large_immediate() || (src.is_memory() && dst.is_memory()) => {
+            instructions.push(Instruction::Mov {
+                ty,
+                src,
+                dst: Operand::Register(Register::R10),
+            });
+            Operand::Register(Register::R10)
+        }
+        src => src,
+    };
+    let dst = match dst {
+        dst @ Operand::Imm(_) => {
+            instructions.push(Instruction::Mov {
+                ty,
+                src: dst,
+                dst: Operand::Register(Register::R11),
+            });
+            Operand::Register(Register::R11)
+        }
+        dst => dst,
+    };
+    instructions.push(Instruction::Cmp { ty, src, dst });
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::{InitialValue, Symbol};
+
+    /// A symbol table declaring `names` as local variables of type `ty`.
+    fn locals(names: &[&str], ty: Type) -> SymbolTable {
+        names
+            .iter()
+            .map(|name| {
+                let symbol = Symbol {
+                    ty: ty.clone(),
+                    attributes: IdentifierAttributes::Local,
+                };
+                (name.to_string(), symbol)
+            })
+            .collect()
+    }
+
+    /// Lowers the body of a function `main` with `symbols`.
+    fn lower(body: Vec<tacky::Instruction>, symbols: &SymbolTable) -> Vec<Instruction> {
+        let program = assembly(
+            tacky::Program {
+                functions: vec![tacky::Function {
+                    name: "main".into(),
+                    global: true,
+                    params: vec![],
+                    body,
+                }],
+                static_variables: vec![],
+            },
+            symbols,
+        );
+        program.functions.into_iter().next().unwrap().instructions
+    }
+
+    #[test]
+    fn reused_pseudo_keeps_its_stack_slot() {
+        let program = assembly(
+            tacky::Program {
+                functions: vec![tacky::Function {
+                    name: "main".into(),
+                    global: true,
+                    params: vec![],
+                    body: vec![
+                        tacky::Instruction::Copy {
+                            src: tacky::Value::Constant(Const::Int(1)),
+                            dst: tacky::Value::Var("a".into()),
+                        },
+                        tacky::Instruction::Copy {
+                            src: tacky::Value::Constant(Const::Int(2)),
+                            dst: tacky::Value::Var("b".into()),
+                        },
+                        tacky::Instruction::Copy {
+                            src: tacky::Value::Var("a".into()),
+                            dst: tacky::Value::Var("c".into()),
+                        },
+                    ],
+                }],
+                static_variables: vec![],
+            },
+            &locals(&["a", "b", "c"], Type::Int),
+        );
+
+        let instructions = &program.functions[0].instructions;
+        let slots = instructions
+            .iter()
+            .filter_map(|i| match i {
+                Instruction::Mov {
+                    dst: Operand::Stack(slot),
+                    ..
+                } => Some(*slot),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(instructions[0], Instruction::AllocateStack(16)));
+        assert_eq!(slots, vec![-4, -8, -12]);
+    }
+
+    #[test]
+    fn shift_count_in_cl() {
+        let fixed = fix_binary(
+            BinaryOperator::Sar,
+            AssemblyType::Longword,
+            Operand::Stack(-4),
+            Operand::Stack(-8),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Stack(-4),
+                    dst: Operand::Register(Register::CX)
+                },
+                Instruction::Binary {
+                    ty: AssemblyType::Longword,
+                    operator: BinaryOperator::Sar,
+                    src: Operand::Register(Register::CX),
+                    dst: Operand::Stack(-8)
+                }
+            ]
+        ));
+
+        let fixed = fix_binary(
+            BinaryOperator::Sal,
+            AssemblyType::Longword,
+            Operand::Imm(3),
+            Operand::Stack(-8),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [Instruction::Binary {
+                src: Operand::Imm(3),
+                ..
+            }]
+        ));
+
+        // Any other count has to be in `%cl`.
+        let fixed = fix_binary(
+            BinaryOperator::Shr,
+            AssemblyType::Quadword,
+            Operand::Register(Register::R10),
+            Operand::Stack(-8),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [
+                Instruction::Mov {
+                    src: Operand::Register(Register::R10),
+                    dst: Operand::Register(Register::CX),
+                    ..
+                },
+                Instruction::Binary {
+                    src: Operand::Register(Register::CX),
+                    ..
+                }
+            ]
+        ));
+
+        // Counts that don't fit in a byte keep their low bits.
+        let fixed = fix_binary(
+            BinaryOperator::Sal,
+            AssemblyType::Longword,
+            Operand::Imm(300),
+            Operand::Stack(-8),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [Instruction::Binary {
+                src: Operand::Imm(44),
+                ..
+            }]
+        ));
+        let fixed = fix_binary(
+            BinaryOperator::Sal,
+            AssemblyType::Quadword,
+            Operand::Imm(0x100_0000_0003),
+            Operand::Stack(-8),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [Instruction::Binary {
+                src: Operand::Imm(3),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn imul_never_writes_to_memory() {
+        let fixed = fix_binary(
+            BinaryOperator::Mult,
+            AssemblyType::Longword,
+            Operand::Imm(3),
+            Operand::Stack(-8),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Stack(-8),
+                    dst: Operand::Register(Register::R11)
+                },
+                Instruction::Binary {
+                    ty: AssemblyType::Longword,
+                    operator: BinaryOperator::Mult,
+                    src: Operand::Imm(3),
+                    dst: Operand::Register(Register::R11)
+                },
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Register(Register::R11),
+                    dst: Operand::Stack(-8)
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn at_most_one_memory_operand() {
+        let fixed = fix_binary(
+            BinaryOperator::Xor,
+            AssemblyType::Longword,
+            Operand::Stack(-4),
+            Operand::Stack(-8),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    dst: Operand::Register(Register::R10),
+                    ..
+                },
+                Instruction::Binary {
+                    ty: AssemblyType::Longword,
+                    operator: BinaryOperator::Xor,
+                    src: Operand::Register(Register::R10),
+                    dst: Operand::Stack(-8)
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn dense_switch_uses_jump_table() {
+        let cases = [1, 2, 4, 5, 3]
+            .into_iter()
+            .map(|n| (Const::Int(n), format!("case.{n}")))
+            .collect();
+        let instructions = switch(
+            AssemblyType::Longword,
+            Operand::Stack(-4),
+            cases,
+            "default.0".into(),
+        );
+        let [.., Instruction::JmpCC(CondCode::A, default), Instruction::JumpTable { targets, .. }] =
+            &instructions[..]
+        else {
+            panic!("expected a jump table");
+        };
+        assert_eq!(default, "default.0");
+        assert_eq!(
+            targets,
+            &vec!["case.1", "case.2", "case.3", "case.4", "case.5"]
+        );
+    }
+
+    #[test]
+    fn jump_table_fills_gaps_with_default() {
+        let cases = [-3, 0, 2, 5]
+            .into_iter()
+            .map(|n| (Const::Int(n), format!("case.{n}")))
+            .collect();
+        let instructions = switch(
+            AssemblyType::Longword,
+            Operand::Stack(-4),
+            cases,
+            "break.0".into(),
+        );
+        assert!(matches!(
+            instructions[1],
+            Instruction::Binary {
+                operator: BinaryOperator::Sub,
+                src: Operand::Imm(-3),
+                ..
             }
-            Instruction::Idiv(op @ Operand::Imm(_)) => {
-                vec![
-                    Instruction::Mov {
-                        src: op,
-                        dst: Operand::Register(Register::R10),
+        ));
+        let Some(Instruction::JumpTable { targets, .. }) = instructions.last() else {
+            panic!("expected a jump table");
+        };
+        assert_eq!(targets.len(), 9);
+        assert_eq!(targets[0], "case.-3");
+        assert_eq!(targets[1], "break.0");
+        assert_eq!(targets[8], "case.5");
+    }
+
+    #[test]
+    fn sparse_switch_uses_comparisons() {
+        let cases = vec![
+            (Const::Int(1), "a".into()),
+            (Const::Int(1000), "b".into()),
+            (Const::Int(-7), "c".into()),
+            (Const::Int(9), "d".into()),
+        ];
+        let instructions = switch(
+            AssemblyType::Longword,
+            Operand::Stack(-4),
+            cases,
+            "default.0".into(),
+        );
+        assert_eq!(instructions.len(), 9);
+        assert!(matches!(
+            &instructions[..2],
+            [
+                Instruction::Cmp {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Imm(1),
+                    dst: Operand::Stack(-4)
+                },
+                Instruction::JmpCC(CondCode::E, _)
+            ]
+        ));
+        assert!(matches!(&instructions[8], Instruction::Jmp(l) if l == "default.0"));
+        assert!(!instructions
+            .iter()
+            .any(|i| matches!(i, Instruction::JumpTable { .. })));
+    }
+
+    #[test]
+    fn switch_without_cases() {
+        let instructions = switch(
+            AssemblyType::Longword,
+            Operand::Stack(-4),
+            vec![],
+            "break.0".into(),
+        );
+        assert!(matches!(&instructions[..], [Instruction::Jmp(l)] if l == "break.0"));
+    }
+
+    #[test]
+    fn call_with_stack_arguments() {
+        let args = (1..=9)
+            .map(|n| tacky::Value::Constant(Const::Int(n)))
+            .collect::<Vec<_>>();
+        let symbols = locals(&["r"], Type::Int);
+        let mut instructions = call(
+            "f".into(),
+            true,
+            args,
+            tacky::Value::Var("r".into()),
+            &symbols,
+        )
+        .into_iter();
+
+        // Three arguments go on the stack, so 8 bytes of padding keep `%rsp`
+        // aligned.
+        assert_eq!(instructions.next(), Some(Instruction::AllocateStack(8)));
+        for (i, register) in ARG_REGISTERS.into_iter().enumerate() {
+            assert_eq!(
+                instructions.next(),
+                Some(Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Imm(i as i64 + 1),
+                    dst: Operand::Register(register),
+                })
+            );
+        }
+        assert_eq!(
+            instructions.collect::<Vec<_>>(),
+            [
+                Instruction::Push(Operand::Imm(9)),
+                Instruction::Push(Operand::Imm(8)),
+                Instruction::Push(Operand::Imm(7)),
+                Instruction::Call {
+                    name: "f".into(),
+                    external: true
+                },
+                Instruction::DeallocateStack(32),
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Register(Register::AX),
+                    dst: Operand::Pseudo("r".into())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parameters_are_copied_from_registers_and_stack() {
+        let params = (0..8).map(|i| format!("p{i}")).collect::<Vec<_>>();
+        let names = params.iter().map(String::as_str).collect::<Vec<_>>();
+        let function = function_definition(
+            tacky::Function {
+                name: "f".into(),
+                global: true,
+                params: params.clone(),
+                body: vec![],
+            },
+            &HashSet::new(),
+            &locals(&names, Type::Long),
+        );
+        let sources = function
+            .instructions
+            .into_iter()
+            .map(|i| match i {
+                Instruction::Mov { src, .. } => src,
+                i => panic!("unexpected instruction {i:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sources[5], Operand::Register(Register::R9));
+        assert_eq!(sources[6..], [Operand::Stack(16), Operand::Stack(24)]);
+    }
+
+    #[test]
+    fn calls_to_defined_functions_are_internal() {
+        let mut symbols = locals(&["a", "b"], Type::Int);
+        for (name, params) in [("main", vec![]), ("putchar", vec![Type::Int])] {
+            symbols.insert(
+                name.into(),
+                Symbol {
+                    ty: Type::Function {
+                        params,
+                        ret: Box::new(Type::Int),
                     },
-                    Instruction::Idiv(Operand::Register(Register::R10)),
-                ]
+                    attributes: IdentifierAttributes::Function {
+                        defined: name == "main",
+                        global: true,
+                    },
+                },
+            );
+        }
+        let program = assembly(
+            tacky::Program {
+                functions: vec![tacky::Function {
+                    name: "main".into(),
+                    global: true,
+                    params: vec![],
+                    body: vec![
+                        tacky::Instruction::FunCall {
+                            name: "main".into(),
+                            args: vec![],
+                            dst: tacky::Value::Var("a".into()),
+                        },
+                        tacky::Instruction::FunCall {
+                            name: "putchar".into(),
+                            args: vec![tacky::Value::Var("a".into())],
+                            dst: tacky::Value::Var("b".into()),
+                        },
+                    ],
+                }],
+                static_variables: vec![],
+            },
+            &symbols,
+        );
+        let calls = program.functions[0]
+            .instructions
+            .iter()
+            .filter_map(|i| match i {
+                Instruction::Call { name, external } => Some((name.as_str(), *external)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(calls, [("main", false), ("putchar", true)]);
+    }
+
+    #[test]
+    fn static_variables_are_addressed_relative_to_rip() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(
+            "a".into(),
+            Symbol {
+                ty: Type::Int,
+                attributes: IdentifierAttributes::Static {
+                    init: InitialValue::Initial(Const::Int(1)),
+                    global: true,
+                },
+            },
+        );
+        symbols.extend(locals(&["b"], Type::Int));
+        let program = assembly(
+            tacky::Program {
+                functions: vec![tacky::Function {
+                    name: "main".into(),
+                    global: true,
+                    params: vec![],
+                    body: vec![tacky::Instruction::Copy {
+                        src: tacky::Value::Var("a".into()),
+                        dst: tacky::Value::Var("b".into()),
+                    }],
+                }],
+                static_variables: vec![tacky::StaticVariable {
+                    name: "a".into(),
+                    global: true,
+                    init: Const::Int(1),
+                }],
+            },
+            &symbols,
+        );
+
+        // Both operands are in memory, so the copy goes through a register.
+        assert_eq!(
+            program.functions[0].instructions,
+            [
+                Instruction::AllocateStack(16),
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Data("a".into()),
+                    dst: Operand::Register(Register::R10)
+                },
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Register(Register::R10),
+                    dst: Operand::Stack(-4)
+                },
+            ]
+        );
+        assert_eq!(
+            program.static_variables,
+            [StaticVariable {
+                name: "a".into(),
+                global: true,
+                init: Const::Int(1),
+                alignment: 4
+            }]
+        );
+        assert_eq!(Operand::Data("a".into()).to_string(), "a(%rip)");
+    }
+
+    #[test]
+    fn stack_slots_are_aligned_to_their_size() {
+        let mut symbols = locals(&["i"], Type::Int);
+        symbols.extend(locals(&["l"], Type::Long));
+        let instructions = lower(
+            vec![
+                tacky::Instruction::Copy {
+                    src: tacky::Value::Constant(Const::Int(1)),
+                    dst: tacky::Value::Var("i".into()),
+                },
+                tacky::Instruction::Copy {
+                    src: tacky::Value::Constant(Const::Long(2)),
+                    dst: tacky::Value::Var("l".into()),
+                },
+            ],
+            &symbols,
+        );
+        assert_eq!(
+            instructions,
+            [
+                Instruction::AllocateStack(16),
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Imm(1),
+                    dst: Operand::Stack(-4)
+                },
+                Instruction::Mov {
+                    ty: AssemblyType::Quadword,
+                    src: Operand::Imm(2),
+                    dst: Operand::Stack(-16)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn large_immediates_go_through_a_register() {
+        let symbols = locals(&["l"], Type::Long);
+        let big = tacky::Value::Constant(Const::Long(1 << 40));
+        let instructions = lower(
+            vec![tacky::Instruction::Binary {
+                operator: tacky::BinaryOperator::Add,
+                left: tacky::Value::Var("l".into()),
+                right: big,
+                dst: tacky::Value::Var("l".into()),
+            }],
+            &symbols,
+        );
+        assert_eq!(
+            instructions[instructions.len() - 2..],
+            [
+                Instruction::Mov {
+                    ty: AssemblyType::Quadword,
+                    src: Operand::Imm(1 << 40),
+                    dst: Operand::Register(Register::R10)
+                },
+                Instruction::Binary {
+                    operator: BinaryOperator::Add,
+                    ty: AssemblyType::Quadword,
+                    src: Operand::Register(Register::R10),
+                    dst: Operand::Stack(-8)
+                },
+            ]
+        );
+
+        // Truncating a constant keeps its low bits.
+        let fixed = fix_mov(
+            AssemblyType::Longword,
+            Operand::Imm(0x1_0000_0005),
+            Operand::Stack(-4),
+        );
+        assert!(matches!(
+            &fixed[..],
+            [Instruction::Mov {
+                src: Operand::Imm(5),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn unsigned_operations() {
+        let symbols = locals(&["a", "b", "c"], Type::UInt);
+        let binary = |operator| tacky::Instruction::Binary {
+            operator,
+            left: tacky::Value::Var("a".into()),
+            right: tacky::Value::Var("b".into()),
+            dst: tacky::Value::Var("c".into()),
+        };
+        let instructions = lower(
+            vec![
+                binary(tacky::BinaryOperator::Divide),
+                binary(tacky::BinaryOperator::ShiftRight),
+            ],
+            &symbols,
+        );
+        assert!(instructions.contains(&Instruction::Div(
+            AssemblyType::Longword,
+            Operand::Stack(-8)
+        )));
+        assert!(!instructions.contains(&Instruction::Cdq(AssemblyType::Longword)));
+        assert!(instructions.iter().any(|i| matches!(
+            i,
+            Instruction::Binary {
+                operator: BinaryOperator::Shr,
+                ..
             }
-            i @ _ => vec![i],
-        })
-        .collect();
-    program
+        )));
+
+        let mut symbols = symbols;
+        symbols.extend(locals(&["r"], Type::Int));
+        let instructions = lower(
+            vec![tacky::Instruction::Binary {
+                operator: tacky::BinaryOperator::LessThan,
+                left: tacky::Value::Var("a".into()),
+                right: tacky::Value::Var("b".into()),
+                dst: tacky::Value::Var("r".into()),
+            }],
+            &symbols,
+        );
+        assert!(instructions.contains(&Instruction::SetCC(CondCode::B, Operand::Stack(-12))));
+    }
+
+    #[test]
+    fn extensions_into_memory_use_a_register() {
+        let mut symbols = locals(&["i"], Type::Int);
+        symbols.extend(locals(&["l"], Type::Long));
+        let instructions = lower(
+            vec![
+                tacky::Instruction::SignExtend {
+                    src: tacky::Value::Var("i".into()),
+                    dst: tacky::Value::Var("l".into()),
+                },
+                tacky::Instruction::ZeroExtend {
+                    src: tacky::Value::Var("i".into()),
+                    dst: tacky::Value::Var("l".into()),
+                },
+            ],
+            &symbols,
+        );
+        assert_eq!(
+            instructions[1..],
+            [
+                Instruction::Movsx {
+                    src: Operand::Stack(-4),
+                    dst: Operand::Register(Register::R11)
+                },
+                Instruction::Mov {
+                    ty: AssemblyType::Quadword,
+                    src: Operand::Register(Register::R11),
+                    dst: Operand::Stack(-16)
+                },
+                Instruction::Mov {
+                    ty: AssemblyType::Longword,
+                    src: Operand::Stack(-4),
+                    dst: Operand::Register(Register::R11)
+                },
+                Instruction::Mov {
+                    ty: AssemblyType::Quadword,
+                    src: Operand::Register(Register::R11),
+                    dst: Operand::Stack(-16)
+                },
+            ]
+        );
+    }
 }
@@ -1,31 +1,242 @@
+use crate::lexer::Span;
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Program {
-    pub function_definition: FunctionDefinition,
+    pub declarations: Vec<Declaration>,
 }
 
 pub type Identifier = String;
+/// A function declaration, which is a definition if it has a body.
 #[derive(Debug, PartialEq, Eq)]
-pub struct FunctionDefinition {
+pub struct FunctionDeclaration {
     pub name: Identifier,
-    pub body: Statement,
+    pub params: Vec<Parameter>,
+    pub return_type: Type,
+    pub body: Option<Block>,
+    pub storage_class: Option<StorageClass>,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Parameter {
+    /// Only prototypes may leave their parameters unnamed.
+    pub name: Option<Identifier>,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block(pub Vec<BlockItem>);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockItem {
+    Statement(Statement),
+    Declaration(Declaration),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Declaration {
+    Variable(VariableDeclaration),
+    Function(FunctionDeclaration),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VariableDeclaration {
+    pub name: Identifier,
+    pub ty: Type,
+    pub init: Option<Expression>,
+    pub storage_class: Option<StorageClass>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Long,
+    UInt,
+    ULong,
+    Function { params: Vec<Type>, ret: Box<Type> },
+}
+
+impl Type {
+    /// The size of a value of this type in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong => 8,
+            Type::Function { .. } => unreachable!("functions have no size"),
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::Int | Type::Long)
+    }
+}
+
+/// An integer constant of one of the arithmetic types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Const {
+    Int(i32),
+    Long(i64),
+    UInt(u32),
+    ULong(u64),
+}
+
+impl Const {
+    pub fn ty(self) -> Type {
+        match self {
+            Const::Int(_) => Type::Int,
+            Const::Long(_) => Type::Long,
+            Const::UInt(_) => Type::UInt,
+            Const::ULong(_) => Type::ULong,
+        }
+    }
+
+    /// The two's complement bits of the value, sign or zero extended to 64
+    /// bits depending on its type.
+    pub fn bits(self) -> u64 {
+        match self {
+            Const::Int(i) => i as i64 as u64,
+            Const::Long(i) => i as u64,
+            Const::UInt(u) => u as u64,
+            Const::ULong(u) => u,
+        }
+    }
+
+    /// The constant of type `ty` whose bits are the low bits of `bits`.
+    pub fn from_bits(bits: u64, ty: &Type) -> Const {
+        match ty {
+            Type::Int => Const::Int(bits as i32),
+            Type::Long => Const::Long(bits as i64),
+            Type::UInt => Const::UInt(bits as u32),
+            Type::ULong => Const::ULong(bits),
+            Type::Function { .. } => unreachable!("no constant has a function type"),
+        }
+    }
+
+    /// Converts the constant to `ty` as C does, by truncating or extending
+    /// its bits.
+    pub fn convert(self, ty: &Type) -> Const {
+        Const::from_bits(self.bits(), ty)
+    }
+
+    /// The mathematical value of the constant.
+    pub fn value(self) -> i128 {
+        match self {
+            Const::Int(i) => i.into(),
+            Const::Long(i) => i.into(),
+            Const::UInt(u) => u.into(),
+            Const::ULong(u) => u.into(),
+        }
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.bits() == 0
+    }
+}
+
+impl std::fmt::Display for Const {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Const::Int(i) => write!(f, "{i}"),
+            Const::Long(i) => write!(f, "{i}"),
+            Const::UInt(u) => write!(f, "{u}"),
+            Const::ULong(u) => write!(f, "{u}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageClass {
+    Static,
+    Extern,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Statement {
     Return(Expression),
+    Expression(Expression),
+    If(Expression, Box<Statement>, Option<Box<Statement>>),
+    Compound(Block),
+    /// `break` and `continue` carry the label of the statement they leave once
+    /// the semantic analysis has found it.
+    Break(Option<Identifier>, Span),
+    Continue(Option<Identifier>, Span),
+    While {
+        condition: Expression,
+        body: Box<Statement>,
+        label: Option<Identifier>,
+    },
+    DoWhile {
+        body: Box<Statement>,
+        condition: Expression,
+        label: Option<Identifier>,
+    },
+    For {
+        init: ForInit,
+        condition: Option<Expression>,
+        post: Option<Expression>,
+        body: Box<Statement>,
+        label: Option<Identifier>,
+    },
+    /// `cases` is filled in by the semantic analysis with the `case` and
+    /// `default` labels belonging to this switch.
+    Switch {
+        condition: Expression,
+        body: Box<Statement>,
+        cases: Vec<SwitchCase>,
+        label: Option<Identifier>,
+    },
+    Case {
+        value: Expression,
+        body: Box<Statement>,
+        label: Option<Identifier>,
+        span: Span,
+    },
+    Default {
+        body: Box<Statement>,
+        label: Option<Identifier>,
+        span: Span,
+    },
+    Labeled(Identifier, Box<Statement>, Span),
+    Goto(Identifier, Span),
+    Null,
+}
+
+/// A `case` label, or the `default` label if `value` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchCase {
+    pub value: Option<Const>,
+    pub label: Identifier,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ForInit {
+    Declaration(Box<VariableDeclaration>),
+    Expression(Option<Expression>),
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Expression {
-    Constant(i32),
+    Constant(Const),
+    Var(Identifier, Span),
+    /// An explicit cast, or an implicit conversion made explicit by the type
+    /// checker.
+    Cast(Type, Box<Expression>),
     Unary(UnaryOperation, Box<Expression>),
     Binary(BinaryOperation, Box<Expression>, Box<Expression>),
+    /// Assignment of the right to the left operand, located at the `=`.
+    Assignment(Box<Expression>, Box<Expression>, Span),
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+    /// A call of the named function, located at the name.
+    FunctionCall(Identifier, Vec<Expression>, Span),
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum UnaryOperation {
     Complement,
     Negate,
+    Not,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -35,4 +246,17 @@ pub enum BinaryOperation {
     Multiply,
     Divide,
     Remainder,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
 }
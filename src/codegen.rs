@@ -1,44 +1,160 @@
-use crate::assembly;
+use crate::assembly::{self, AssemblyType};
 
 pub fn codegen(assembly: assembly::Program) -> String {
     program(assembly)
 }
 
 fn program(program: assembly::Program) -> String {
-    let function = function_definition(program.function);
-    format!("{function}\n\n\t.section .note.GNU-stack,\"\",@progbits")
+    let functions = program.functions.into_iter().map(function_definition);
+    let variables = program.static_variables.into_iter().map(static_variable);
+    let top_level = functions.chain(variables).collect::<Vec<_>>().join("\n\n");
+    format!("{top_level}\n\n\t.section .note.GNU-stack,\"\",@progbits")
+}
+
+/// Emits the `.globl` directive for `name` if it has external linkage.
+fn globl(name: &str, global: bool) -> String {
+    match global {
+        true => format!("\t.globl {name}\n"),
+        false => String::new(),
+    }
+}
+
+/// Places a variable initialized with zero in `.bss`, which takes no space in
+/// the object file, and any other in `.data`.
+fn static_variable(variable: assembly::StaticVariable) -> String {
+    let size = variable.init.ty().size();
+    let (section, value) = match (variable.init.is_zero(), size) {
+        (true, size) => (".bss", format!("\t.zero {size}")),
+        (false, 4) => (".data", format!("\t.long {}", variable.init)),
+        (false, _) => (".data", format!("\t.quad {}", variable.init)),
+    };
+    format!(
+        "{}\t{section}\n\t.align {}\n{}:\n{value}",
+        globl(&variable.name, variable.global),
+        variable.alignment,
+        variable.name,
+    )
 }
 
 fn function_definition(function: assembly::Function) -> String {
     let is = function
         .instructions
         .into_iter()
-        .map(|i| instruction(i))
+        .map(instruction)
         .collect::<Vec<_>>()
         .join("\n");
 
     format!(
-        "\t.global {}\n{}:\n\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n{}",
-        function.name, function.name, is
+        "{}\t.text\n{}:\n\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n{}",
+        globl(&function.name, function.global),
+        function.name,
+        is
     )
 }
 
 fn instruction(instruction: assembly::Instruction) -> String {
     match instruction {
-        assembly::Instruction::Mov { src, dst } => {
-            format!("\tmovl\t{}, {}", operand(src), operand(dst))
+        assembly::Instruction::Mov { ty, src, dst } => {
+            format!(
+                "\tmov{}\t{}, {}",
+                ty.suffix(),
+                typed_operand(ty, src),
+                typed_operand(ty, dst)
+            )
         }
-        assembly::Instruction::Unary { operator, operand } => {
-            format!("\t{}\t{}", operator, operand)
+        assembly::Instruction::Movsx { src, dst } => {
+            format!("\tmovslq\t{}, {}", operand(src), quad_operand(dst))
+        }
+        assembly::Instruction::MovZeroExtend { .. } => {
+            panic!("Found zero extension, should be replaced by moves")
+        }
+        assembly::Instruction::Unary {
+            operator,
+            ty,
+            operand,
+        } => {
+            format!(
+                "\t{}{}\t{}",
+                operator,
+                ty.suffix(),
+                typed_operand(ty, operand)
+            )
         }
         assembly::Instruction::AllocateStack(i) => {
             format!("\tsubq\t${i}, %rsp")
         }
-        assembly::Instruction::Binary { operator, src, dst } => {
-            format!("\t{}\t{}, {}", operator, src, dst)
+        assembly::Instruction::DeallocateStack(i) => {
+            format!("\taddq\t${i}, %rsp")
+        }
+        assembly::Instruction::Push(op) => format!("\tpushq\t{}", quad_operand(op)),
+        assembly::Instruction::Call { name, external } => {
+            // Functions from other objects may live in a shared library.
+            if external {
+                format!("\tcall\t{name}@PLT")
+            } else {
+                format!("\tcall\t{name}")
+            }
+        }
+        assembly::Instruction::Binary {
+            operator,
+            ty,
+            src,
+            dst,
+        } if operator.is_shift() => {
+            format!(
+                "\t{}{}\t{}, {}",
+                operator,
+                ty.suffix(),
+                byte_operand(src),
+                typed_operand(ty, dst)
+            )
+        }
+        assembly::Instruction::Binary {
+            operator,
+            ty,
+            src,
+            dst,
+        } => {
+            format!(
+                "\t{}{}\t{}, {}",
+                operator,
+                ty.suffix(),
+                typed_operand(ty, src),
+                typed_operand(ty, dst)
+            )
+        }
+        assembly::Instruction::Cmp { ty, src, dst } => {
+            format!(
+                "\tcmp{}\t{}, {}",
+                ty.suffix(),
+                typed_operand(ty, src),
+                typed_operand(ty, dst)
+            )
+        }
+        assembly::Instruction::Idiv(ty, op) => {
+            format!("\tidiv{}\t{}", ty.suffix(), typed_operand(ty, op))
+        }
+        assembly::Instruction::Div(ty, op) => {
+            format!("\tdiv{}\t{}", ty.suffix(), typed_operand(ty, op))
+        }
+        assembly::Instruction::Cdq(AssemblyType::Longword) => "\tcdq".into(),
+        assembly::Instruction::Cdq(AssemblyType::Quadword) => "\tcqo".into(),
+        assembly::Instruction::Jmp(label) => format!("\tjmp\t.L{label}"),
+        assembly::Instruction::JmpCC(cc, label) => format!("\tj{cc}\t.L{label}"),
+        assembly::Instruction::SetCC(cc, op) => format!("\tset{cc}\t{}", byte_operand(op)),
+        assembly::Instruction::Label(label) => format!(".L{label}:"),
+        assembly::Instruction::JumpTable { table, targets } => {
+            // The offsets are relative to the table to keep the code position
+            // independent.
+            let entries = targets
+                .iter()
+                .map(|target| format!("\t.long\t.L{target}-.L{table}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!(
+                "\tleaq\t.L{table}(%rip), %rdx\n\tmovslq\t(%rdx,%rax,4), %rax\n\taddq\t%rdx, %rax\n\tjmp\t*%rax\n\t.section .rodata\n\t.align 4\n.L{table}:\n{entries}\n\t.text"
+            )
         }
-        assembly::Instruction::Idiv(r) => format!("\tidivl\t{}", r),
-        assembly::Instruction::Cdq => "cdq".into(),
         assembly::Instruction::Ret => "\tmovq\t%rbp, %rsp\n\tpopq\t%rbp\n\tret".into(),
     }
 }
@@ -49,3 +165,27 @@ fn operand(operand: assembly::Operand) -> String {
     }
     operand.to_string()
 }
+
+/// Formats the lowest byte of `operand`.
+fn byte_operand(operand: assembly::Operand) -> String {
+    match operand {
+        assembly::Operand::Register(r) => r.byte_name().into(),
+        op => self::operand(op),
+    }
+}
+
+/// Formats `operand` with the size of `ty`.
+fn typed_operand(ty: AssemblyType, operand: assembly::Operand) -> String {
+    match ty {
+        AssemblyType::Longword => self::operand(operand),
+        AssemblyType::Quadword => quad_operand(operand),
+    }
+}
+
+/// Formats `operand` as a 64-bit operand.
+fn quad_operand(operand: assembly::Operand) -> String {
+    match operand {
+        assembly::Operand::Register(r) => r.quad_name().into(),
+        op => self::operand(op),
+    }
+}
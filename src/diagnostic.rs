@@ -0,0 +1,429 @@
+use std::fmt::Display;
+
+use crate::lexer::{LexError, LexWarning, Linemarker, Span, Spanned};
+use crate::parser::ParseError;
+use crate::preprocessor::PreprocessError;
+use crate::semantic::SemanticError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Level {
+    fn color(&self) -> &'static str {
+        match self {
+            Self::Error => RED,
+            Self::Warning => YELLOW,
+            Self::Note => GREEN,
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        };
+        write!(f, "{out}")
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const GREEN: &str = "\x1b[1;32m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// A message about a problem in the compiled program, optionally pointing at
+/// the location in the source that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Short text printed next to the caret underline.
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+            span: None,
+            label: None,
+            notes: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Level::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Level::Warning, message)
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Renders the diagnostic in the style of rustc, quoting the offending
+    /// source line and underlining the span with carets.
+    pub fn render(&self, source: &SourceMap, color: bool) -> String {
+        let style = |code: &'static str| if color { code } else { "" };
+        let (reset, bold, blue) = (style(RESET), style(BOLD), style(BLUE));
+        let level_color = style(self.level.color());
+
+        let mut out = format!(
+            "{level_color}{}{reset}{bold}: {}{reset}\n",
+            self.level, self.message
+        );
+
+        let gutter_width = self
+            .span
+            .map_or(0, |span| source.location(span).line.to_string().len())
+            .max(1);
+        let gutter = " ".repeat(gutter_width);
+
+        if let Some(span) = self.span {
+            let line = source.line(span.line);
+            let location = source.location(span);
+            let line_number = location.line;
+            out += &format!(
+                "{gutter}{blue}-->{reset} {}:{}:{}\n",
+                location.file, location.line, location.column
+            );
+            out += &format!("{gutter} {blue}|{reset}\n");
+            out += &format!("{blue}{line_number:>gutter_width$} |{reset} {line}\n");
+
+            // Reproduce tabs so the carets line up with the quoted line.
+            let indent = line
+                .chars()
+                .take(span.column.saturating_sub(1) as usize)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let available = line
+                .chars()
+                .count()
+                .saturating_sub(span.column.saturating_sub(1) as usize);
+            let width = source.text
+                [span.start.min(source.text.len())..span.end.min(source.text.len())]
+                .chars()
+                .count()
+                .min(available)
+                .max(1);
+            let label = self
+                .label
+                .as_ref()
+                .map_or(String::new(), |l| format!(" {l}"));
+            out += &format!(
+                "{gutter} {blue}|{reset} {indent}{level_color}{}{label}{reset}\n",
+                "^".repeat(width)
+            );
+        }
+
+        if !self.notes.is_empty() || !self.help.is_empty() {
+            if self.span.is_some() {
+                out += &format!("{gutter} {blue}|{reset}\n");
+            }
+            for note in &self.notes {
+                out += &format!("{gutter} {blue}={reset} {bold}note{reset}: {note}\n");
+            }
+            for help in &self.help {
+                out += &format!("{gutter} {blue}={reset} {bold}help{reset}: {help}\n");
+            }
+        }
+        out
+    }
+}
+
+impl From<&ParseError<'_>> for Diagnostic {
+    fn from(value: &ParseError<'_>) -> Self {
+        let diagnostic = Diagnostic::error(value.to_string()).with_span(value.span());
+        match value {
+            ParseError::UnexpectedEOF(_) => diagnostic.with_label("input ends here"),
+            ParseError::LexError(e, span) => Diagnostic::from(&Spanned::new(e.clone(), *span)),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&Spanned<LexError>> for Diagnostic {
+    fn from(value: &Spanned<LexError>) -> Self {
+        let diagnostic = Diagnostic::error(value.node.to_string()).with_span(value.span);
+        match value.node {
+            LexError::UnterminatedComment => diagnostic.with_label("comment starts here"),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&Spanned<LexWarning>> for Diagnostic {
+    fn from(value: &Spanned<LexWarning>) -> Self {
+        Diagnostic::warning(value.node.to_string()).with_span(value.span)
+    }
+}
+
+impl From<&SemanticError> for Diagnostic {
+    fn from(value: &SemanticError) -> Self {
+        let diagnostic = Diagnostic::error(value.to_string()).with_span(value.span());
+        match value {
+            SemanticError::UndeclaredVariable(..) => {
+                diagnostic.with_label("not declared in this scope")
+            }
+            SemanticError::DuplicateDeclaration(..) => diagnostic.with_label("redeclared here"),
+            SemanticError::DuplicateCase(..) | SemanticError::DuplicateDefault(_) => {
+                diagnostic.with_label("already used in this switch")
+            }
+            SemanticError::DuplicateLabel(..) | SemanticError::Redefinition(..) => {
+                diagnostic.with_label("redefined here")
+            }
+            SemanticError::ConflictingDeclaration(..) | SemanticError::ConflictingLinkage(..) => {
+                diagnostic.with_label("redeclared here")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&PreprocessError> for Diagnostic {
+    fn from(value: &PreprocessError) -> Self {
+        Diagnostic::error(value.to_string()).with_span(value.span)
+    }
+}
+
+/// The text a program was compiled from, used to quote source lines in
+/// diagnostics.
+///
+/// Linemarkers left in the text by the preprocessor are honored, so locations
+/// refer to the file and line the code was originally written in.
+pub struct SourceMap<'a> {
+    pub name: &'a str,
+    pub text: &'a str,
+    line_starts: Vec<usize>,
+    markers: Vec<Marker>,
+}
+
+/// A linemarker found on the 1-based line `at`.
+struct Marker {
+    at: u32,
+    linemarker: Linemarker,
+}
+
+/// A position in an original source file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub file: &'a str,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(name: &'a str, text: &'a str) -> Self {
+        let line_starts: Vec<_> = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let markers = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.trim_start().starts_with('#'))
+            .filter_map(|(i, line)| {
+                Some(Marker {
+                    at: i as u32 + 1,
+                    linemarker: Linemarker::parse(line)?,
+                })
+            })
+            .collect();
+        Self {
+            name,
+            text,
+            line_starts,
+            markers,
+        }
+    }
+
+    /// Returns the 1-based line `line` without its line terminator.
+    pub fn line(&self, line: u32) -> &'a str {
+        let Some(&start) = self.line_starts.get(line.saturating_sub(1) as usize) else {
+            return "";
+        };
+        let end = self
+            .line_starts
+            .get(line as usize)
+            .map_or(self.text.len(), |&next| next - 1);
+        self.text[start..end].trim_end_matches('\r')
+    }
+
+    /// Maps the start of `span` back to the file and line it originates from.
+    pub fn location(&self, span: Span) -> Location<'_> {
+        let mut location = Location {
+            file: self.name,
+            line: span.line,
+            column: span.column,
+        };
+        for marker in self.markers.iter().take_while(|m| m.at < span.line) {
+            location.line = marker.linemarker.line + (span.line - marker.at - 1);
+            if let Some(file) = &marker.linemarker.file {
+                location.file = file;
+            }
+        }
+        location
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_caret_under_span() {
+        let text = "int main(void) {\n  return 2)\n}\n";
+        let source = SourceMap::new("a.c", text);
+        let span = Span {
+            start: 27,
+            end: 28,
+            line: 2,
+            column: 11,
+        };
+        let out = Diagnostic::error("found an unexpected token )")
+            .with_span(span)
+            .with_label("expected `;`")
+            .render(&source, false);
+        assert_eq!(
+            out,
+            "error: found an unexpected token )\n \
+             --> a.c:2:11\n  \
+             |\n\
+             2 |   return 2)\n  \
+             |           ^ expected `;`\n"
+        );
+    }
+
+    #[test]
+    fn render_underlines_whole_token_and_keeps_tabs() {
+        let text = "\treturn foo;";
+        let source = SourceMap::new("b.c", text);
+        let span = Span {
+            start: 8,
+            end: 11,
+            line: 1,
+            column: 9,
+        };
+        let out = Diagnostic::error("undeclared variable")
+            .with_span(span)
+            .render(&source, false);
+        assert!(out.contains("1 | \treturn foo;\n  | \t       ^^^\n"));
+    }
+
+    #[test]
+    fn render_notes_and_help() {
+        let source = SourceMap::new("c.c", "");
+        let out = Diagnostic::error("something went wrong")
+            .with_note("this is a note")
+            .with_help("try this instead")
+            .render(&source, false);
+        assert_eq!(
+            out,
+            "error: something went wrong\n  \
+             = note: this is a note\n  \
+             = help: try this instead\n"
+        );
+    }
+
+    #[test]
+    fn render_with_color() {
+        let source = SourceMap::new("c.c", "");
+        let out = Diagnostic::warning("careful").render(&source, true);
+        assert!(out.starts_with("\x1b[1;33mwarning\x1b[0m"));
+    }
+
+    #[test]
+    fn source_map_lines() {
+        let source = SourceMap::new("d.c", "a\r\nbc\n\nd");
+        assert_eq!("a", source.line(1));
+        assert_eq!("bc", source.line(2));
+        assert_eq!("", source.line(3));
+        assert_eq!("d", source.line(4));
+        assert_eq!("", source.line(5));
+    }
+
+    #[test]
+    fn source_map_honors_linemarkers() {
+        let text = "# 1 \"a.c\"\nint x;\n# 1 \"b.h\" 1\n\nint y;\n# 3 \"a.c\" 2\nint z;";
+        let source = SourceMap::new("a.i", text);
+        let at = |line| Span {
+            line,
+            column: 5,
+            ..Default::default()
+        };
+        let location = |file, line| Location {
+            file,
+            line,
+            column: 5,
+        };
+        assert_eq!(location("a.c", 1), source.location(at(2)));
+        assert_eq!(location("b.h", 2), source.location(at(5)));
+        assert_eq!(location("a.c", 3), source.location(at(7)));
+    }
+
+    #[test]
+    fn source_map_without_linemarkers() {
+        let source = SourceMap::new("a.c", "int x;\n#line\nint y;");
+        let span = Span {
+            line: 3,
+            column: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Location {
+                file: "a.c",
+                line: 3,
+                column: 1
+            },
+            source.location(span)
+        );
+    }
+
+    #[test]
+    fn render_points_at_original_file() {
+        let text = "# 1 \"main.c\"\n# 10 \"main.c\"\nint main(void) { return 2) }";
+        let source = SourceMap::new("main.i", text);
+        let span = Span {
+            start: 51,
+            end: 52,
+            line: 3,
+            column: 25,
+        };
+        let out = Diagnostic::error("found an unexpected token )")
+            .with_span(span)
+            .render(&source, false);
+        assert!(out.contains("--> main.c:10:25\n"));
+        assert!(out.contains("10 | int main(void) { return 2) }\n"));
+    }
+}
@@ -1,163 +1,1186 @@
-use std::{fmt::Display, str::Chars};
+use std::{borrow::Cow, fmt::Display};
 
-pub fn lex(source: &str) -> impl Iterator<Item = Token> {
-    let mut chars = Cursor::new(source.chars());
+/// Splits `source` into tokens.
+///
+/// Identifiers and literals borrow their text from `source` where possible.
+pub fn lex(source: &str) -> impl Iterator<Item = Spanned<Token<'_>>> {
+    let mut cursor = Cursor::new(source);
 
-    std::iter::from_fn(move || chars.lex())
+    std::iter::from_fn(move || cursor.lex())
 }
 
-struct Cursor<'a> {
-    chars: Chars<'a>,
-    current: char,
+/// Splits `source` into preprocessing tokens, each paired with whether it is
+/// the first token on its line.
+///
+/// Unlike `lex`, linemarkers are returned as tokens and adjacent string
+/// literals are not concatenated.
+pub(crate) fn lex_preprocessing(source: &str) -> impl Iterator<Item = (Spanned<Token<'_>>, bool)> {
+    let mut cursor = Cursor::new(source);
+    cursor.preprocessing = true;
+
+    std::iter::from_fn(move || {
+        let token = cursor.lex()?;
+        Some((token, cursor.token_starts_line))
+    })
+}
+
+/// Lexes all of `source`, separating the lexical errors from the tokens.
+///
+/// The lexer recovers from every error, so the invalid tokens are left out
+/// and the remaining tokens can still be parsed.
+pub fn tokenize(source: &str) -> (Vec<Spanned<Token<'_>>>, Vec<Spanned<LexError>>) {
+    let mut errors = vec![];
+    let tokens = lex(source)
+        .filter_map(|t| match t.node {
+            Token::Invalid(e) => {
+                errors.push(Spanned::new(e, t.span));
+                None
+            }
+            _ => Some(t),
+        })
+        .collect();
+    (tokens, errors)
+}
+
+/// Finds the valid but suspicious tokens in `tokens`.
+pub fn warnings(tokens: &[Spanned<Token>]) -> Vec<Spanned<LexWarning>> {
+    tokens
+        .iter()
+        .filter_map(|t| match &t.node {
+            Token::MultiCharLiteral(chars) if chars.len() > 4 => {
+                Some(Spanned::new(LexWarning::CharacterConstantTooLong, t.span))
+            }
+            Token::MultiCharLiteral(_) => {
+                Some(Spanned::new(LexWarning::MultiCharacterConstant, t.span))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The value of the character constant `chars` with more than one character.
+///
+/// Like gcc, the characters are packed into an `int` with the last one in the
+/// lowest byte, so only the last four of them count.
+pub fn multi_char_value(chars: &[u8]) -> i32 {
+    chars
+        .iter()
+        .fold(0u32, |value, &c| (value << 8) | u32::from(c)) as i32
+}
+
+/// A region of the source text.
+///
+/// `start` and `end` are byte offsets, `line` and `column` are 1-based and
+/// refer to the first character of the region.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
-const EOF: char = '\0';
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Self { node, span }
+    }
+}
 
-impl<'a> Cursor<'a> {
-    fn new(mut chars: Chars<'a>) -> Self {
-        let current = chars.next().unwrap_or(EOF);
-        Self { chars, current }
+struct Cursor<'src> {
+    source: &'src str,
+    bytes: &'src [u8],
+    /// Byte offset of `current`.
+    position: usize,
+    current: u8,
+    line: u32,
+    column: u32,
+    /// Number of line continuations removed so far, used to tell whether the
+    /// text of a token can be borrowed from the source as is.
+    splices: usize,
+    /// Whether only whitespace has been seen since the last newline.
+    line_start: bool,
+    /// Whether the last token lexed is the first on its line.
+    token_starts_line: bool,
+    /// Whether tokens are lexed for the preprocessor, which interprets
+    /// linemarkers and leaves string literals apart.
+    preprocessing: bool,
+    /// A token that was lexed ahead and is returned next.
+    pending: Option<Spanned<Token<'src>>>,
+}
+
+/// Value of `current` at the end of the input. A NUL byte within the input is
+/// distinguished from the end by `Cursor::at_eof`.
+const EOF: u8 = b'\0';
+
+impl<'src> Cursor<'src> {
+    fn new(source: &'src str) -> Self {
+        let mut cursor = Self {
+            source,
+            bytes: source.as_bytes(),
+            position: 0,
+            current: EOF,
+            line: 1,
+            column: 1,
+            splices: 0,
+            line_start: true,
+            token_starts_line: true,
+            preprocessing: false,
+            pending: None,
+        };
+        cursor.splice_lines();
+        cursor
     }
 
-    fn lex(&mut self) -> Option<Token> {
-        self.skip_whitespace();
+    fn lex(&mut self) -> Option<Spanned<Token<'src>>> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+        let trivia_error = self.skip_trivia();
+        self.token_starts_line = self.line_start;
+        if let Some(error) = trivia_error {
+            return Some(error);
+        }
+        let start = self.mark();
         let token = match self.current {
-            '(' => {
+            b'(' => self.single(Token::OpenParenthesis),
+            b')' => self.single(Token::CloseParenthesis),
+            b'{' => self.single(Token::OpenBrace),
+            b'}' => self.single(Token::CloseBrace),
+            b'[' => self.single(Token::OpenBracket),
+            b']' => self.single(Token::CloseBracket),
+            b';' => self.single(Token::Semicolon),
+            b',' => self.single(Token::Comma),
+            b'?' => self.single(Token::Question),
+            b'~' => self.single(Token::Tilde),
+            b'.' if self.peek().is_ascii_digit() => self.constant(),
+            b'.' => {
+                self.bump();
+                if self.current == b'.' && self.peek() == b'.' {
+                    self.bump();
+                    self.bump();
+                    Token::Ellipsis
+                } else {
+                    Token::Dot
+                }
+            }
+            b':' => {
                 self.bump();
-                Token::OpenParenthesis
+                if self.eat(b'>') {
+                    Token::CloseBracket
+                } else {
+                    Token::Colon
+                }
             }
-            ')' => {
+            b'+' => {
                 self.bump();
-                Token::CloseParenthesis
+                if self.eat(b'+') {
+                    Token::DoublePlus
+                } else if self.eat(b'=') {
+                    Token::PlusEqual
+                } else {
+                    Token::Plus
+                }
             }
-            '{' => {
+            b'-' => {
                 self.bump();
-                Token::OpenBrace
+                if self.eat(b'-') {
+                    Token::DoubleMinus
+                } else if self.eat(b'=') {
+                    Token::MinusEqual
+                } else if self.eat(b'>') {
+                    Token::Arrow
+                } else {
+                    Token::Minus
+                }
             }
-            '}' => {
+            b'*' => {
                 self.bump();
-                Token::CloseBrace
+                self.or_equal(Token::Star, Token::StarEqual)
             }
-            ';' => {
+            b'/' => {
                 self.bump();
-                Token::Semicolon
+                self.or_equal(Token::Slash, Token::SlashEqual)
             }
-            '+' => {
+            b'^' => {
                 self.bump();
-                Token::Plus
+                self.or_equal(Token::Caret, Token::CaretEqual)
             }
-            '*' => {
+            b'=' => {
                 self.bump();
-                Token::Star
+                self.or_equal(Token::Equal, Token::DoubleEqual)
             }
-            '/' => {
+            b'!' => {
                 self.bump();
-                Token::Slash
+                self.or_equal(Token::Bang, Token::BangEqual)
             }
-            '%' => {
+            b'%' => {
                 self.bump();
-                Token::Percent
+                if self.eat(b'=') {
+                    Token::PercentEqual
+                } else if self.eat(b'>') {
+                    Token::CloseBrace
+                } else if self.eat(b':') {
+                    if self.current == b'%' && self.peek() == b':' {
+                        self.bump();
+                        self.bump();
+                        Token::DoubleHash
+                    } else {
+                        Token::Hash
+                    }
+                } else {
+                    Token::Percent
+                }
             }
-            '~' => {
+            b'&' => {
                 self.bump();
-                Token::Tilde
+                if self.eat(b'&') {
+                    Token::DoubleAmpersand
+                } else if self.eat(b'=') {
+                    Token::AmpersandEqual
+                } else {
+                    Token::Ampersand
+                }
             }
-            '-' => {
-                if self.bump() == '-' {
-                    self.bump();
-                    Token::DoubleMinus
+            b'|' => {
+                self.bump();
+                if self.eat(b'|') {
+                    Token::DoublePipe
+                } else if self.eat(b'=') {
+                    Token::PipeEqual
                 } else {
-                    Token::Minus
+                    Token::Pipe
                 }
             }
-            '0'..='9' => self.constant(),
-            'a'..='z' => self.identifier(),
-            'A'..='Z' => self.identifier(),
-            EOF => return None,
-            _ => {
-                let current = self.current;
+            b'<' => {
+                self.bump();
+                if self.eat(b'<') {
+                    self.or_equal(Token::DoubleLess, Token::DoubleLessEqual)
+                } else if self.eat(b'=') {
+                    Token::LessEqual
+                } else if self.eat(b':') {
+                    Token::OpenBracket
+                } else if self.eat(b'%') {
+                    Token::OpenBrace
+                } else {
+                    Token::Less
+                }
+            }
+            b'>' => {
+                self.bump();
+                if self.eat(b'>') {
+                    self.or_equal(Token::DoubleGreater, Token::DoubleGreaterEqual)
+                } else if self.eat(b'=') {
+                    Token::GreaterEqual
+                } else {
+                    Token::Greater
+                }
+            }
+            b'#' => {
                 self.bump();
-                Token::Invalid(current.into())
+                if self.eat(b'#') {
+                    Token::DoubleHash
+                } else {
+                    Token::Hash
+                }
+            }
+            b'\'' => self.char_literal(),
+            b'"' => self
+                .quoted(b'"')
+                .map_or_else(Token::Invalid, Token::StringLiteral),
+            b'0'..=b'9' => self.constant(),
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.identifier(),
+            EOF if self.at_eof() => return None,
+            _ => {
+                let current = self.source[self.position..]
+                    .chars()
+                    .next()
+                    .expect("tokens start at character boundaries");
+                for _ in 0..current.len_utf8() {
+                    self.bump();
+                }
+                Token::Invalid(LexError::InvalidCharacter(current))
             }
         };
+        self.line_start = false;
+        let mut token = self.spanned(token, start);
+        if !self.preprocessing && matches!(token.node, Token::StringLiteral(_)) {
+            self.concatenate_strings(&mut token);
+        }
         Some(token)
     }
 
-    fn identifier(&mut self) -> Token {
-        let mut buffer = String::new();
-        buffer.push(self.current);
-        while self.bump().is_alphanumeric() {
-            buffer.push(self.current);
+    /// Consumes the current character and returns `token`.
+    fn single(&mut self, token: Token<'src>) -> Token<'src> {
+        self.bump();
+        token
+    }
+
+    /// Consumes a following `=` and returns `with_equal` if there is one,
+    /// and `token` otherwise.
+    fn or_equal(&mut self, token: Token<'src>, with_equal: Token<'src>) -> Token<'src> {
+        if self.eat(b'=') {
+            with_equal
+        } else {
+            token
+        }
+    }
+
+    /// Consumes the current character if it is `c`.
+    fn eat(&mut self, c: u8) -> bool {
+        if self.current == c {
+            self.bump();
+            return true;
+        }
+        false
+    }
+
+    /// Returns an empty span at the current position.
+    fn mark(&self) -> Span {
+        Span {
+            start: self.position,
+            end: self.position,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Attaches a span from `start` up to the current position to `token`.
+    fn spanned(&self, token: Token<'src>, start: Span) -> Spanned<Token<'src>> {
+        let span = Span {
+            end: self.position,
+            ..start
+        };
+        Spanned::new(token, span)
+    }
+
+    /// Returns the source text from `start` up to the current position.
+    ///
+    /// The text is borrowed from the source unless line continuations have
+    /// to be removed from it, which is the case if `self.splices` changed
+    /// since `splices` was taken.
+    fn text(&self, start: usize, splices: usize) -> Cow<'src, str> {
+        let text = &self.source[start..self.position];
+        if splices == self.splices {
+            return Cow::Borrowed(text);
+        }
+        Cow::Owned(text.replace("\\\r\n", "").replace("\\\n", ""))
+    }
+
+    /// Skips whitespace, comments and linemarkers.
+    /// An unterminated block comment is returned as an invalid token.
+    fn skip_trivia(&mut self) -> Option<Spanned<Token<'src>>> {
+        loop {
+            self.skip_whitespace();
+            if !self.preprocessing
+                && self.line_start
+                && self.current == b'#'
+                && self.skip_linemarker()
+            {
+                continue;
+            }
+            if self.current != b'/' {
+                return None;
+            }
+            match self.peek() {
+                b'/' => self.skip_line_comment(),
+                b'*' => {
+                    let start = self.mark();
+                    if !self.skip_block_comment() {
+                        let token = Token::Invalid(LexError::UnterminatedComment);
+                        return Some(self.spanned(token, start));
+                    }
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Skips the rest of the line if the cursor is at a linemarker.
+    ///
+    /// The locations they describe are resolved by `diagnostic::SourceMap`.
+    fn skip_linemarker(&mut self) -> bool {
+        let rest = &self.source[self.position..];
+        let line = rest.split('\n').next().unwrap_or_default();
+        if Linemarker::parse(line).is_none() {
+            return false;
+        }
+        self.skip_line_comment();
+        true
+    }
+
+    fn identifier(&mut self) -> Token<'src> {
+        let (start, splices) = (self.position, self.splices);
+        while self.bump().is_ascii_alphanumeric() || self.current == b'_' {}
+        identifier_to_token(self.text(start, splices))
+    }
+
+    /// Lexes a preprocessing number (C17 6.4.8) and converts it into an
+    /// integer or floating constant.
+    fn constant(&mut self) -> Token<'src> {
+        let (start, splices) = (self.position, self.splices);
+        let mut previous = EOF;
+        loop {
+            let exponent_sign = matches!(self.current, b'+' | b'-')
+                && matches!(previous, b'e' | b'E' | b'p' | b'P');
+            if !(self.current.is_ascii_alphanumeric()
+                || matches!(self.current, b'_' | b'.')
+                || exponent_sign)
+            {
+                break;
+            }
+            previous = self.current;
+            self.bump();
+        }
+
+        let text = self.text(start, splices);
+        let hex = text.starts_with("0x") || text.starts_with("0X");
+        let float = text.contains('.')
+            || (hex && text.contains(['p', 'P']))
+            || (!hex && text.contains(['e', 'E']));
+        if float {
+            float_constant(&text).unwrap_or_else(Token::Invalid)
+        } else {
+            integer_constant(&text).unwrap_or_else(Token::Invalid)
+        }
+    }
+
+    /// Skips a `//` comment, leaving the terminating newline in place.
+    fn skip_line_comment(&mut self) {
+        while self.current != b'\n' && !self.at_eof() {
+            self.bump();
+        }
+    }
+
+    /// Skips a `/* */` comment.
+    /// Returns false if the input ends before the comment is closed.
+    ///
+    /// The comment stands for a single space, so the line it ends on does
+    /// not count as a new one.
+    fn skip_block_comment(&mut self) -> bool {
+        let line_start = self.line_start;
+        self.bump();
+        self.bump();
+        let closed = loop {
+            if self.at_eof() {
+                break false;
+            }
+            if self.current == b'*' {
+                if self.bump() == b'/' {
+                    self.bump();
+                    break true;
+                }
+            } else {
+                self.bump();
+            }
+        };
+        self.line_start = line_start;
+        closed
+    }
+
+    fn char_literal(&mut self) -> Token<'src> {
+        match self.quoted(b'\'') {
+            Ok(chars) => match chars.len() {
+                0 => Token::Invalid(LexError::EmptyCharacterConstant),
+                1 => Token::CharLiteral(chars[0]),
+                _ => Token::MultiCharLiteral(chars),
+            },
+            Err(e) => Token::Invalid(e),
+        }
+    }
+
+    /// Lexes a character or string literal delimited by `quote` and returns
+    /// its contents with all escape sequences decoded.
+    ///
+    /// The contents are only copied if they contain escape sequences or line
+    /// continuations.
+    fn quoted(&mut self, quote: u8) -> Result<Cow<'src, [u8]>, LexError> {
+        self.bump();
+        let (start, splices) = (self.position, self.splices);
+        let mut decoded: Option<Vec<u8>> = None;
+        let mut error = None;
+        loop {
+            match self.current {
+                c if c == quote => break,
+                b'\n' if quote == b'"' => return Err(LexError::UnterminatedString),
+                b'\n' => return Err(LexError::UnterminatedCharacter),
+                EOF if self.at_eof() && quote == b'"' => return Err(LexError::UnterminatedString),
+                EOF if self.at_eof() => return Err(LexError::UnterminatedCharacter),
+                b'\\' => {
+                    let decoded = decoded
+                        .get_or_insert_with(|| self.text(start, splices).into_owned().into_bytes());
+                    match self.escape_sequence() {
+                        Ok(b) => decoded.push(b),
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                c => {
+                    if let Some(decoded) = &mut decoded {
+                        decoded.push(c);
+                    }
+                    self.bump();
+                }
+            }
+        }
+        let contents = match decoded {
+            Some(decoded) => Cow::Owned(decoded),
+            None => match self.text(start, splices) {
+                Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+            },
+        };
+        self.bump();
+        error.map_or(Ok(contents), Err)
+    }
+
+    /// Decodes the escape sequence starting at the backslash under the cursor.
+    fn escape_sequence(&mut self) -> Result<u8, LexError> {
+        let c = self.bump();
+        let simple = match c {
+            b'\'' | b'"' | b'?' | b'\\' => Some(c),
+            b'a' => Some(0x07),
+            b'b' => Some(0x08),
+            b'f' => Some(0x0c),
+            b'n' => Some(b'\n'),
+            b'r' => Some(b'\r'),
+            b't' => Some(b'\t'),
+            b'v' => Some(0x0b),
+            _ => None,
+        };
+        if let Some(b) = simple {
+            self.bump();
+            return Ok(b);
+        }
+
+        let (radix, max_digits) = match c {
+            b'0'..=b'7' => (8, 3),
+            b'x' => {
+                self.bump();
+                (16, usize::MAX)
+            }
+            b'\n' => return Err(LexError::InvalidEscape("\\".into())),
+            EOF if self.at_eof() => return Err(LexError::InvalidEscape("\\".into())),
+            _ => {
+                let c = self.source[self.position..]
+                    .chars()
+                    .next()
+                    .expect("escapes start at character boundaries");
+                for _ in 0..c.len_utf8() {
+                    self.bump();
+                }
+                return Err(LexError::InvalidEscape(format!("\\{c}")));
+            }
+        };
+        let mut digits = String::new();
+        while digits.len() < max_digits && (self.current as char).is_digit(radix) {
+            digits.push(self.current as char);
+            self.bump();
+        }
+
+        let prefix = if radix == 16 { "\\x" } else { "\\" };
+        let escape = || format!("{prefix}{digits}");
+        if digits.is_empty() {
+            return Err(LexError::InvalidEscape(escape()));
         }
-        identifier_to_token(buffer)
+        u32::from_str_radix(&digits, radix)
+            .ok()
+            .and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| LexError::EscapeOutOfRange(escape()))
     }
 
-    fn constant(&mut self) -> Token {
-        let mut buffer = String::new();
-        buffer.push(self.current);
-        while self.bump().is_alphanumeric() {
-            buffer.push(self.current);
+    /// Appends the string literals following `literal` to it, as adjacent
+    /// literals are concatenated in translation phase 6 (C17 5.1.1.2).
+    fn concatenate_strings(&mut self, literal: &mut Spanned<Token<'src>>) {
+        loop {
+            if let Some(error) = self.skip_trivia() {
+                self.pending = Some(error);
+                return;
+            }
+            if self.current != b'"' {
+                return;
+            }
+            let next = self.quoted(b'"');
+            literal.span.end = self.position;
+            match (&mut literal.node, next) {
+                (Token::StringLiteral(bytes), Ok(next)) => bytes.to_mut().extend_from_slice(&next),
+                (Token::StringLiteral(_), Err(e)) => literal.node = Token::Invalid(e),
+                _ => {}
+            }
         }
-        buffer
-            .parse()
-            .map_or_else(|_| Token::Invalid(buffer), |i| Token::Constant(i))
     }
 
-    fn skip_whitespace(&mut self) -> char {
-        while self.current.is_whitespace() {
+    fn skip_whitespace(&mut self) -> u8 {
+        while matches!(self.current, b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c) {
             self.bump();
         }
         self.current
     }
-    fn bump(&mut self) -> char {
-        self.current = self.chars.next().unwrap_or(EOF);
+
+    fn bump(&mut self) -> u8 {
+        if let Some(&b) = self.bytes.get(self.position) {
+            self.position += 1;
+            if b == b'\n' {
+                self.line += 1;
+                self.column = 1;
+                self.line_start = true;
+            } else if b & 0xc0 != 0x80 {
+                // Continuation bytes of multi-byte characters take no column.
+                self.column += 1;
+            }
+        }
+        self.current = self.byte_at(self.position);
+        if self.current == b'\\' {
+            self.splice_lines();
+        }
         self.current
     }
+
+    fn at_eof(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn byte_at(&self, position: usize) -> u8 {
+        self.bytes.get(position).copied().unwrap_or(EOF)
+    }
+
+    /// Returns the length of the backslash-newline sequence at `position`, or
+    /// 0 if there is none.
+    fn splice_at(&self, position: usize) -> usize {
+        match self.bytes.get(position..position + 2) {
+            Some(b"\\\n") => 2,
+            Some(b"\\\r") if self.byte_at(position + 2) == b'\n' => 3,
+            _ => 0,
+        }
+    }
+
+    /// Returns the character after the current one, looking through line
+    /// continuations.
+    fn peek(&self) -> u8 {
+        let mut position = self.position + 1;
+        loop {
+            match self.splice_at(position) {
+                0 => return self.byte_at(position),
+                n => position += n,
+            }
+        }
+    }
+
+    /// Removes backslash-newline sequences in front of the cursor, joining
+    /// the physical lines around them.
+    fn splice_lines(&mut self) {
+        loop {
+            match self.splice_at(self.position) {
+                0 => break,
+                n => {
+                    self.position += n;
+                    self.line += 1;
+                    self.column = 1;
+                    self.splices += 1;
+                }
+            }
+        }
+        self.current = self.byte_at(self.position);
+    }
+}
+
+/// Parses an integer constant including its base prefix and suffix, choosing
+/// its type as described in C17 6.4.4.1.
+///
+/// `long long` has the same representation as `long`, so `ll` constants
+/// become `LongConstant`s. Binary constants (`0b101`) are a GNU extension and
+/// follow the rules for octal and hexadecimal constants.
+fn integer_constant(literal: &str) -> Result<Token<'static>, LexError> {
+    let invalid = || LexError::InvalidConstant(literal.into());
+
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x" | "0X") => (16, &literal[2..]),
+        Some("0b" | "0B") => (2, &literal[2..]),
+        _ if literal.starts_with('0') => (8, literal),
+        _ => (10, literal),
+    };
+
+    let suffix_start = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let (digits, suffix) = digits.split_at(suffix_start);
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let (unsigned, long) = match suffix.to_ascii_lowercase().as_str() {
+        "" => (false, false),
+        "u" => (true, false),
+        "l" => (false, true),
+        "ul" | "lu" => (true, true),
+        // Both `l`s of `ll` must have the same case.
+        lower @ ("ll" | "ull" | "llu") if !suffix.contains("lL") && !suffix.contains("Ll") => {
+            (lower.contains('u'), true)
+        }
+        _ => return Err(invalid()),
+    };
+
+    let value = u64::from_str_radix(digits, radix)
+        .map_err(|_| LexError::ConstantTooLarge(literal.into()))?;
+    let decimal = radix == 10;
+
+    let token = if !long && !unsigned && value <= i32::MAX as u64 {
+        Token::Constant(value as i32)
+    } else if !long && (unsigned || !decimal) && value <= u32::MAX as u64 {
+        Token::UnsignedConstant(value as u32)
+    } else if !unsigned && value <= i64::MAX as u64 {
+        Token::LongConstant(value as i64)
+    } else if unsigned || !decimal {
+        Token::UnsignedLongConstant(value)
+    } else {
+        return Err(LexError::ConstantTooLarge(literal.into()));
+    };
+    Ok(token)
+}
+
+/// Parses a decimal or hexadecimal floating constant (C17 6.4.4.2).
+///
+/// The value is rounded correctly to the type given by the suffix. Long
+/// doubles are represented with the precision and range of a double.
+fn float_constant(literal: &str) -> Result<Token<'static>, LexError> {
+    let invalid = || LexError::InvalidConstant(literal.into());
+
+    let (body, kind) = match literal.as_bytes().last() {
+        Some(b'f' | b'F') => (&literal[..literal.len() - 1], FloatKind::Float),
+        Some(b'l' | b'L') => (&literal[..literal.len() - 1], FloatKind::LongDouble),
+        _ => (literal, FloatKind::Double),
+    };
+
+    let hex = body.strip_prefix("0x").or_else(|| body.strip_prefix("0X"));
+    let (mantissa, exponent) = match hex {
+        Some(hex) => hex.split_once(['p', 'P']).ok_or_else(invalid)?,
+        None => body.split_once(['e', 'E']).unwrap_or((body, "0")),
+    };
+    let radix = if hex.is_some() { 16 } else { 10 };
+
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits_valid = |s: &str| s.chars().all(|c| c.is_digit(radix));
+    if integer.is_empty() && fraction.is_empty()
+        || !digits_valid(integer)
+        || !digits_valid(fraction)
+    {
+        return Err(invalid());
+    }
+    let exponent_digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+    if exponent_digits.is_empty() || !exponent_digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let value = if hex.is_some() {
+        // Exponents beyond the range of i64 over- or underflow any type anyway.
+        let exponent = exponent
+            .parse::<i64>()
+            .unwrap_or(if exponent.starts_with('-') {
+                i64::MIN / 2
+            } else {
+                i64::MAX / 2
+            });
+        hex_float(integer, fraction, exponent, kind)
+    } else {
+        // Rust's parsers round correctly, and every float is exact as a double.
+        let decimal = format!("{integer}.{fraction}e{exponent}");
+        match kind {
+            FloatKind::Float => decimal.parse::<f32>().map(f64::from),
+            FloatKind::Double | FloatKind::LongDouble => decimal.parse::<f64>(),
+        }
+        .map_err(|_| invalid())?
+    };
+
+    if value.is_infinite() {
+        return Err(LexError::FloatConstantTooLarge(literal.into(), kind));
+    }
+    Ok(Token::FloatConstant(value, kind))
+}
+
+/// Computes the value of the hexadecimal floating constant
+/// `0x{integer}.{fraction}p{exponent}`, rounded to nearest, ties to even, to
+/// the precision of `kind`.
+fn hex_float(integer: &str, fraction: &str, exponent: i64, kind: FloatKind) -> f64 {
+    let (precision, min_exponent, max_exponent) = match kind {
+        FloatKind::Float => (24, -126, 127),
+        FloatKind::Double | FloatKind::LongDouble => (53, -1022, 1023),
+    };
+
+    // Collect the significant bits in `mantissa` so that the value is
+    // `mantissa * 2^exponent`, remembering whether any set bits were dropped.
+    let mut mantissa: u64 = 0;
+    let mut exponent = exponent;
+    let mut sticky = false;
+    let digits = integer.chars().map(|c| (c, false));
+    let digits = digits.chain(fraction.chars().map(|c| (c, true)));
+    for (digit, is_fraction) in digits {
+        let digit = digit.to_digit(16).expect("validated by the caller") as u64;
+        if mantissa >> 60 == 0 {
+            mantissa = mantissa << 4 | digit;
+            if is_fraction {
+                exponent -= 4;
+            }
+        } else {
+            sticky |= digit != 0;
+            if !is_fraction {
+                exponent += 4;
+            }
+        }
+    }
+    if mantissa == 0 {
+        return 0.0;
+    }
+
+    let bits = 64 - mantissa.leading_zeros() as i64;
+    let top_exponent = exponent + bits - 1;
+    if top_exponent > max_exponent {
+        return f64::INFINITY;
+    }
+    // Subnormal results have fewer bits of precision available.
+    let precision = precision - (min_exponent - top_exponent).max(0);
+    if precision < 0 {
+        return 0.0;
+    }
+
+    let shift = bits - precision;
+    if shift > 0 {
+        let shift = shift as u32;
+        let dropped = mantissa as u128 & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        mantissa = mantissa.checked_shr(shift).unwrap_or(0);
+        exponent += shift as i64;
+        let round_up = dropped > half || dropped == half && (sticky || mantissa & 1 == 1);
+        if round_up {
+            mantissa += 1;
+        }
+    }
+
+    // All steps are exact: the mantissa has at most `precision` bits.
+    let mut value = mantissa as f64;
+    while exponent > 0 {
+        let step = exponent.min(1000);
+        value *= 2f64.powi(step as i32);
+        exponent -= step;
+    }
+    while exponent < 0 {
+        let step = exponent.max(-1000);
+        value *= 2f64.powi(step as i32);
+        exponent -= step;
+    }
+    if kind == FloatKind::Float && value > f32::MAX as f64 {
+        return f64::INFINITY;
+    }
+    value
+}
+
+/// A line directive left behind by the preprocessor, such as
+/// `# 12 "foo.c" 2` or `#line 12 "foo.c"`.
+///
+/// It states that the following line is line `line` of `file`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Linemarker {
+    pub line: u32,
+    pub file: Option<String>,
+}
+
+impl Linemarker {
+    /// Parses a whole source line as a linemarker.
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim_start().strip_prefix('#')?.trim_start();
+        let rest = rest
+            .strip_prefix("line")
+            .filter(|r| r.starts_with(char::is_whitespace))
+            .unwrap_or(rest)
+            .trim_start();
+
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let line = rest[..digits].parse().ok()?;
+        let rest = rest[digits..].trim_start();
+        if rest.is_empty() {
+            return Some(Self { line, file: None });
+        }
+
+        let mut file = String::new();
+        let mut chars = rest.strip_prefix('"')?.chars();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => file.push(chars.next()?),
+                c => file.push(c),
+            }
+        }
+        // Anything after the file name are gcc's flags, which are ignored.
+        chars
+            .as_str()
+            .split_whitespace()
+            .all(|flag| flag.parse::<u32>().is_ok())
+            .then_some(Self {
+                line,
+                file: Some(file),
+            })
+    }
 }
 
-fn identifier_to_token(identifier: String) -> Token {
-    match identifier.as_str() {
+fn identifier_to_token(identifier: Cow<'_, str>) -> Token<'_> {
+    match &*identifier {
+        "auto" => Token::Auto,
+        "break" => Token::Break,
+        "case" => Token::Case,
+        "char" => Token::Char,
+        "const" => Token::Const,
+        "continue" => Token::Continue,
+        "default" => Token::Default,
+        "do" => Token::Do,
+        "double" => Token::Double,
+        "else" => Token::Else,
+        "enum" => Token::Enum,
+        "extern" => Token::Extern,
+        "float" => Token::Float,
+        "for" => Token::For,
+        "goto" => Token::Goto,
+        "if" => Token::If,
+        "inline" => Token::Inline,
         "int" => Token::Int,
-        "void" => Token::Void,
+        "long" => Token::Long,
+        "register" => Token::Register,
+        "restrict" => Token::Restrict,
         "return" => Token::Return,
+        "short" => Token::Short,
+        "signed" => Token::Signed,
+        "sizeof" => Token::Sizeof,
+        "static" => Token::Static,
+        "struct" => Token::Struct,
+        "switch" => Token::Switch,
+        "typedef" => Token::Typedef,
+        "union" => Token::Union,
+        "unsigned" => Token::Unsigned,
+        "void" => Token::Void,
+        "volatile" => Token::Volatile,
+        "while" => Token::While,
+        "_Alignas" => Token::Alignas,
+        "_Alignof" => Token::Alignof,
+        "_Atomic" => Token::Atomic,
+        "_Bool" => Token::Bool,
+        "_Complex" => Token::Complex,
+        "_Generic" => Token::Generic,
+        "_Imaginary" => Token::Imaginary,
+        "_Noreturn" => Token::Noreturn,
+        "_Static_assert" => Token::StaticAssert,
+        "_Thread_local" => Token::ThreadLocal,
         _ => Token::Identifier(identifier),
     }
 }
 
+/// A token, borrowing the text of identifiers and string literals from the
+/// source where possible.
 #[derive(Debug, PartialEq, Clone)]
-pub enum Token {
-    Identifier(String),
+pub enum Token<'src> {
+    Identifier(Cow<'src, str>),
     Constant(i32),
+    LongConstant(i64),
+    UnsignedConstant(u32),
+    UnsignedLongConstant(u64),
+    FloatConstant(f64, FloatKind),
+    CharLiteral(u8),
+    /// A character constant like `'ab'`, whose value is implementation-defined
+    /// (C17 6.4.4.4p10) and given by [`multi_char_value`].
+    MultiCharLiteral(Cow<'src, [u8]>),
+    StringLiteral(Cow<'src, [u8]>),
 
     // Operators
     Tilde,
     Minus,
     DoubleMinus,
     Plus,
+    DoublePlus,
     Star,
     Slash,
     Percent,
+    Bang,
+    Ampersand,
+    DoubleAmpersand,
+    Pipe,
+    DoublePipe,
+    Caret,
+    DoubleLess,
+    DoubleGreater,
+    Equal,
+    DoubleEqual,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    AmpersandEqual,
+    PipeEqual,
+    CaretEqual,
+    DoubleLessEqual,
+    DoubleGreaterEqual,
+    Question,
+    Colon,
+    Dot,
+    Arrow,
 
     // Keywords
+    Auto,
+    Break,
+    Case,
+    Char,
+    Const,
+    Continue,
+    Default,
+    Do,
+    Double,
+    Else,
+    Enum,
+    Extern,
+    Float,
+    For,
+    Goto,
+    If,
+    Inline,
     Int,
-    Void,
+    Long,
+    Register,
+    Restrict,
     Return,
+    Short,
+    Signed,
+    Sizeof,
+    Static,
+    Struct,
+    Switch,
+    Typedef,
+    Union,
+    Unsigned,
+    Void,
+    Volatile,
+    While,
+    Alignas,
+    Alignof,
+    Atomic,
+    Bool,
+    Complex,
+    Generic,
+    Imaginary,
+    Noreturn,
+    StaticAssert,
+    ThreadLocal,
 
     // Punctuation
     OpenParenthesis,
     CloseParenthesis,
     OpenBrace,
     CloseBrace,
+    OpenBracket,
+    CloseBracket,
     Semicolon,
+    Comma,
+    Ellipsis,
+    Hash,
+    DoubleHash,
+
+    Invalid(LexError),
+}
+
+/// The type of a floating constant, given by its suffix.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FloatKind {
+    Float,
+    Double,
+    LongDouble,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexError {
+    InvalidCharacter(char),
+    InvalidConstant(String),
+    ConstantTooLarge(String),
+    FloatConstantTooLarge(String, FloatKind),
+    UnterminatedComment,
+    UnterminatedCharacter,
+    UnterminatedString,
+    EmptyCharacterConstant,
+    InvalidEscape(String),
+    EscapeOutOfRange(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexWarning {
+    MultiCharacterConstant,
+    CharacterConstantTooLong,
+}
+
+impl Display for LexWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MultiCharacterConstant => write!(f, "multi-character character constant"),
+            Self::CharacterConstantTooLong => {
+                write!(f, "character constant too long for its type")
+            }
+        }
+    }
+}
 
-    Invalid(String),
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCharacter(c) => write!(f, "invalid character {c:?}"),
+            Self::InvalidConstant(s) => write!(f, "invalid constant `{s}`"),
+            Self::ConstantTooLarge(s) => write!(f, "integer constant `{s}` is too large"),
+            Self::FloatConstantTooLarge(s, FloatKind::Float) => {
+                write!(f, "floating constant `{s}` exceeds range of 'float'")
+            }
+            Self::FloatConstantTooLarge(s, FloatKind::Double) => {
+                write!(f, "floating constant `{s}` exceeds range of 'double'")
+            }
+            Self::FloatConstantTooLarge(s, FloatKind::LongDouble) => write!(
+                f,
+                "floating constant `{s}` exceeds range of 'long double', which is limited to the range of 'double'"
+            ),
+            Self::UnterminatedComment => write!(f, "unterminated block comment"),
+            Self::UnterminatedCharacter => write!(f, "missing terminating ' character"),
+            Self::UnterminatedString => write!(f, "missing terminating \" character"),
+            Self::EmptyCharacterConstant => write!(f, "empty character constant"),
+            Self::InvalidEscape(e) => write!(f, "invalid escape sequence `{e}`"),
+            Self::EscapeOutOfRange(e) => write!(f, "escape sequence `{e}` out of range"),
+        }
+    }
 }
 
-impl Display for Token {
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let out = match self {
-            Self::Identifier(s) => s.clone(),
+            Self::Identifier(s) => s.to_string(),
             Self::Constant(n) => n.to_string(),
+            Self::LongConstant(n) => format!("{n}l"),
+            Self::UnsignedConstant(n) => format!("{n}u"),
+            Self::UnsignedLongConstant(n) => format!("{n}ul"),
+            Self::FloatConstant(n, FloatKind::Float) => format!("{:?}f", *n as f32),
+            Self::FloatConstant(n, FloatKind::Double) => format!("{n:?}"),
+            Self::FloatConstant(n, FloatKind::LongDouble) => format!("{n:?}l"),
+            Self::CharLiteral(c) => format!("'{}'", escape(&[*c])),
+            Self::MultiCharLiteral(chars) => format!("'{}'", escape(chars)),
+            Self::StringLiteral(s) => format!("\"{}\"", escape(s)),
             Self::Tilde => "~".into(),
             Self::Minus => "-".into(),
             Self::Plus => "+".into(),
@@ -165,25 +1188,111 @@ impl Display for Token {
             Self::Slash => "/".into(),
             Self::Percent => "%".into(),
             Self::DoubleMinus => "--".into(),
+            Self::DoublePlus => "++".into(),
+            Self::Bang => "!".into(),
+            Self::Ampersand => "&".into(),
+            Self::DoubleAmpersand => "&&".into(),
+            Self::Pipe => "|".into(),
+            Self::DoublePipe => "||".into(),
+            Self::Caret => "^".into(),
+            Self::DoubleLess => "<<".into(),
+            Self::DoubleGreater => ">>".into(),
+            Self::Equal => "=".into(),
+            Self::DoubleEqual => "==".into(),
+            Self::BangEqual => "!=".into(),
+            Self::Less => "<".into(),
+            Self::LessEqual => "<=".into(),
+            Self::Greater => ">".into(),
+            Self::GreaterEqual => ">=".into(),
+            Self::PlusEqual => "+=".into(),
+            Self::MinusEqual => "-=".into(),
+            Self::StarEqual => "*=".into(),
+            Self::SlashEqual => "/=".into(),
+            Self::PercentEqual => "%=".into(),
+            Self::AmpersandEqual => "&=".into(),
+            Self::PipeEqual => "|=".into(),
+            Self::CaretEqual => "^=".into(),
+            Self::DoubleLessEqual => "<<=".into(),
+            Self::DoubleGreaterEqual => ">>=".into(),
+            Self::Question => "?".into(),
+            Self::Colon => ":".into(),
+            Self::Dot => ".".into(),
+            Self::Arrow => "->".into(),
+            Self::Auto => "auto".into(),
+            Self::Break => "break".into(),
+            Self::Case => "case".into(),
+            Self::Char => "char".into(),
+            Self::Const => "const".into(),
+            Self::Continue => "continue".into(),
+            Self::Default => "default".into(),
+            Self::Do => "do".into(),
+            Self::Double => "double".into(),
+            Self::Else => "else".into(),
+            Self::Enum => "enum".into(),
+            Self::Extern => "extern".into(),
+            Self::Float => "float".into(),
+            Self::For => "for".into(),
+            Self::Goto => "goto".into(),
+            Self::If => "if".into(),
+            Self::Inline => "inline".into(),
             Self::Int => "int".into(),
-            Self::Void => "void".into(),
+            Self::Long => "long".into(),
+            Self::Register => "register".into(),
+            Self::Restrict => "restrict".into(),
             Self::Return => "return".into(),
+            Self::Short => "short".into(),
+            Self::Signed => "signed".into(),
+            Self::Sizeof => "sizeof".into(),
+            Self::Static => "static".into(),
+            Self::Struct => "struct".into(),
+            Self::Switch => "switch".into(),
+            Self::Typedef => "typedef".into(),
+            Self::Union => "union".into(),
+            Self::Unsigned => "unsigned".into(),
+            Self::Void => "void".into(),
+            Self::Volatile => "volatile".into(),
+            Self::While => "while".into(),
+            Self::Alignas => "_Alignas".into(),
+            Self::Alignof => "_Alignof".into(),
+            Self::Atomic => "_Atomic".into(),
+            Self::Bool => "_Bool".into(),
+            Self::Complex => "_Complex".into(),
+            Self::Generic => "_Generic".into(),
+            Self::Imaginary => "_Imaginary".into(),
+            Self::Noreturn => "_Noreturn".into(),
+            Self::StaticAssert => "_Static_assert".into(),
+            Self::ThreadLocal => "_Thread_local".into(),
             Self::OpenParenthesis => "(".into(),
             Self::CloseParenthesis => ")".into(),
             Self::OpenBrace => "{".into(),
             Self::CloseBrace => "}".into(),
+            Self::OpenBracket => "[".into(),
+            Self::CloseBracket => "]".into(),
             Self::Semicolon => ";".into(),
-            Self::Invalid(t) => format!("#InvalidToken({t})"),
+            Self::Comma => ",".into(),
+            Self::Ellipsis => "...".into(),
+            Self::Hash => "#".into(),
+            Self::DoubleHash => "##".into(),
+            Self::Invalid(e) => format!("#InvalidToken({e})"),
         };
         write!(f, "{out}")
     }
 }
 
-pub fn identifier(s: impl Into<String>) -> Token {
+/// Escapes `bytes` for printing inside a character or string literal.
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|b| std::ascii::escape_default(*b))
+        .map(char::from)
+        .collect()
+}
+
+pub fn identifier<'src>(s: impl Into<Cow<'src, str>>) -> Token<'src> {
     Token::Identifier(s.into())
 }
 
-pub fn constant(i: i32) -> Token {
+pub fn constant(i: i32) -> Token<'static> {
     Token::Constant(i)
 }
 
@@ -217,140 +1326,140 @@ mod tests {
     #[test]
     fn lex_identifier_asdf() {
         let source = "asdf";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Identifier("asdf".into()), token);
     }
 
     #[test]
     fn lex_constant_1() {
         let source = "1";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Constant(1), token);
     }
 
     #[test]
     fn lex_constant_10() {
         let source = "10";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Constant(10), token);
     }
 
     #[test]
     fn lex_invalid_identifier() {
         let source = "1anInvalidIdentifier";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert!(matches!(token, Token::Invalid(_)));
     }
 
     #[test]
     fn lex_int_keyword() {
         let source = "int";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Int, token);
     }
 
     #[test]
     fn lex_void_keyword() {
         let source = "void";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Void, token);
     }
 
     #[test]
     fn lex_return_keyword() {
         let source = "return";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Return, token);
     }
 
     #[test]
     fn lex_open_parenthesis() {
         let source = "(";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::OpenParenthesis, token);
     }
 
     #[test]
     fn lex_close_parenthesis() {
         let source = ")";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::CloseParenthesis, token);
     }
 
     #[test]
     fn lex_open_brace() {
         let source = "{";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::OpenBrace, token);
     }
 
     #[test]
     fn lex_close_brace() {
         let source = "}";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::CloseBrace, token);
     }
 
     #[test]
     fn lex_semicolon() {
         let source = ";";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Semicolon, token);
     }
 
     #[test]
     fn lex_tilde() {
         let source = "~";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Tilde, token);
     }
 
     #[test]
     fn lex_minus() {
         let source = "-";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Minus, token);
     }
 
     #[test]
     fn lex_double_minus() {
         let source = "--";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::DoubleMinus, token);
     }
 
     #[test]
     fn lex_plus() {
         let source = "+";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Plus, token);
     }
 
     #[test]
     fn lex_star() {
         let source = "*";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Star, token);
     }
 
     #[test]
     fn lex_slash() {
         let source = "/";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Slash, token);
     }
 
     #[test]
     fn lex_percent() {
         let source = "%";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert_eq!(Token::Percent, token);
     }
 
     #[test]
     fn lex_simple_applcation() {
         let source = "int main(void){return 2;}";
-        let tokens = lex(source.into()).map(|t| t).collect::<Vec<_>>();
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
         assert_eq!(
             vec![
                 Token::Int,
@@ -371,14 +1480,14 @@ mod tests {
     #[test]
     fn lex_with_spaces() {
         let source = "int main    (   void)   {   return  0   ;   }";
-        let lexed_successfully = lex(source.into()).all(|r| !matches!(r, Token::Invalid(_)));
+        let lexed_successfully = lex(source).all(|r| !matches!(r.node, Token::Invalid(_)));
         assert!(lexed_successfully);
     }
 
     #[test]
     fn lex_double_negation() {
         let source = "int main (void) { return -(-2); }";
-        let tokens = lex(source.into()).collect::<Vec<_>>();
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
         assert_eq!(
             vec![
                 Token::Int,
@@ -403,14 +1512,809 @@ mod tests {
     #[test]
     fn lex_blub() {
         let source = "int main (void) { return return -((((10)))); }";
-        let lexed_successfully = lex(source.into()).all(|r| !matches!(r, Token::Invalid(_)));
+        let lexed_successfully = lex(source).all(|r| !matches!(r.node, Token::Invalid(_)));
         assert!(lexed_successfully);
     }
 
     #[test]
     fn lex_catch_invalid_identifier() {
         let source = "@";
-        let token = lex(source.into()).next().unwrap();
+        let token = lex(source).next().unwrap().node;
         assert!(matches!(token, Token::Invalid(_)));
     }
+
+    #[test]
+    fn lex_spans() {
+        let source = "int main\n  (void)";
+        let spans = lex(source).map(|t| t.span).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Span {
+                    start: 0,
+                    end: 3,
+                    line: 1,
+                    column: 1
+                },
+                Span {
+                    start: 4,
+                    end: 8,
+                    line: 1,
+                    column: 5
+                },
+                Span {
+                    start: 11,
+                    end: 12,
+                    line: 2,
+                    column: 3
+                },
+                Span {
+                    start: 12,
+                    end: 16,
+                    line: 2,
+                    column: 4
+                },
+                Span {
+                    start: 16,
+                    end: 17,
+                    line: 2,
+                    column: 8
+                },
+            ],
+            spans
+        );
+    }
+
+    #[test]
+    fn lex_skips_linemarkers() {
+        let source = "# 1 \"a.c\"\n# 1 \"/usr/include/stdio.h\" 1 3 4\nint\n#line 7\nmain";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(2, tokens.len());
+        assert_eq!(Token::Int, tokens[0].node);
+        assert_eq!(3, tokens[0].span.line);
+        assert_eq!(Token::Identifier("main".into()), tokens[1].node);
+        assert_eq!(5, tokens[1].span.line);
+    }
+
+    #[test]
+    fn lex_hash_not_at_line_start_is_not_a_linemarker() {
+        let source = "int # 1 \"a.c\"";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(Token::Hash, tokens[1]);
+    }
+
+    #[test]
+    fn parse_linemarker() {
+        assert_eq!(
+            Some(Linemarker {
+                line: 12,
+                file: Some("foo.c".into())
+            }),
+            Linemarker::parse("# 12 \"foo.c\" 2")
+        );
+        assert_eq!(
+            Some(Linemarker {
+                line: 3,
+                file: Some("dir\\a \"b\".h".into())
+            }),
+            Linemarker::parse("#line 3 \"dir\\\\a \\\"b\\\".h\"")
+        );
+        assert_eq!(
+            Some(Linemarker {
+                line: 4,
+                file: None
+            }),
+            Linemarker::parse("  #  4")
+        );
+        assert_eq!(None, Linemarker::parse("#define X 1"));
+        assert_eq!(None, Linemarker::parse("# 12 foo.c"));
+        assert_eq!(None, Linemarker::parse("#lineup 3"));
+    }
+
+    #[test]
+    fn lex_line_comment() {
+        let source = "int // return 2;\nmain";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(2, tokens.len());
+        assert_eq!(Token::Identifier("main".into()), tokens[1].node);
+        assert_eq!(2, tokens[1].span.line);
+    }
+
+    #[test]
+    fn lex_block_comment() {
+        let source = "int /* a\n * comment **/ main /**/ ;";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Int,
+                Token::Identifier("main".into()),
+                Token::Semicolon
+            ],
+            tokens.iter().map(|t| t.node.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!((2, 16), (tokens[1].span.line, tokens[1].span.column));
+    }
+
+    #[test]
+    fn lex_comment_markers_are_not_nested() {
+        let source = "/* // */ int /* /* */ void";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(vec![Token::Int, Token::Void], tokens);
+    }
+
+    #[test]
+    fn lex_slash_is_not_a_comment() {
+        let source = "4 / 2";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![Token::Constant(4), Token::Slash, Token::Constant(2)],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_unterminated_block_comment() {
+        let source = "int\n  /* return 2;\n";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(2, tokens.len());
+        assert_eq!(
+            Token::Invalid(LexError::UnterminatedComment),
+            tokens[1].node
+        );
+        assert_eq!((2, 3), (tokens[1].span.line, tokens[1].span.column));
+    }
+
+    #[test]
+    fn lex_line_continuation() {
+        let source = "re\\\ntu\\\r\nrn 1\\\n0";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(
+            vec![Token::Return, Token::Constant(10)],
+            tokens.iter().map(|t| t.node.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!((3, 4), (tokens[1].span.line, tokens[1].span.column));
+        assert_eq!(&source[tokens[1].span.start..tokens[1].span.end], "1\\\n0");
+    }
+
+    #[test]
+    fn lex_line_continuation_extends_line_comment() {
+        let source = "int // comment \\\n main\nvoid";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(vec![Token::Int, Token::Void], tokens);
+    }
+
+    #[test]
+    fn lex_line_continuation_at_start() {
+        let source = "\\\nint";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(Token::Int, tokens[0].node);
+        assert_eq!(2, tokens[0].span.start);
+    }
+
+    #[test]
+    fn lex_constant_bases() {
+        let source = "0 017 0x1F 0XfF 0b101 0B11";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Constant(0),
+                Token::Constant(15),
+                Token::Constant(31),
+                Token::Constant(255),
+                Token::Constant(5),
+                Token::Constant(3),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_constant_suffixes() {
+        let source = "1u 2U 3l 4L 5ul 6LU 7ll 8LL 9ull 10LLu 11uLL";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::UnsignedConstant(1),
+                Token::UnsignedConstant(2),
+                Token::LongConstant(3),
+                Token::LongConstant(4),
+                Token::UnsignedLongConstant(5),
+                Token::UnsignedLongConstant(6),
+                Token::LongConstant(7),
+                Token::LongConstant(8),
+                Token::UnsignedLongConstant(9),
+                Token::UnsignedLongConstant(10),
+                Token::UnsignedLongConstant(11),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_decimal_constant_widens_to_signed_types() {
+        let source = "2147483647 2147483648 9223372036854775807 4294967296u";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Constant(i32::MAX),
+                Token::LongConstant(2147483648),
+                Token::LongConstant(i64::MAX),
+                Token::UnsignedLongConstant(4294967296),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_hex_constant_widens_to_unsigned_types() {
+        let source = "0x7FFFFFFF 0x80000000 0x100000000 0x8000000000000000 0xFFFFFFFFl";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Constant(i32::MAX),
+                Token::UnsignedConstant(0x80000000),
+                Token::LongConstant(0x100000000),
+                Token::UnsignedLongConstant(0x8000000000000000),
+                Token::LongConstant(0xFFFFFFFF),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_constant_too_large() {
+        let source = "9223372036854775808 18446744073709551616u 0x10000000000000000";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Invalid(LexError::ConstantTooLarge("9223372036854775808".into())),
+                Token::Invalid(LexError::ConstantTooLarge("18446744073709551616u".into())),
+                Token::Invalid(LexError::ConstantTooLarge("0x10000000000000000".into())),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_malformed_constants() {
+        let source = "09 0x 0b2 1lL 1uu 12abc 1_0";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Invalid(LexError::InvalidConstant("09".into())),
+                Token::Invalid(LexError::InvalidConstant("0x".into())),
+                Token::Invalid(LexError::InvalidConstant("0b2".into())),
+                Token::Invalid(LexError::InvalidConstant("1lL".into())),
+                Token::Invalid(LexError::InvalidConstant("1uu".into())),
+                Token::Invalid(LexError::InvalidConstant("12abc".into())),
+                Token::Invalid(LexError::InvalidConstant("1_0".into())),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_char_literals() {
+        let source = r"'a' '\n' '\'' '\\' '\0' '\101' '\x41' '\xff' '?'";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::CharLiteral(b'a'),
+                Token::CharLiteral(b'\n'),
+                Token::CharLiteral(b'\''),
+                Token::CharLiteral(b'\\'),
+                Token::CharLiteral(0),
+                Token::CharLiteral(b'A'),
+                Token::CharLiteral(b'A'),
+                Token::CharLiteral(0xff),
+                Token::CharLiteral(b'?'),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_string_literals() {
+        let source = r#""hello\x41" "" "\"\?\a\b\f\r\t\v" "\1234" "é""#;
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![Token::StringLiteral(
+                b"helloA\"?\x07\x08\x0c\r\t\x0bS4\xc3\xa9"[..].into()
+            )],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_adjacent_string_literals() {
+        let source = "\"foo\" /* c */ \"bar\"\n  \"baz\" ;";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(2, tokens.len());
+        assert_eq!(
+            Token::StringLiteral(b"foobarbaz"[..].into()),
+            tokens[0].node
+        );
+        assert_eq!(0, tokens[0].span.start);
+        assert_eq!(27, tokens[0].span.end);
+        assert_eq!(Token::Semicolon, tokens[1].node);
+    }
+
+    #[test]
+    fn lex_string_followed_by_unterminated_comment() {
+        let source = "\"foo\" /* c";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::StringLiteral(b"foo"[..].into()),
+                Token::Invalid(LexError::UnterminatedComment)
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_malformed_literals() {
+        let source = "'' '\\q' \"\\x\" \"\\x100\" \"\\777\" 'a\n\"abc\n";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Invalid(LexError::EmptyCharacterConstant),
+                Token::Invalid(LexError::InvalidEscape("\\q".into())),
+                Token::Invalid(LexError::InvalidEscape("\\x".into())),
+                Token::Invalid(LexError::EscapeOutOfRange("\\x100".into())),
+                Token::Invalid(LexError::EscapeOutOfRange("\\777".into())),
+                Token::Invalid(LexError::UnterminatedCharacter),
+                Token::Invalid(LexError::UnterminatedString),
+            ],
+            tokens.iter().map(|t| t.node.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!((2, 1), (tokens[6].span.line, tokens[6].span.column));
+    }
+
+    #[test]
+    fn lex_multi_character_constants() {
+        let source = "'ab' '\\xff\\xfe' 'abcde'";
+        let (tokens, errors) = tokenize(source);
+        assert!(errors.is_empty());
+        let values = tokens
+            .iter()
+            .map(|t| match &t.node {
+                Token::MultiCharLiteral(chars) => multi_char_value(chars),
+                token => panic!("unexpected token {token:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, [0x6162, 0xfffe, 0x62636465]);
+        assert_eq!(multi_char_value(b"\xff\xfe\xfd\xfc"), -66052);
+        assert_eq!(
+            warnings(&tokens),
+            [
+                Spanned::new(LexWarning::MultiCharacterConstant, tokens[0].span),
+                Spanned::new(LexWarning::MultiCharacterConstant, tokens[1].span),
+                Spanned::new(LexWarning::CharacterConstantTooLong, tokens[2].span),
+            ]
+        );
+        assert_eq!("'ab'", tokens[0].node.to_string());
+    }
+
+    #[test]
+    fn display_literals() {
+        assert_eq!("'\\n'", Token::CharLiteral(b'\n').to_string());
+        assert_eq!(
+            "\"a\\\"b\\x01\"",
+            Token::StringLiteral(b"a\"b\x01"[..].into()).to_string()
+        );
+    }
+
+    #[test]
+    fn lex_punctuators() {
+        let source = "[ ] ( ) { } . -> ++ -- & * + - ~ ! / % << >> < > <= >= == != ^ | && || \
+                      ? : ; ... = *= /= %= += -= <<= >>= &= ^= |= , # ##";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::OpenBracket,
+                Token::CloseBracket,
+                Token::OpenParenthesis,
+                Token::CloseParenthesis,
+                Token::OpenBrace,
+                Token::CloseBrace,
+                Token::Dot,
+                Token::Arrow,
+                Token::DoublePlus,
+                Token::DoubleMinus,
+                Token::Ampersand,
+                Token::Star,
+                Token::Plus,
+                Token::Minus,
+                Token::Tilde,
+                Token::Bang,
+                Token::Slash,
+                Token::Percent,
+                Token::DoubleLess,
+                Token::DoubleGreater,
+                Token::Less,
+                Token::Greater,
+                Token::LessEqual,
+                Token::GreaterEqual,
+                Token::DoubleEqual,
+                Token::BangEqual,
+                Token::Caret,
+                Token::Pipe,
+                Token::DoubleAmpersand,
+                Token::DoublePipe,
+                Token::Question,
+                Token::Colon,
+                Token::Semicolon,
+                Token::Ellipsis,
+                Token::Equal,
+                Token::StarEqual,
+                Token::SlashEqual,
+                Token::PercentEqual,
+                Token::PlusEqual,
+                Token::MinusEqual,
+                Token::DoubleLessEqual,
+                Token::DoubleGreaterEqual,
+                Token::AmpersandEqual,
+                Token::CaretEqual,
+                Token::PipeEqual,
+                Token::Comma,
+                Token::Hash,
+                Token::DoubleHash,
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_digraphs() {
+        let source = "<: :> <% %> %: %:%:";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::OpenBracket,
+                Token::CloseBracket,
+                Token::OpenBrace,
+                Token::CloseBrace,
+                Token::Hash,
+                Token::DoubleHash,
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_maximal_munch() {
+        let source = "a+++++b x-->y c<<=d e..f %:%g ===";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                identifier("a"),
+                Token::DoublePlus,
+                Token::DoublePlus,
+                Token::Plus,
+                identifier("b"),
+                identifier("x"),
+                Token::DoubleMinus,
+                Token::Greater,
+                identifier("y"),
+                identifier("c"),
+                Token::DoubleLessEqual,
+                identifier("d"),
+                identifier("e"),
+                Token::Dot,
+                Token::Dot,
+                identifier("f"),
+                Token::Hash,
+                Token::Percent,
+                identifier("g"),
+                Token::DoubleEqual,
+                Token::Equal,
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_display_round_trips_punctuators() {
+        let source = "-> ... <<= >>= && || != ##";
+        let printed = lex(source)
+            .map(|t| t.node.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(source, printed);
+    }
+
+    #[test]
+    fn lex_keywords() {
+        let keywords = [
+            ("auto", Token::Auto),
+            ("break", Token::Break),
+            ("case", Token::Case),
+            ("char", Token::Char),
+            ("const", Token::Const),
+            ("continue", Token::Continue),
+            ("default", Token::Default),
+            ("do", Token::Do),
+            ("double", Token::Double),
+            ("else", Token::Else),
+            ("enum", Token::Enum),
+            ("extern", Token::Extern),
+            ("float", Token::Float),
+            ("for", Token::For),
+            ("goto", Token::Goto),
+            ("if", Token::If),
+            ("inline", Token::Inline),
+            ("int", Token::Int),
+            ("long", Token::Long),
+            ("register", Token::Register),
+            ("restrict", Token::Restrict),
+            ("return", Token::Return),
+            ("short", Token::Short),
+            ("signed", Token::Signed),
+            ("sizeof", Token::Sizeof),
+            ("static", Token::Static),
+            ("struct", Token::Struct),
+            ("switch", Token::Switch),
+            ("typedef", Token::Typedef),
+            ("union", Token::Union),
+            ("unsigned", Token::Unsigned),
+            ("void", Token::Void),
+            ("volatile", Token::Volatile),
+            ("while", Token::While),
+            ("_Alignas", Token::Alignas),
+            ("_Alignof", Token::Alignof),
+            ("_Atomic", Token::Atomic),
+            ("_Bool", Token::Bool),
+            ("_Complex", Token::Complex),
+            ("_Generic", Token::Generic),
+            ("_Imaginary", Token::Imaginary),
+            ("_Noreturn", Token::Noreturn),
+            ("_Static_assert", Token::StaticAssert),
+            ("_Thread_local", Token::ThreadLocal),
+        ];
+        for (source, keyword) in keywords {
+            let token = lex(source).next().unwrap().node;
+            assert_eq!(keyword, token);
+            assert_eq!(source, token.to_string());
+        }
+    }
+
+    #[test]
+    fn lex_keyword_prefixes_are_identifiers() {
+        let source = "integer _Bool_ ifx While _";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                identifier("integer"),
+                identifier("_Bool_"),
+                identifier("ifx"),
+                identifier("While"),
+                identifier("_"),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_identifiers_with_underscores() {
+        let source = "_foo __bar_1 a_b_c";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                identifier("_foo"),
+                identifier("__bar_1"),
+                identifier("a_b_c")
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_identifier_stops_at_non_ascii() {
+        let source = "aé";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                identifier("a"),
+                Token::Invalid(LexError::InvalidCharacter('é'))
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_decimal_floats() {
+        let source = "1.5 .5e-3 1. 1e10 2E+2 0.1f 0.1 1.5L 01.5 00e1";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::FloatConstant(1.5, FloatKind::Double),
+                Token::FloatConstant(0.0005, FloatKind::Double),
+                Token::FloatConstant(1.0, FloatKind::Double),
+                Token::FloatConstant(1e10, FloatKind::Double),
+                Token::FloatConstant(200.0, FloatKind::Double),
+                Token::FloatConstant(0.1f32 as f64, FloatKind::Float),
+                Token::FloatConstant(0.1, FloatKind::Double),
+                Token::FloatConstant(1.5, FloatKind::LongDouble),
+                Token::FloatConstant(1.5, FloatKind::Double),
+                Token::FloatConstant(0.0, FloatKind::Double),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_hex_floats() {
+        let source = "0x1.8p3 0X.8P1 0x10p-4 0xAp0f 0x1p-1074 0x1p-1075 0x1.8p-1074";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::FloatConstant(12.0, FloatKind::Double),
+                Token::FloatConstant(1.0, FloatKind::Double),
+                Token::FloatConstant(1.0, FloatKind::Double),
+                Token::FloatConstant(10.0, FloatKind::Float),
+                Token::FloatConstant(f64::from_bits(1), FloatKind::Double),
+                Token::FloatConstant(0.0, FloatKind::Double),
+                Token::FloatConstant(f64::from_bits(2), FloatKind::Double),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_hex_floats_round_to_nearest_even() {
+        let source = "0x1.fffffffffffff8p0 0x1.00000000000008p0 0x1.000000000000081p0 \
+                      0x1.000001p0f 0x1.000003p0f 0x1.0000010000000000000001p0f";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::FloatConstant(2.0, FloatKind::Double),
+                Token::FloatConstant(1.0, FloatKind::Double),
+                Token::FloatConstant(1.0 + f64::EPSILON, FloatKind::Double),
+                Token::FloatConstant(1.0, FloatKind::Float),
+                Token::FloatConstant((1.0 + 2.0 * f32::EPSILON) as f64, FloatKind::Float),
+                Token::FloatConstant((1.0 + f32::EPSILON) as f64, FloatKind::Float),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_malformed_floats() {
+        let source = "1.5x 1e 1e+ 0x1.8 1.2.3 1.5q 1e999 0x1p128f 1e400L";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Invalid(LexError::InvalidConstant("1.5x".into())),
+                Token::Invalid(LexError::InvalidConstant("1e".into())),
+                Token::Invalid(LexError::InvalidConstant("1e+".into())),
+                Token::Invalid(LexError::InvalidConstant("0x1.8".into())),
+                Token::Invalid(LexError::InvalidConstant("1.2.3".into())),
+                Token::Invalid(LexError::InvalidConstant("1.5q".into())),
+                Token::Invalid(LexError::FloatConstantTooLarge(
+                    "1e999".into(),
+                    FloatKind::Double
+                )),
+                Token::Invalid(LexError::FloatConstantTooLarge(
+                    "0x1p128f".into(),
+                    FloatKind::Float
+                )),
+                Token::Invalid(LexError::FloatConstantTooLarge(
+                    "1e400L".into(),
+                    FloatKind::LongDouble
+                )),
+            ],
+            tokens
+        );
+        assert_eq!(
+            "floating constant `1e999` exceeds range of 'double'",
+            LexError::FloatConstantTooLarge("1e999".into(), FloatKind::Double).to_string()
+        );
+    }
+
+    #[test]
+    fn lex_hex_floats_with_many_digits() {
+        let source = "0x8000000000000000p-1138 0x8000000000000001p-1138 0x123456789abcdef0123p0";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::FloatConstant(0.0, FloatKind::Double),
+                Token::FloatConstant(f64::from_bits(1), FloatKind::Double),
+                Token::FloatConstant(0x123456789abcdef0123u128 as f64, FloatKind::Double),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_hex_integer_with_e_is_not_a_float() {
+        let source = "0x1e 0x1e+1 1+2";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Token::Constant(30),
+                Token::Invalid(LexError::InvalidConstant("0x1e+1".into())),
+                Token::Constant(1),
+                Token::Plus,
+                Token::Constant(2),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn lex_member_access_is_not_a_float() {
+        let source = "a.b";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert_eq!(vec![identifier("a"), Token::Dot, identifier("b")], tokens);
+    }
+
+    #[test]
+    fn lex_borrows_text_from_source() {
+        let source = "foo \"bar\"; \"a\\n\"; b\\\naz";
+        let tokens = lex(source).map(|t| t.node).collect::<Vec<_>>();
+        assert!(matches!(tokens[0], Token::Identifier(Cow::Borrowed("foo"))));
+        assert!(matches!(
+            tokens[1],
+            Token::StringLiteral(Cow::Borrowed(b"bar"))
+        ));
+        assert!(matches!(&tokens[3], Token::StringLiteral(Cow::Owned(s)) if s == b"a\n"));
+        assert!(matches!(&tokens[5], Token::Identifier(Cow::Owned(s)) if s == "baz"));
+    }
+
+    #[test]
+    fn lex_non_ascii_characters() {
+        let source = "\u{e9}x \"\u{e9}\" \0";
+        let tokens = lex(source).collect::<Vec<_>>();
+        assert_eq!(
+            Token::Invalid(LexError::InvalidCharacter('\u{e9}')),
+            tokens[0].node
+        );
+        assert_eq!((1, 2), (tokens[1].span.line, tokens[1].span.column));
+        assert_eq!(
+            Token::StringLiteral("\u{e9}".as_bytes().into()),
+            tokens[2].node
+        );
+        assert_eq!((1, 8), (tokens[3].span.line, tokens[3].span.column));
+        assert_eq!(
+            Token::Invalid(LexError::InvalidCharacter('\0')),
+            tokens[3].node
+        );
+        assert_eq!(4, tokens.len());
+    }
+
+    #[test]
+    fn tokenize_reports_all_errors() {
+        let source = "int @x = 0x1g$;\nchar *s = \"abc\n`return 1;";
+        let (tokens, errors) = tokenize(source);
+        let errors = errors
+            .into_iter()
+            .map(|e| (e.node, e.span.line, e.span.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (LexError::InvalidCharacter('@'), 1, 5),
+                (LexError::InvalidConstant("0x1g".into()), 1, 10),
+                (LexError::InvalidCharacter('$'), 1, 14),
+                (LexError::UnterminatedString, 2, 11),
+                (LexError::InvalidCharacter('`'), 3, 1),
+            ],
+            errors
+        );
+        assert_eq!(
+            vec![
+                Token::Int,
+                identifier("x"),
+                Token::Equal,
+                Token::Semicolon,
+                Token::Char,
+                Token::Star,
+                identifier("s"),
+                Token::Equal,
+                Token::Return,
+                constant(1),
+                Token::Semicolon,
+            ],
+            tokens.into_iter().map(|t| t.node).collect::<Vec<_>>()
+        );
+    }
 }
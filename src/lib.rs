@@ -1,28 +1,49 @@
 pub mod assembly;
 pub mod ast;
 pub mod codegen;
+pub mod diagnostic;
 pub mod lexer;
 pub mod parser;
+pub mod preprocessor;
+pub mod semantic;
 pub mod tacky;
 
 pub use lexer::*;
 
-pub fn lex(source: &str) -> impl Iterator<Item = Token> {
+pub fn preprocess(
+    name: &str,
+    source: &str,
+    include_dirs: &[std::path::PathBuf],
+) -> Result<String, preprocessor::PreprocessError> {
+    preprocessor::preprocess(name, source, include_dirs)
+}
+
+pub fn lex(source: &str) -> impl Iterator<Item = Spanned<Token<'_>>> {
     lexer::lex(source)
 }
 
-pub fn parse(
-    token_stream: impl Iterator<Item = Token>,
-) -> Result<ast::Program, parser::ParseError> {
+pub fn tokenize(source: &str) -> (Vec<Spanned<Token<'_>>>, Vec<Spanned<LexError>>) {
+    lexer::tokenize(source)
+}
+
+pub fn parse<'src>(
+    token_stream: impl Iterator<Item = Spanned<Token<'src>>>,
+) -> Result<ast::Program, parser::ParseError<'src>> {
     parser::parse(token_stream)
 }
 
-pub fn tacky(program: ast::Program) -> tacky::Program {
-    tacky::tacky(program)
+pub fn analyze(
+    program: ast::Program,
+) -> Result<(ast::Program, semantic::SymbolTable), semantic::SemanticError> {
+    semantic::analyze(program)
+}
+
+pub fn tacky(program: ast::Program, symbols: &mut semantic::SymbolTable) -> tacky::Program {
+    tacky::tacky(program, symbols)
 }
 
-pub fn assembly(program: tacky::Program) -> assembly::Program {
-    assembly::assembly(program)
+pub fn assembly(program: tacky::Program, symbols: &semantic::SymbolTable) -> assembly::Program {
+    assembly::assembly(program, symbols)
 }
 
 pub fn codegen(program: assembly::Program) -> String {
@@ -1,69 +1,164 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use myc::diagnostic::{Diagnostic, SourceMap};
 use std::{
     fs::File,
-    io::{Read, Write},
-    process::{exit, Command, ExitStatus},
+    io::{IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{exit, Command},
 };
 
-fn run_preprocessor(input_file: &str, output_file: &str) -> std::io::Result<ExitStatus> {
-    Command::new("gcc")
-        .args(&["-E", "-P", input_file, "-o", output_file])
-        .spawn()
-        .expect("Failed to run preprocessor")
-        .wait()
+/// Preprocesses `input_file` with the built-in preprocessor.
+fn run_preprocessor(input_file: &str, args: &Cli) -> String {
+    let source = read_source(input_file, args);
+    let include_dirs = args
+        .include_dirs
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    myc::preprocess(input_file, &source, &include_dirs).unwrap_or_else(|e| {
+        report(
+            &[Diagnostic::from(&e)],
+            &SourceMap::new(&e.file, &e.text),
+            args,
+        )
+    })
 }
 
-fn run_compiler(input_file: &str, output_file: &str, args: &Cli) {
-    let mut file = File::open(input_file).unwrap();
+/// Preprocesses `input_file` into `output_file` with `gcc -E`.
+fn run_gcc_preprocessor(input_file: &str, output_file: &str, args: &Cli) -> String {
+    let status = Command::new("gcc")
+        .args(["-E", input_file, "-o", output_file])
+        .args(args.include_dirs.iter().map(|dir| format!("-I{dir}")))
+        .status();
+    match status {
+        Ok(status) if status.success() => read_source(output_file, args),
+        // gcc has reported the errors already.
+        Ok(_) => exit(1),
+        Err(e) => report(
+            &[Diagnostic::error(format!("could not run gcc: {e}"))],
+            &SourceMap::new(input_file, ""),
+            args,
+        ),
+    }
+}
+
+fn read_source(input_file: &str, args: &Cli) -> String {
     let mut source = String::new();
-    file.read_to_string(&mut source).unwrap();
+    if let Err(e) = File::open(input_file).and_then(|mut f| f.read_to_string(&mut source)) {
+        report(
+            &[Diagnostic::error(format!(
+                "could not read {input_file}: {e}"
+            ))],
+            &SourceMap::new(input_file, ""),
+            args,
+        );
+    }
+    source
+}
 
-    let mut token_stream = myc::lex(&source);
+/// Compiles `source` into `output_file` and returns whether any assembly was
+/// written.
+fn run_compiler(input_file: &str, source: &str, output_file: &str, args: &Cli) -> bool {
+    let mut warnings = vec![];
+    let result = compile(source, args, &mut warnings);
+    let source_map = SourceMap::new(input_file, source);
+    print_diagnostics(&warnings, &source_map, args);
+    let code = match result {
+        Ok(Some(code)) => code,
+        Ok(None) => return false,
+        Err(diagnostics) => report(&diagnostics, &source_map, args),
+    };
+
+    if let Err(e) = File::create(output_file).and_then(|mut f| writeln!(f, "{code}")) {
+        report(
+            &[Diagnostic::error(format!(
+                "could not write {output_file}: {e}"
+            ))],
+            &SourceMap::new(input_file, ""),
+            args,
+        );
+    }
+    true
+}
 
-    if args.lex {
-        let lexed_succesfully = token_stream.all(|t| !matches!(t, myc::lexer::Token::Invalid(_)));
-        if !lexed_succesfully {
-            eprintln!("Lex Error: Found an invalid token");
-            exit(1);
-        }
+/// Runs the compiler stages requested by `args` and returns the generated
+/// assembly, or `None` if compilation was asked to stop early. Warnings are
+/// added to `warnings` whether or not compilation succeeds.
+fn compile(
+    source: &str,
+    args: &Cli,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Option<String>, Vec<Diagnostic>> {
+    let (tokens, errors) = myc::tokenize(source);
+    warnings.extend(myc::lexer::warnings(&tokens).iter().map(Diagnostic::from));
+    if !errors.is_empty() {
+        return Err(errors.iter().map(Diagnostic::from).collect());
+    }
 
-        return;
+    if args.lex {
+        return Ok(None);
     }
 
-    let ast = myc::parse(token_stream);
+    let ast = myc::parse(tokens.into_iter()).map_err(|e| vec![Diagnostic::from(&e)])?;
 
-    if ast.is_err() {
-        eprintln!("Parse error: {}", ast.unwrap_err());
-        exit(1);
+    if args.parse {
+        return Ok(None);
     }
 
-    if args.parse {
-        return;
+    let (ast, mut symbols) = myc::analyze(ast).map_err(|e| vec![Diagnostic::from(&e)])?;
+
+    if args.validate {
+        return Ok(None);
     }
 
-    let tacky = myc::tacky(ast.expect("already checked previousley"));
+    let tacky = myc::tacky(ast, &mut symbols);
 
     if args.tacky {
-        return;
+        return Ok(None);
     }
 
-    let assembly = myc::assembly(tacky);
+    let assembly = myc::assembly(tacky, &symbols);
 
     if args.codegen {
-        return;
+        return Ok(None);
     }
 
-    let code = myc::codegen(assembly);
-    let mut file = std::fs::File::create(output_file).unwrap();
-    writeln!(&mut file, "{code}").unwrap();
+    Ok(Some(myc::codegen(assembly)))
 }
 
-fn run_linker(input_file: &str, output_file: &str) -> std::io::Result<ExitStatus> {
-    Command::new("gcc")
-        .args(&[input_file, "-o", output_file])
-        .spawn()
-        .expect("Failed to run linker")
-        .wait()
+/// Prints `diagnostics` to stderr and terminates the process.
+fn report(diagnostics: &[Diagnostic], source: &SourceMap, args: &Cli) -> ! {
+    print_diagnostics(diagnostics, source, args);
+    exit(1);
+}
+
+/// Prints `diagnostics` to stderr.
+fn print_diagnostics(diagnostics: &[Diagnostic], source: &SourceMap, args: &Cli) {
+    let color = match args.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => std::io::stderr().is_terminal(),
+    };
+    for diagnostic in diagnostics {
+        eprint!("{}", diagnostic.render(source, color));
+    }
+}
+
+/// Assembles and links `input_file` into `output_file` with gcc.
+fn run_linker(input_file: &str, output_file: &str, args: &Cli) {
+    let status = Command::new("gcc")
+        .args([input_file, "-o", output_file])
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        // gcc has reported the errors already.
+        Ok(_) => exit(1),
+        Err(e) => report(
+            &[Diagnostic::error(format!("could not run gcc: {e}"))],
+            &SourceMap::new(input_file, ""),
+            args,
+        ),
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -77,11 +172,33 @@ struct Cli {
     #[arg(long)]
     parse: bool,
 
+    #[arg(long)]
+    validate: bool,
+
     #[arg(long)]
     tacky: bool,
 
     #[arg(long)]
     codegen: bool,
+
+    /// Add a directory to search for included files
+    #[arg(short = 'I', value_name = "DIR")]
+    include_dirs: Vec<String>,
+
+    /// Preprocess with `gcc -E` instead of the built-in preprocessor
+    #[arg(long)]
+    gcc_preprocessor: bool,
+
+    /// When to use colors in diagnostics
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
 }
 
 fn main() {
@@ -90,9 +207,14 @@ fn main() {
     let c_file = cli.filename.clone();
     let i_file = format!("{}i", &c_file[..c_file.len() - 1]);
     let s_file = format!("{}s", &c_file[..c_file.len() - 1]);
-    let bin = format!("{}", &c_file[..c_file.len() - 2]);
-
-    run_preprocessor(&c_file, &i_file).expect("Error during preprocessing");
-    run_compiler(&i_file, &s_file, &cli);
-    run_linker(&s_file, &bin).expect("Error during linking");
+    let bin = c_file[..c_file.len() - 2].to_string();
+
+    let source = if cli.gcc_preprocessor {
+        run_gcc_preprocessor(&c_file, &i_file, &cli)
+    } else {
+        run_preprocessor(&c_file, &cli)
+    };
+    if run_compiler(&c_file, &source, &s_file, &cli) {
+        run_linker(&s_file, &bin, &cli);
+    }
 }
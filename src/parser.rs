@@ -3,77 +3,447 @@ use std::fmt::Display;
 use std::iter::Peekable;
 
 use crate::ast::{
-    BinaryOperation, Expression, FunctionDefinition, Program, Statement, UnaryOperation,
+    BinaryOperation, Block, BlockItem, Const, Declaration, Expression, ForInit,
+    FunctionDeclaration, Identifier, Parameter, Program, Statement, StorageClass, Type,
+    UnaryOperation, VariableDeclaration,
 };
-use crate::lexer;
+use crate::lexer::{self, Span, Spanned};
 use crate::Token;
 
-pub fn parse(token_stream: impl Iterator<Item = Token>) -> Result<Program> {
+pub fn parse<'src>(
+    token_stream: impl Iterator<Item = Spanned<Token<'src>>>,
+) -> Result<'src, Program> {
     let mut parser = Parser {
         token_stream: token_stream.peekable(),
+        last_span: Span::default(),
     };
 
-    let program = parser.parse_program();
-    if !parser.is_empty() {
-        return Err(ParseError::UnexpectedToken(
-            parser.bump().expect("should be checked by is_empty"),
-        ));
+    let program = parser.parse_program()?;
+    if let Some(t) = parser.bump() {
+        return Err(ParseError::unexpected(t.node, t.span));
     }
-    program
+    Ok(program)
 }
 
-struct Parser<T: Iterator<Item = Token>> {
+struct Parser<T: Iterator> {
     token_stream: Peekable<T>,
+    /// Span of the most recently consumed token, used to locate an unexpected EOF.
+    last_span: Span,
 }
 
-impl<T: Iterator<Item = Token>> Parser<T> {
-    fn parse_program(&mut self) -> Result<Program> {
-        Ok(Program {
-            function_definition: self.parse_function_definition()?,
-        })
+impl<'src, T: Iterator<Item = Spanned<Token<'src>>>> Parser<T> {
+    fn parse_program(&mut self) -> Result<'src, Program> {
+        let mut declarations = vec![];
+        while self.token_stream.peek().is_some() {
+            declarations.push(self.parse_declaration()?);
+        }
+        Ok(Program { declarations })
     }
 
-    fn parse_function_definition(&mut self) -> Result<FunctionDefinition> {
-        self.bump_if_equal(&lexer::Token::Int)?;
-        let t = self.bump().ok_or(ParseError::UnexpectedEOF)?;
-        let Token::Identifier(name) = t else {
-            return Err(ParseError::UnexpectedToken(t));
-        };
+    /// Parses the parameters and the body of the function `name`.
+    fn parse_function_declaration_rest(
+        &mut self,
+        name: Identifier,
+        return_type: Type,
+        storage_class: Option<StorageClass>,
+        span: Span,
+    ) -> Result<'src, FunctionDeclaration> {
         self.bump_if_equal(&lexer::Token::OpenParenthesis)?;
-        self.bump_if_equal(&lexer::Token::Void)?;
+        let params = self.parse_parameters()?;
         self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
-        self.bump_if_equal(&lexer::Token::OpenBrace)?;
+        let body = match self.next_is(&Token::Semicolon) {
+            true => {
+                self.bump();
+                None
+            }
+            false => Some(self.parse_block()?),
+        };
+        Ok(FunctionDeclaration {
+            name,
+            params,
+            return_type,
+            body,
+            storage_class,
+            span,
+        })
+    }
 
-        let body = self.parse_statement()?;
+    fn parse_parameters(&mut self) -> Result<'src, Vec<Parameter>> {
+        if self.next_is(&Token::CloseParenthesis) {
+            return Ok(vec![]);
+        }
+        if self.next_is(&Token::Void) {
+            self.bump();
+            return Ok(vec![]);
+        }
 
+        let mut params = vec![];
+        loop {
+            let ty = self.parse_type()?;
+            // Prototypes may leave out the parameter names.
+            let named = self
+                .token_stream
+                .peek()
+                .is_some_and(|t| matches!(t.node, Token::Identifier(_)));
+            let name = match named {
+                true => Some(self.parse_identifier()?),
+                false => None,
+            };
+            params.push(Parameter {
+                name,
+                ty,
+                span: self.last_span,
+            });
+            if !self.next_is(&Token::Comma) {
+                return Ok(params);
+            }
+            self.bump();
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<'src, Block> {
+        self.bump_if_equal(&lexer::Token::OpenBrace)?;
+        let mut items = vec![];
+        while self
+            .token_stream
+            .peek()
+            .is_some_and(|t| t.node != Token::CloseBrace)
+        {
+            items.push(self.parse_block_item()?);
+        }
         self.bump_if_equal(&lexer::Token::CloseBrace)?;
+        Ok(Block(items))
+    }
+
+    fn parse_block_item(&mut self) -> Result<'src, BlockItem> {
+        if self.next_is_specifier() {
+            return Ok(BlockItem::Declaration(self.parse_declaration()?));
+        }
+        Ok(BlockItem::Statement(self.parse_statement()?))
+    }
+
+    fn parse_declaration(&mut self) -> Result<'src, Declaration> {
+        let (ty, storage_class) = self.parse_specifiers()?;
+        let name = self.parse_identifier()?;
+        let span = self.last_span;
+        Ok(match self.next_is(&Token::OpenParenthesis) {
+            true => Declaration::Function(self.parse_function_declaration_rest(
+                name,
+                ty,
+                storage_class,
+                span,
+            )?),
+            false => Declaration::Variable(self.parse_variable_declaration_rest(
+                name,
+                ty,
+                storage_class,
+                span,
+            )?),
+        })
+    }
+
+    fn parse_variable_declaration(&mut self) -> Result<'src, VariableDeclaration> {
+        let (ty, storage_class) = self.parse_specifiers()?;
+        let name = self.parse_identifier()?;
+        let span = self.last_span;
+        self.parse_variable_declaration_rest(name, ty, storage_class, span)
+    }
+
+    /// Parses the initializer of the variable `name`.
+    fn parse_variable_declaration_rest(
+        &mut self,
+        name: Identifier,
+        ty: Type,
+        storage_class: Option<StorageClass>,
+        span: Span,
+    ) -> Result<'src, VariableDeclaration> {
+        let init = match self.next_is(&Token::Equal) {
+            true => {
+                self.bump();
+                Some(self.parse_expression(0)?)
+            }
+            false => None,
+        };
+        self.bump_if_equal(&lexer::Token::Semicolon)?;
+        Ok(VariableDeclaration {
+            name,
+            ty,
+            init,
+            storage_class,
+            span,
+        })
+    }
+
+    /// Parses the specifiers of a declaration and returns its type and
+    /// storage class.
+    fn parse_specifiers(&mut self) -> Result<'src, (Type, Option<StorageClass>)> {
+        if !self.next_is_specifier() {
+            let t = self.bump_or_eof()?;
+            return Err(ParseError::unexpected(t.node, t.span));
+        }
 
-        Ok(FunctionDefinition { name, body })
+        let mut types = TypeSpecifiers::default();
+        let mut storage_class = None;
+        while self.next_is_specifier() {
+            let t = self.bump().expect("already checked");
+            let class = match t.node {
+                Token::Static => StorageClass::Static,
+                Token::Extern => StorageClass::Extern,
+                node => {
+                    types.add(&node, t.span)?;
+                    continue;
+                }
+            };
+            if storage_class.is_some() {
+                return Err(ParseError::MultipleStorageClasses(t.span));
+            }
+            storage_class = Some(class);
+        }
+        Ok((types.resolve(self.last_span)?, storage_class))
+    }
+
+    /// Parses the type specifiers of a parameter or a cast.
+    fn parse_type(&mut self) -> Result<'src, Type> {
+        if !self.next_is_type_specifier() {
+            let t = self.bump_or_eof()?;
+            return Err(ParseError::unexpected(t.node, t.span));
+        }
+
+        let mut types = TypeSpecifiers::default();
+        while self.next_is_type_specifier() {
+            let t = self.bump().expect("already checked");
+            types.add(&t.node, t.span)?;
+        }
+        types.resolve(self.last_span)
+    }
+
+    fn next_is_specifier(&mut self) -> bool {
+        self.next_is_type_specifier()
+            || self.next_is(&Token::Static)
+            || self.next_is(&Token::Extern)
     }
 
-    fn parse_statement(&mut self) -> Result<Statement> {
-        self.bump_if_equal(&lexer::Token::Return)?;
+    fn next_is_type_specifier(&mut self) -> bool {
+        self.token_stream.peek().is_some_and(|t| {
+            matches!(
+                t.node,
+                Token::Int | Token::Long | Token::Unsigned | Token::Signed
+            )
+        })
+    }
 
-        let expression = self.parse_expression(0)?;
+    fn parse_statement(&mut self) -> Result<'src, Statement> {
+        if self.next_is(&Token::If) {
+            return self.parse_if_statement();
+        }
+        if self.next_is(&Token::OpenBrace) {
+            return Ok(Statement::Compound(self.parse_block()?));
+        }
+        if self.next_is(&Token::While) {
+            return self.parse_while_statement();
+        }
+        if self.next_is(&Token::For) {
+            return self.parse_for_statement();
+        }
+        if self.next_is(&Token::Switch) {
+            return self.parse_switch_statement();
+        }
+        if self.next_is(&Token::Case) {
+            let span = self.bump().expect("already peeked").span;
+            let value = self.parse_expression(0)?;
+            self.bump_if_equal(&lexer::Token::Colon)?;
+            return Ok(Statement::Case {
+                value,
+                body: Box::new(self.parse_statement()?),
+                label: None,
+                span,
+            });
+        }
+        if self.next_is(&Token::Default) {
+            let span = self.bump().expect("already peeked").span;
+            self.bump_if_equal(&lexer::Token::Colon)?;
+            return Ok(Statement::Default {
+                body: Box::new(self.parse_statement()?),
+                label: None,
+                span,
+            });
+        }
+
+        let statement = match self.token_stream.peek().map(|t| &t.node) {
+            Some(Token::Return) => {
+                self.bump();
+                Statement::Return(self.parse_expression(0)?)
+            }
+            Some(Token::Do) => self.parse_do_while_statement()?,
+            Some(Token::Break) => Statement::Break(None, self.bump().expect("already peeked").span),
+            Some(Token::Continue) => {
+                Statement::Continue(None, self.bump().expect("already peeked").span)
+            }
+            Some(Token::Goto) => {
+                self.bump();
+                let label = self.parse_identifier()?;
+                Statement::Goto(label, self.last_span)
+            }
+            Some(Token::Identifier(_)) => {
+                // An identifier starts either a labeled statement or an
+                // expression.
+                let t = self.bump().expect("already peeked");
+                if self.next_is(&Token::Colon) {
+                    self.bump();
+                    let Token::Identifier(label) = t.node else {
+                        unreachable!("already peeked");
+                    };
+                    let body = Box::new(self.parse_statement()?);
+                    return Ok(Statement::Labeled(label.into_owned(), body, t.span));
+                }
+                let left = self.parse_factor_from(t)?;
+                Statement::Expression(self.parse_binary_expression(left, 0)?)
+            }
+            Some(Token::Semicolon) => Statement::Null,
+            _ => Statement::Expression(self.parse_expression(0)?),
+        };
 
         self.bump_if_equal(&lexer::Token::Semicolon)?;
-        Ok(Statement::Return(expression))
+        Ok(statement)
+    }
+
+    fn parse_if_statement(&mut self) -> Result<'src, Statement> {
+        self.bump_if_equal(&lexer::Token::If)?;
+        self.bump_if_equal(&lexer::Token::OpenParenthesis)?;
+        let condition = self.parse_expression(0)?;
+        self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
+        let then = Box::new(self.parse_statement()?);
+        // An `else` belongs to the innermost `if` without one.
+        let otherwise = match self.next_is(&Token::Else) {
+            true => {
+                self.bump();
+                Some(Box::new(self.parse_statement()?))
+            }
+            false => None,
+        };
+        Ok(Statement::If(condition, then, otherwise))
+    }
+
+    fn parse_while_statement(&mut self) -> Result<'src, Statement> {
+        self.bump_if_equal(&lexer::Token::While)?;
+        self.bump_if_equal(&lexer::Token::OpenParenthesis)?;
+        let condition = self.parse_expression(0)?;
+        self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
+        let body = Box::new(self.parse_statement()?);
+        Ok(Statement::While {
+            condition,
+            body,
+            label: None,
+        })
     }
 
-    fn parse_expression(&mut self, min_precedence: u32) -> Result<Expression> {
-        let mut left = self.parse_factor()?;
+    /// Parses a `do` loop up to, but not including, its final `;`.
+    fn parse_do_while_statement(&mut self) -> Result<'src, Statement> {
+        self.bump_if_equal(&lexer::Token::Do)?;
+        let body = Box::new(self.parse_statement()?);
+        self.bump_if_equal(&lexer::Token::While)?;
+        self.bump_if_equal(&lexer::Token::OpenParenthesis)?;
+        let condition = self.parse_expression(0)?;
+        self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
+        Ok(Statement::DoWhile {
+            body,
+            condition,
+            label: None,
+        })
+    }
+
+    fn parse_for_statement(&mut self) -> Result<'src, Statement> {
+        self.bump_if_equal(&lexer::Token::For)?;
+        self.bump_if_equal(&lexer::Token::OpenParenthesis)?;
+        let init = match self.next_is_specifier() {
+            true => ForInit::Declaration(Box::new(self.parse_variable_declaration()?)),
+            false => ForInit::Expression(self.parse_optional_expression(&Token::Semicolon)?),
+        };
+        let condition = self.parse_optional_expression(&Token::Semicolon)?;
+        let post = self.parse_optional_expression(&Token::CloseParenthesis)?;
+        let body = Box::new(self.parse_statement()?);
+        Ok(Statement::For {
+            init,
+            condition,
+            post,
+            body,
+            label: None,
+        })
+    }
+
+    fn parse_switch_statement(&mut self) -> Result<'src, Statement> {
+        self.bump_if_equal(&lexer::Token::Switch)?;
+        self.bump_if_equal(&lexer::Token::OpenParenthesis)?;
+        let condition = self.parse_expression(0)?;
+        self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
+        let body = Box::new(self.parse_statement()?);
+        Ok(Statement::Switch {
+            condition,
+            body,
+            cases: vec![],
+            label: None,
+        })
+    }
+
+    /// Parses an expression unless the next token is `end`, then consumes `end`.
+    fn parse_optional_expression(&mut self, end: &Token<'src>) -> Result<'src, Option<Expression>> {
+        let expression = match self.next_is(end) {
+            true => None,
+            false => Some(self.parse_expression(0)?),
+        };
+        self.bump_if_equal(end)?;
+        Ok(expression)
+    }
+
+    fn parse_identifier(&mut self) -> Result<'src, Identifier> {
+        let t = self.bump_or_eof()?;
+        let Token::Identifier(name) = t.node else {
+            return Err(ParseError::unexpected(t.node, t.span));
+        };
+        Ok(name.into_owned())
+    }
+
+    fn parse_expression(&mut self, min_precedence: u32) -> Result<'src, Expression> {
+        let left = self.parse_factor()?;
+        self.parse_binary_expression(left, min_precedence)
+    }
+
+    /// Parses the rest of an expression whose first operand is `left`.
+    fn parse_binary_expression(
+        &mut self,
+        mut left: Expression,
+        min_precedence: u32,
+    ) -> Result<'src, Expression> {
         loop {
             let next_token = self.token_stream.peek();
-            if next_token.is_none() || next_token.is_some_and(|t| !is_binary_operator(t)) {
+            if next_token.is_none() || next_token.is_some_and(|t| !is_binary_operator(&t.node)) {
                 break;
             }
 
             let next_token = next_token.expect("already checked");
-            let prec = precedence(&next_token);
+            let prec = precedence(&next_token.node);
             if prec < min_precedence {
                 break;
             }
 
+            if next_token.node == Token::Equal {
+                let equal = self.bump().expect("already checked").span;
+                // Assignment is right-associative.
+                let right = Box::new(self.parse_expression(prec)?);
+                left = Expression::Assignment(Box::new(left), right, equal);
+                continue;
+            }
+
+            if next_token.node == Token::Question {
+                self.bump();
+                let then = Box::new(self.parse_expression(0)?);
+                self.bump_if_equal(&lexer::Token::Colon)?;
+                // The conditional operator is right-associative.
+                let otherwise = Box::new(self.parse_expression(prec)?);
+                left = Expression::Conditional(Box::new(left), then, otherwise);
+                continue;
+            }
+
             let binary_operator = self.parse_binary_operation()?;
             let right = Box::new(self.parse_expression(prec + 1)?);
             left = Expression::Binary(binary_operator, Box::new(left), right);
@@ -81,117 +451,302 @@ impl<T: Iterator<Item = Token>> Parser<T> {
         Ok(left)
     }
 
-    fn parse_factor(&mut self) -> Result<Expression> {
-        let t = self.bump().ok_or(ParseError::UnexpectedEOF)?;
-        let exp = match t {
-            Token::Constant(n) => Expression::Constant(n),
-            Token::Minus | Token::Tilde => self.parse_unary_operation(t)?,
+    fn parse_factor(&mut self) -> Result<'src, Expression> {
+        let t = self.bump_or_eof()?;
+        self.parse_factor_from(t)
+    }
+
+    /// Parses a factor starting with the already consumed token `t`.
+    fn parse_factor_from(&mut self, t: Spanned<Token<'src>>) -> Result<'src, Expression> {
+        let exp = match t.node {
+            Token::Constant(n) => Expression::Constant(Const::Int(n)),
+            Token::LongConstant(n) => Expression::Constant(Const::Long(n)),
+            Token::UnsignedConstant(n) => Expression::Constant(Const::UInt(n)),
+            Token::UnsignedLongConstant(n) => Expression::Constant(Const::ULong(n)),
+            Token::Identifier(name) if self.next_is(&Token::OpenParenthesis) => {
+                self.bump();
+                let args = self.parse_arguments()?;
+                Expression::FunctionCall(name.into_owned(), args, t.span)
+            }
+            Token::Identifier(name) => Expression::Var(name.into_owned(), t.span),
+            Token::Minus | Token::Tilde | Token::Bang => self.parse_unary_operation(t)?,
+            Token::OpenParenthesis if self.next_is_type_specifier() => {
+                let ty = self.parse_type()?;
+                self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
+                Expression::Cast(ty, Box::new(self.parse_factor()?))
+            }
             Token::OpenParenthesis => {
                 let exp = self.parse_expression(0)?;
                 self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
                 exp
             }
-            t => return Err(ParseError::UnexpectedToken(t.clone())),
+            node => return Err(ParseError::unexpected(node, t.span)),
         };
         Ok(exp)
     }
 
-    fn parse_unary_operation(&mut self, token: Token) -> Result<Expression> {
-        let op = match token {
+    /// Parses the arguments of a call up to and including the `)`.
+    fn parse_arguments(&mut self) -> Result<'src, Vec<Expression>> {
+        let mut args = vec![];
+        while !self.next_is(&Token::CloseParenthesis) {
+            if !args.is_empty() {
+                self.bump_if_equal(&lexer::Token::Comma)?;
+            }
+            args.push(self.parse_expression(0)?);
+        }
+        self.bump_if_equal(&lexer::Token::CloseParenthesis)?;
+        Ok(args)
+    }
+
+    fn parse_unary_operation(&mut self, token: Spanned<Token<'src>>) -> Result<'src, Expression> {
+        let op = match token.node {
             Token::Minus => UnaryOperation::Negate,
             Token::Tilde => UnaryOperation::Complement,
-            t @ _ => return Err(ParseError::UnexpectedToken(t)),
+            Token::Bang => UnaryOperation::Not,
+            t => return Err(ParseError::unexpected(t, token.span)),
         };
-        let exp = self.parse_expression(0)?;
+        let exp = self.parse_factor()?;
         Ok(Expression::Unary(op, Box::new(exp)))
     }
 
-    fn parse_binary_operation(&mut self) -> Result<BinaryOperation> {
-        let token = self.bump().ok_or(ParseError::UnexpectedEOF)?;
-        let op = match token {
+    fn parse_binary_operation(&mut self) -> Result<'src, BinaryOperation> {
+        let token = self.bump_or_eof()?;
+        let op = match token.node {
             Token::Plus => BinaryOperation::Add,
             Token::Minus => BinaryOperation::Subtract,
             Token::Star => BinaryOperation::Multiply,
             Token::Slash => BinaryOperation::Divide,
             Token::Percent => BinaryOperation::Remainder,
-            _ => return Err(ParseError::UnexpectedToken(token)),
+            Token::Ampersand => BinaryOperation::BitwiseAnd,
+            Token::Pipe => BinaryOperation::BitwiseOr,
+            Token::Caret => BinaryOperation::BitwiseXor,
+            Token::DoubleLess => BinaryOperation::ShiftLeft,
+            Token::DoubleGreater => BinaryOperation::ShiftRight,
+            Token::DoubleAmpersand => BinaryOperation::And,
+            Token::DoublePipe => BinaryOperation::Or,
+            Token::DoubleEqual => BinaryOperation::Equal,
+            Token::BangEqual => BinaryOperation::NotEqual,
+            Token::Less => BinaryOperation::LessThan,
+            Token::LessEqual => BinaryOperation::LessOrEqual,
+            Token::Greater => BinaryOperation::GreaterThan,
+            Token::GreaterEqual => BinaryOperation::GreaterOrEqual,
+            t => return Err(ParseError::unexpected(t, token.span)),
         };
         Ok(op)
     }
 
-    /// Checks if the `token_stream` is empty.
-    /// Does not consume the token_stream.
-    fn is_empty(&mut self) -> bool {
-        self.token_stream.peek().is_none()
+    /// Advances the token stream and returns the next Token if any.
+    fn bump(&mut self) -> Option<Spanned<Token<'src>>> {
+        let t = self.token_stream.next()?;
+        self.last_span = t.span;
+        Some(t)
     }
 
-    /// Advances the token stream and returns the next Token if any.
-    fn bump(&mut self) -> Option<Token> {
-        self.token_stream.next()
+    /// Advances the token stream and returns the next Token or an `UnexpectedEOF` error.
+    fn bump_or_eof(&mut self) -> Result<'src, Spanned<Token<'src>>> {
+        self.bump()
+            .ok_or(ParseError::UnexpectedEOF(self.eof_span()))
+    }
+
+    /// Returns true if the next token is equal to `token`.
+    fn next_is(&mut self, token: &Token<'src>) -> bool {
+        self.token_stream.peek().is_some_and(|t| &t.node == token)
     }
 
     /// Returns Ok(()) if the next token is equal to `expected_token`.
-    fn expect_token(&mut self, expected_token: &Token) -> Result<()> {
-        let p = self.token_stream.peek().ok_or(ParseError::UnexpectedEOF)?;
-        if p != expected_token {
-            return Err(ParseError::UnexpectedToken(p.clone()));
+    fn expect_token(&mut self, expected_token: &Token<'src>) -> Result<'src, ()> {
+        let eof = self.eof_span();
+        let p = self
+            .token_stream
+            .peek()
+            .ok_or(ParseError::UnexpectedEOF(eof))?;
+        if &p.node != expected_token {
+            return Err(ParseError::unexpected(p.node.clone(), p.span));
         }
         Ok(())
     }
 
     /// Advances the token stream and returns the next Token if the current is equal to the `expected_token`.
-    fn bump_if_equal(&mut self, expected_token: &Token) -> Result<Token> {
+    fn bump_if_equal(
+        &mut self,
+        expected_token: &Token<'src>,
+    ) -> Result<'src, Spanned<Token<'src>>> {
         self.expect_token(expected_token)?;
         Ok(self
             .bump()
             .expect("should be checked by `expect_token` and return early if None"))
     }
+
+    /// Returns an empty span located directly after the last consumed token.
+    fn eof_span(&self) -> Span {
+        Span {
+            start: self.last_span.end,
+            end: self.last_span.end,
+            line: self.last_span.line,
+            column: self.last_span.column + (self.last_span.end - self.last_span.start) as u32,
+        }
+    }
 }
 
-fn is_binary_operator(token: &Token) -> bool {
-    match token {
-        Token::Minus | Token::Plus | Token::Star | Token::Slash | Token::Percent => true,
-        _ => false,
+/// The type specifiers seen so far in a declaration or cast.
+#[derive(Default)]
+struct TypeSpecifiers {
+    int: bool,
+    long: bool,
+    signed: bool,
+    unsigned: bool,
+}
+
+impl TypeSpecifiers {
+    /// Records the specifier `token`, which may appear at most once.
+    fn add<'src>(&mut self, token: &Token<'src>, span: Span) -> Result<'src, ()> {
+        let seen = match token {
+            Token::Int => &mut self.int,
+            Token::Long => &mut self.long,
+            Token::Signed => &mut self.signed,
+            Token::Unsigned => &mut self.unsigned,
+            _ => unreachable!("not a type specifier"),
+        };
+        if *seen {
+            return Err(ParseError::InvalidType(span));
+        }
+        *seen = true;
+        Ok(())
     }
+
+    /// Returns the type named by the specifiers, reporting an invalid
+    /// combination at `span`.
+    fn resolve<'src>(&self, span: Span) -> Result<'src, Type> {
+        let any = self.int || self.long || self.signed || self.unsigned;
+        if !any || (self.signed && self.unsigned) {
+            return Err(ParseError::InvalidType(span));
+        }
+        Ok(match (self.long, self.unsigned) {
+            (false, false) => Type::Int,
+            (true, false) => Type::Long,
+            (false, true) => Type::UInt,
+            (true, true) => Type::ULong,
+        })
+    }
+}
+
+fn is_binary_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Minus
+            | Token::Plus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent
+            | Token::DoubleLess
+            | Token::DoubleGreater
+            | Token::Ampersand
+            | Token::Caret
+            | Token::Pipe
+            | Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual
+            | Token::DoubleEqual
+            | Token::BangEqual
+            | Token::DoubleAmpersand
+            | Token::DoublePipe
+            | Token::Question
+            | Token::Equal
+    )
 }
 
 fn precedence(token: &Token) -> u32 {
     match token {
         Token::Star | Token::Slash | Token::Percent => 50,
         Token::Minus | Token::Plus => 45,
+        Token::DoubleLess | Token::DoubleGreater => 40,
+        Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual => 35,
+        Token::DoubleEqual | Token::BangEqual => 30,
+        Token::Ampersand => 25,
+        Token::Caret => 20,
+        Token::Pipe => 15,
+        Token::DoubleAmpersand => 10,
+        Token::DoublePipe => 5,
+        Token::Question => 3,
+        Token::Equal => 1,
         _ => 0,
     }
 }
 
-pub type Result<T> = std::result::Result<T, ParseError>;
+pub type Result<'src, T> = std::result::Result<T, ParseError<'src>>;
 
 #[derive(Debug)]
-pub enum ParseError {
-    UnexpectedToken(Token),
-    UnexpectedEOF,
-    LexError,
+pub enum ParseError<'src> {
+    UnexpectedToken(Token<'src>, Span),
+    UnexpectedEOF(Span),
+    LexError(lexer::LexError, Span),
+    /// The type specifiers of a declaration or cast are missing, repeated or
+    /// contradictory, located at the offending or last specifier.
+    InvalidType(Span),
+    MultipleStorageClasses(Span),
 }
 
-impl Display for ParseError {
+impl<'src> ParseError<'src> {
+    /// Creates an error for `token`, reporting the lexer's error if the token
+    /// could not be lexed in the first place.
+    fn unexpected(token: Token<'src>, span: Span) -> Self {
+        match token {
+            Token::Invalid(e) => Self::LexError(e, span),
+            t => Self::UnexpectedToken(t, span),
+        }
+    }
+
+    /// Location in the source at which the error was detected.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnexpectedToken(_, span)
+            | Self::UnexpectedEOF(span)
+            | Self::LexError(_, span)
+            | Self::InvalidType(span)
+            | Self::MultipleStorageClasses(span) => *span,
+        }
+    }
+}
+
+impl Display for ParseError<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
-            Self::UnexpectedToken(t) => format!("found an unexpected token {t}"),
-            Self::UnexpectedEOF => "reached unexpected EOF".into(),
-            Self::LexError => "encountered an lexing error".into(),
+            Self::UnexpectedToken(t, _) => format!("found an unexpected token {t}"),
+            Self::UnexpectedEOF(_) => "reached unexpected EOF".into(),
+            Self::LexError(e, _) => e.to_string(),
+            Self::InvalidType(_) => "invalid combination of type specifiers".into(),
+            Self::MultipleStorageClasses(_) => {
+                "multiple storage classes in declaration specifiers".into()
+            }
         };
         write!(f, "{s}")
     }
 }
 
-impl Error for ParseError {}
+impl Error for ParseError<'_> {}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn spanned(tokens: Vec<Token>) -> impl Iterator<Item = Spanned<Token>> {
+        tokens.into_iter().map(|t| Spanned::new(t, Span::default()))
+    }
+
+    /// Items of the body of the first function in `program`.
+    fn body(program: &Program) -> &[BlockItem] {
+        match &program.declarations[0] {
+            Declaration::Function(FunctionDeclaration {
+                body: Some(body), ..
+            }) => &body.0,
+            declaration => panic!("not a function definition: {declaration:?}"),
+        }
+    }
+
     #[test]
     fn parse_simple_applcation() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -202,14 +757,19 @@ mod tests {
             Token::Constant(2),
             Token::Semicolon,
             Token::CloseBrace,
-        ]
-        .into_iter();
+        ]);
 
         let expected_ast = Program {
-            function_definition: FunctionDefinition {
+            declarations: vec![Declaration::Function(FunctionDeclaration {
                 name: "main".into(),
-                body: Statement::Return(Expression::Constant(2)),
-            },
+                params: vec![],
+                return_type: Type::Int,
+                body: Some(Block(vec![BlockItem::Statement(Statement::Return(
+                    Expression::Constant(Const::Int(2)),
+                ))])),
+                storage_class: None,
+                span: Span::default(),
+            })],
         };
 
         let ast = parse(token_stream).unwrap();
@@ -218,7 +778,7 @@ mod tests {
 
     #[test]
     fn parse_unary_operation() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -230,17 +790,22 @@ mod tests {
             Token::Constant(5),
             Token::Semicolon,
             Token::CloseBrace,
-        ]
-        .into_iter();
+        ]);
 
         let expected_ast = Program {
-            function_definition: FunctionDefinition {
+            declarations: vec![Declaration::Function(FunctionDeclaration {
                 name: "main".into(),
-                body: Statement::Return(Expression::Unary(
-                    UnaryOperation::Negate,
-                    Box::new(Expression::Constant(5)),
-                )),
-            },
+                params: vec![],
+                return_type: Type::Int,
+                body: Some(Block(vec![BlockItem::Statement(Statement::Return(
+                    Expression::Unary(
+                        UnaryOperation::Negate,
+                        Box::new(Expression::Constant(Const::Int(5))),
+                    ),
+                ))])),
+                storage_class: None,
+                span: Span::default(),
+            })],
         };
 
         let ast = parse(token_stream).unwrap();
@@ -249,7 +814,7 @@ mod tests {
 
     #[test]
     fn parse_binary_operation() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -264,22 +829,27 @@ mod tests {
             Token::Constant(3),
             Token::Semicolon,
             Token::CloseBrace,
-        ]
-        .into_iter();
+        ]);
 
         let expected_ast = Program {
-            function_definition: FunctionDefinition {
+            declarations: vec![Declaration::Function(FunctionDeclaration {
                 name: "main".into(),
-                body: Statement::Return(Expression::Binary(
-                    BinaryOperation::Subtract,
-                    Box::new(Expression::Binary(
+                params: vec![],
+                return_type: Type::Int,
+                body: Some(Block(vec![BlockItem::Statement(Statement::Return(
+                    Expression::Binary(
                         BinaryOperation::Subtract,
-                        Box::new(Expression::Constant(1)),
-                        Box::new(Expression::Constant(2)),
-                    )),
-                    Box::new(Expression::Constant(3)),
-                )),
-            },
+                        Box::new(Expression::Binary(
+                            BinaryOperation::Subtract,
+                            Box::new(Expression::Constant(Const::Int(1))),
+                            Box::new(Expression::Constant(Const::Int(2))),
+                        )),
+                        Box::new(Expression::Constant(Const::Int(3))),
+                    ),
+                ))])),
+                storage_class: None,
+                span: Span::default(),
+            })],
         };
 
         let ast = parse(token_stream).unwrap();
@@ -288,7 +858,7 @@ mod tests {
 
     #[test]
     fn parse_binary_precedence_operation() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -303,22 +873,27 @@ mod tests {
             Token::Constant(3),
             Token::Semicolon,
             Token::CloseBrace,
-        ]
-        .into_iter();
+        ]);
 
         let expected_ast = Program {
-            function_definition: FunctionDefinition {
+            declarations: vec![Declaration::Function(FunctionDeclaration {
                 name: "main".into(),
-                body: Statement::Return(Expression::Binary(
-                    BinaryOperation::Subtract,
-                    Box::new(Expression::Constant(1)),
-                    Box::new(Expression::Binary(
-                        BinaryOperation::Multiply,
-                        Box::new(Expression::Constant(2)),
-                        Box::new(Expression::Constant(3)),
-                    )),
-                )),
-            },
+                params: vec![],
+                return_type: Type::Int,
+                body: Some(Block(vec![BlockItem::Statement(Statement::Return(
+                    Expression::Binary(
+                        BinaryOperation::Subtract,
+                        Box::new(Expression::Constant(Const::Int(1))),
+                        Box::new(Expression::Binary(
+                            BinaryOperation::Multiply,
+                            Box::new(Expression::Constant(Const::Int(2))),
+                            Box::new(Expression::Constant(Const::Int(3))),
+                        )),
+                    ),
+                ))])),
+                storage_class: None,
+                span: Span::default(),
+            })],
         };
 
         let ast = parse(token_stream).unwrap();
@@ -327,7 +902,7 @@ mod tests {
 
     #[test]
     fn invalid_function_definition_missing_open_parenthesis() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::Void,
@@ -337,14 +912,13 @@ mod tests {
             Token::Constant(2),
             Token::Semicolon,
             Token::CloseBrace,
-        ]
-        .into_iter();
+        ]);
         parse(token_stream).unwrap_err();
     }
 
     #[test]
     fn invalid_function_definition_missing_close_parenthesis() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -354,14 +928,13 @@ mod tests {
             Token::Constant(2),
             Token::Semicolon,
             Token::CloseBrace,
-        ]
-        .into_iter();
+        ]);
         parse(token_stream).unwrap_err();
     }
 
     #[test]
     fn invalid_function_definition_missing_open_brace() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -371,14 +944,13 @@ mod tests {
             Token::Constant(2),
             Token::Semicolon,
             Token::CloseBrace,
-        ]
-        .into_iter();
+        ]);
         parse(token_stream).unwrap_err();
     }
 
     #[test]
     fn invalid_function_definition_missing_close_brace() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -388,14 +960,13 @@ mod tests {
             Token::Return,
             Token::Constant(2),
             Token::Semicolon,
-        ]
-        .into_iter();
+        ]);
         parse(token_stream).unwrap_err();
     }
 
     #[test]
     fn invalid_gargabe_at_the_end() {
-        let token_stream = vec![
+        let token_stream = spanned(vec![
             Token::Int,
             Token::Identifier("main".into()),
             Token::OpenParenthesis,
@@ -407,8 +978,522 @@ mod tests {
             Token::Semicolon,
             Token::CloseBrace,
             Token::Identifier("foo".into()),
-        ]
-        .into_iter();
+        ]);
+        parse(token_stream).unwrap_err();
+    }
+
+    #[test]
+    fn error_reports_location_of_unexpected_token() {
+        let token_stream = lexer::lex("int main(void) {\n    return 2)\n}");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken(Token::CloseParenthesis, _)
+        ));
+        assert_eq!((2, 13), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn error_reports_location_of_unexpected_eof() {
+        let token_stream = lexer::lex("int main(void) {\n    return 2;");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedEOF(_)));
+        assert_eq!((2, 14), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn error_reports_lex_error() {
+        let token_stream = lexer::lex("int main(void) {\n    return /* 2;\n}");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::LexError(lexer::LexError::UnterminatedComment, _)
+        ));
+        assert_eq!((2, 12), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn parse_declarations_and_assignments() {
+        let token_stream = lexer::lex("int main(void) { int a = 1; int b; b = a = -a + 2; ; a; }");
+        let at = |start: usize| Span {
+            start,
+            end: start + 1,
+            line: 1,
+            column: start as u32 + 1,
+        };
+        let var = |name: &str, start| Box::new(Expression::Var(name.into(), at(start)));
+        let expected_body = vec![
+            BlockItem::Declaration(Declaration::Variable(VariableDeclaration {
+                name: "a".into(),
+                ty: Type::Int,
+                init: Some(Expression::Constant(Const::Int(1))),
+                storage_class: None,
+                span: at(21),
+            })),
+            BlockItem::Declaration(Declaration::Variable(VariableDeclaration {
+                name: "b".into(),
+                ty: Type::Int,
+                init: None,
+                storage_class: None,
+                span: at(32),
+            })),
+            BlockItem::Statement(Statement::Expression(Expression::Assignment(
+                var("b", 35),
+                Box::new(Expression::Assignment(
+                    var("a", 39),
+                    Box::new(Expression::Binary(
+                        BinaryOperation::Add,
+                        Box::new(Expression::Unary(UnaryOperation::Negate, var("a", 44))),
+                        Box::new(Expression::Constant(Const::Int(2))),
+                    )),
+                    at(41),
+                )),
+                at(37),
+            ))),
+            BlockItem::Statement(Statement::Null),
+            BlockItem::Statement(Statement::Expression(*var("a", 53))),
+        ];
+
+        let ast = parse(token_stream).unwrap();
+        assert_eq!(body(&ast), expected_body);
+    }
+
+    #[test]
+    fn parse_assignment_to_non_variable() {
+        // Whether the left operand is an lvalue is checked by the semantic analysis.
+        let token_stream = lexer::lex("int main(void) { 2 = 3; }");
+        let ast = parse(token_stream).unwrap();
+        assert!(matches!(
+            body(&ast),
+            [BlockItem::Statement(Statement::Expression(
+                Expression::Assignment(..)
+            ))]
+        ));
+    }
+
+    #[test]
+    fn invalid_declaration_without_name() {
+        let token_stream = lexer::lex("int main(void) { int = 2; }");
         parse(token_stream).unwrap_err();
     }
+
+    #[test]
+    fn parse_relational_and_logical_precedence() {
+        let token_stream = lexer::lex("int main(void) { return !1 || 2 < 3 == 4 && 5 >= 6 != 7; }");
+        let constant = |n| Box::new(Expression::Constant(Const::Int(n)));
+        let binary = |op, left, right| Box::new(Expression::Binary(op, left, right));
+        let expected = binary(
+            BinaryOperation::Or,
+            Box::new(Expression::Unary(UnaryOperation::Not, constant(1))),
+            binary(
+                BinaryOperation::And,
+                binary(
+                    BinaryOperation::Equal,
+                    binary(BinaryOperation::LessThan, constant(2), constant(3)),
+                    constant(4),
+                ),
+                binary(
+                    BinaryOperation::NotEqual,
+                    binary(BinaryOperation::GreaterOrEqual, constant(5), constant(6)),
+                    constant(7),
+                ),
+            ),
+        );
+
+        let ast = parse(token_stream).unwrap();
+        assert_eq!(
+            body(&ast),
+            vec![BlockItem::Statement(Statement::Return(*expected))]
+        );
+    }
+
+    #[test]
+    fn parse_bitwise_precedence() {
+        let token_stream = lexer::lex("int main(void) { return 1 | 2 ^ 3 & 4 == 5 << 6 + 7; }");
+        let constant = |n| Box::new(Expression::Constant(Const::Int(n)));
+        let binary = |op, left, right| Box::new(Expression::Binary(op, left, right));
+        let expected = binary(
+            BinaryOperation::BitwiseOr,
+            constant(1),
+            binary(
+                BinaryOperation::BitwiseXor,
+                constant(2),
+                binary(
+                    BinaryOperation::BitwiseAnd,
+                    constant(3),
+                    binary(
+                        BinaryOperation::Equal,
+                        constant(4),
+                        binary(
+                            BinaryOperation::ShiftLeft,
+                            constant(5),
+                            binary(BinaryOperation::Add, constant(6), constant(7)),
+                        ),
+                    ),
+                ),
+            ),
+        );
+
+        let ast = parse(token_stream).unwrap();
+        assert_eq!(
+            body(&ast),
+            vec![BlockItem::Statement(Statement::Return(*expected))]
+        );
+    }
+
+    #[test]
+    fn parse_dangling_else() {
+        let token_stream = lexer::lex("int main(void) { if (1) if (2) return 3; else return 4; }");
+        let ret = |n| Box::new(Statement::Return(Expression::Constant(Const::Int(n))));
+        let expected = Statement::If(
+            Expression::Constant(Const::Int(1)),
+            Box::new(Statement::If(
+                Expression::Constant(Const::Int(2)),
+                ret(3),
+                Some(ret(4)),
+            )),
+            None,
+        );
+
+        let ast = parse(token_stream).unwrap();
+        assert_eq!(body(&ast), vec![BlockItem::Statement(expected)]);
+    }
+
+    #[test]
+    fn parse_conditional_is_right_associative() {
+        let token_stream = lexer::lex("int main(void) { return 1 || 2 ? 3 : 4 ? 5 : 6; }");
+        let constant = |n| Box::new(Expression::Constant(Const::Int(n)));
+        let expected = Expression::Conditional(
+            Box::new(Expression::Binary(
+                BinaryOperation::Or,
+                constant(1),
+                constant(2),
+            )),
+            constant(3),
+            Box::new(Expression::Conditional(
+                constant(4),
+                constant(5),
+                constant(6),
+            )),
+        );
+
+        let ast = parse(token_stream).unwrap();
+        assert_eq!(
+            body(&ast),
+            vec![BlockItem::Statement(Statement::Return(expected))]
+        );
+    }
+
+    #[test]
+    fn conditional_without_colon() {
+        let token_stream = lexer::lex("int main(void) { return 1 ? 2; }");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken(Token::Semicolon, _)
+        ));
+    }
+
+    #[test]
+    fn parse_nested_blocks() {
+        let token_stream = lexer::lex("int main(void) { { int a; { } } if (1) { ; } }");
+        let ast = parse(token_stream).unwrap();
+        let [BlockItem::Statement(Statement::Compound(outer)), BlockItem::Statement(Statement::If(_, then, None))] =
+            body(&ast)
+        else {
+            panic!("unexpected body {:?}", ast.declarations);
+        };
+        assert!(matches!(
+            &outer.0[..],
+            [
+                BlockItem::Declaration(_),
+                BlockItem::Statement(Statement::Compound(Block(inner)))
+            ] if inner.is_empty()
+        ));
+        assert_eq!(
+            **then,
+            Statement::Compound(Block(vec![BlockItem::Statement(Statement::Null)]))
+        );
+    }
+
+    #[test]
+    fn unterminated_block() {
+        let token_stream = lexer::lex("int main(void) { { return 1; }");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedEOF(_)));
+    }
+
+    #[test]
+    fn parse_loops() {
+        let token_stream = lexer::lex(
+            "int main(void) { for (int i = 0; ; i = i + 1) continue; for (;;) ; do break; while (1); }",
+        );
+        let ast = parse(token_stream).unwrap();
+        let [BlockItem::Statement(Statement::For {
+            init: ForInit::Declaration(declaration),
+            condition: None,
+            post: Some(Expression::Assignment(..)),
+            body: first_body,
+            label: None,
+        }), BlockItem::Statement(Statement::For {
+            init: ForInit::Expression(None),
+            condition: None,
+            post: None,
+            body: second_body,
+            ..
+        }), BlockItem::Statement(Statement::DoWhile {
+            body: do_body,
+            condition: Expression::Constant(Const::Int(1)),
+            ..
+        })] = body(&ast)
+        else {
+            panic!("unexpected body {:?}", ast.declarations);
+        };
+        assert_eq!(declaration.name, "i");
+        assert!(matches!(**first_body, Statement::Continue(None, _)));
+        assert_eq!(**second_body, Statement::Null);
+        assert!(matches!(**do_body, Statement::Break(None, _)));
+    }
+
+    #[test]
+    fn parse_while() {
+        let token_stream = lexer::lex("int main(void) { while (1) ; }");
+        let ast = parse(token_stream).unwrap();
+        assert_eq!(
+            body(&ast),
+            vec![BlockItem::Statement(Statement::While {
+                condition: Expression::Constant(Const::Int(1)),
+                body: Box::new(Statement::Null),
+                label: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn do_while_requires_semicolon() {
+        let token_stream = lexer::lex("int main(void) { do ; while (1) }");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken(Token::CloseBrace, _)
+        ));
+    }
+
+    #[test]
+    fn parse_switch() {
+        let token_stream =
+            lexer::lex("int main(void) { switch (a) { case 1 ? 2 : 3: default: return 0; } }");
+        let ast = parse(token_stream).unwrap();
+        let [BlockItem::Statement(Statement::Switch {
+            condition: Expression::Var(..),
+            body,
+            cases,
+            label: None,
+        })] = body(&ast)
+        else {
+            panic!("unexpected body {:?}", ast.declarations);
+        };
+        assert!(cases.is_empty());
+        let Statement::Compound(Block(items)) = &**body else {
+            panic!("unexpected switch body {body:?}");
+        };
+        let [BlockItem::Statement(Statement::Case {
+            value: Expression::Conditional(..),
+            body,
+            ..
+        })] = &items[..]
+        else {
+            panic!("unexpected switch body {items:?}");
+        };
+        assert!(matches!(&**body, Statement::Default { body, .. }
+            if matches!(**body, Statement::Return(_))));
+    }
+
+    #[test]
+    fn parse_labels_and_goto() {
+        let token_stream = lexer::lex("int main(void) { a: b: goto a; a = a + 1; a; }");
+        let ast = parse(token_stream).unwrap();
+        let [BlockItem::Statement(Statement::Labeled(a, body, span)), BlockItem::Statement(Statement::Expression(Expression::Assignment(..))), BlockItem::Statement(Statement::Expression(Expression::Var(..)))] =
+            body(&ast)
+        else {
+            panic!("unexpected body {:?}", ast.declarations);
+        };
+        assert_eq!(a, "a");
+        assert_eq!(span.column, 18);
+        assert!(matches!(&**body, Statement::Labeled(b, goto, _)
+            if b == "b" && matches!(&**goto, Statement::Goto(l, _) if l == "a")));
+    }
+
+    #[test]
+    fn label_without_statement() {
+        let token_stream = lexer::lex("int main(void) { a: }");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken(Token::CloseBrace, _)
+        ));
+    }
+
+    #[test]
+    fn parse_functions_with_parameters_and_calls() {
+        let token_stream =
+            lexer::lex("int add(int a, int b);\nint add(int a, int b) { return a + b; }\nint main() { int twice(int x); return add(1, twice(2)); }");
+        let ast = parse(token_stream).unwrap();
+        let [Declaration::Function(declaration), Declaration::Function(definition), Declaration::Function(main)] =
+            &ast.declarations[..]
+        else {
+            panic!("unexpected declarations {:?}", ast.declarations);
+        };
+        assert_eq!(declaration.name, "add");
+        assert!(declaration.body.is_none());
+        let params = definition
+            .params
+            .iter()
+            .map(|p| p.name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(params, [Some("a"), Some("b")]);
+        assert!(definition.body.is_some());
+        assert_eq!((main.span.line, main.span.column), (3, 5));
+        assert!(main.params.is_empty());
+
+        let [BlockItem::Declaration(Declaration::Function(twice)), BlockItem::Statement(Statement::Return(Expression::FunctionCall(name, args, _)))] =
+            &main.body.as_ref().unwrap().0[..]
+        else {
+            panic!("unexpected body {:?}", main.body);
+        };
+        assert_eq!(twice.name, "twice");
+        assert_eq!(name, "add");
+        assert!(
+            matches!(&args[..], [Expression::Constant(Const::Int(1)), Expression::FunctionCall(name, args, _)]
+            if name == "twice" && args.len() == 1)
+        );
+    }
+
+    #[test]
+    fn parse_unnamed_parameters() {
+        let token_stream = lexer::lex("int putchar(int);\nlong f(int a, unsigned long);");
+        let ast = parse(token_stream).unwrap();
+        let [Declaration::Function(putchar), Declaration::Function(f)] = &ast.declarations[..]
+        else {
+            panic!("unexpected declarations {:?}", ast.declarations);
+        };
+        let [param] = &putchar.params[..] else {
+            panic!("unexpected parameters {:?}", putchar.params);
+        };
+        assert_eq!(param.name, None);
+        assert_eq!(param.ty, Type::Int);
+        assert_eq!((param.span.line, param.span.column), (1, 13));
+        let params = f
+            .params
+            .iter()
+            .map(|p| (p.name.as_deref(), &p.ty))
+            .collect::<Vec<_>>();
+        assert_eq!(params, [(Some("a"), &Type::Int), (None, &Type::ULong)]);
+    }
+
+    #[test]
+    fn call_arguments_are_comma_separated() {
+        let token_stream = lexer::lex("int main(void) { return f(1 2); }");
+        let err = parse(token_stream).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedToken(Token::Constant(2), _)
+        ));
+    }
+
+    #[test]
+    fn parse_storage_classes() {
+        let token_stream =
+            lexer::lex("static int a = 1;\nint extern b;\nint main(void) { static int c; extern int f(void); }");
+        let ast = parse(token_stream).unwrap();
+        let [Declaration::Variable(a), Declaration::Variable(b), Declaration::Function(main)] =
+            &ast.declarations[..]
+        else {
+            panic!("unexpected declarations {:?}", ast.declarations);
+        };
+        assert_eq!(a.storage_class, Some(StorageClass::Static));
+        assert_eq!(b.storage_class, Some(StorageClass::Extern));
+        let [BlockItem::Declaration(Declaration::Variable(c)), BlockItem::Declaration(Declaration::Function(f))] =
+            &main.body.as_ref().unwrap().0[..]
+        else {
+            panic!("unexpected body {:?}", main.body);
+        };
+        assert_eq!(c.storage_class, Some(StorageClass::Static));
+        assert_eq!(f.storage_class, Some(StorageClass::Extern));
+    }
+
+    #[test]
+    fn parse_integer_types() {
+        let token_stream = lexer::lex(
+            "unsigned long f(long int a, unsigned b);\nsigned long int signed_long = 2l;\nint long unsigned c = 3ul + (long) 4u;",
+        );
+        let ast = parse(token_stream).unwrap();
+        let [Declaration::Function(f), Declaration::Variable(signed_long), Declaration::Variable(c)] =
+            &ast.declarations[..]
+        else {
+            panic!("unexpected declarations {:?}", ast.declarations);
+        };
+        assert_eq!(f.return_type, Type::ULong);
+        let params = f.params.iter().map(|p| &p.ty).collect::<Vec<_>>();
+        assert_eq!(params, [&Type::Long, &Type::UInt]);
+        assert_eq!(signed_long.ty, Type::Long);
+        assert_eq!(signed_long.init, Some(Expression::Constant(Const::Long(2))));
+        assert_eq!(c.ty, Type::ULong);
+        assert_eq!(
+            c.init,
+            Some(Expression::Binary(
+                BinaryOperation::Add,
+                Box::new(Expression::Constant(Const::ULong(3))),
+                Box::new(Expression::Cast(
+                    Type::Long,
+                    Box::new(Expression::Constant(Const::UInt(4)))
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn cast_binds_tighter_than_binary_operators() {
+        let token_stream = lexer::lex("int main(void) { return (unsigned) -1 < 0; }");
+        let ast = parse(token_stream).unwrap();
+        let [Declaration::Function(main)] = &ast.declarations[..] else {
+            panic!("unexpected declarations {:?}", ast.declarations);
+        };
+        let [BlockItem::Statement(Statement::Return(expr))] = &main.body.as_ref().unwrap().0[..]
+        else {
+            panic!("unexpected body {:?}", main.body);
+        };
+        assert_eq!(
+            *expr,
+            Expression::Binary(
+                BinaryOperation::LessThan,
+                Box::new(Expression::Cast(
+                    Type::UInt,
+                    Box::new(Expression::Unary(
+                        UnaryOperation::Negate,
+                        Box::new(Expression::Constant(Const::Int(1)))
+                    ))
+                )),
+                Box::new(Expression::Constant(Const::Int(0))),
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_specifiers() {
+        let err = parse(lexer::lex("static extern int a;")).unwrap_err();
+        assert!(matches!(err, ParseError::MultipleStorageClasses(span) if span.column == 8));
+        let err = parse(lexer::lex("int static int a;")).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidType(span) if span.column == 12));
+        let err = parse(lexer::lex("int main(void) { static a; }")).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidType(span) if span.column == 18));
+        let err = parse(lexer::lex("signed unsigned a;")).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidType(span) if span.column == 8));
+        let err = parse(lexer::lex("long long a;")).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidType(span) if span.column == 6));
+        let err = parse(lexer::lex("int f(unsigned signed a);")).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidType(_)));
+        let err = parse(lexer::lex("int main(void) { return (static int) 1; }")).unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedToken(Token::Static, _)));
+    }
 }
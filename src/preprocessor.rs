@@ -0,0 +1,1590 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+use crate::lexer::{self, Span, Spanned, Token};
+
+/// Environment variable naming the multiarch triple whose include directory
+/// is searched, like `x86_64-linux-gnu`.
+const MULTIARCH_VAR: &str = "MYC_MULTIARCH";
+
+/// Definitions in effect before the first line of every translation unit.
+const PREDEFINED_MACROS: &str = "\
+#define __STDC__ 1
+#define __STDC_VERSION__ 201710L
+#define __STDC_HOSTED__ 1
+#define __x86_64__ 1
+#define __linux__ 1
+#define __LP64__ 1
+";
+
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+/// Line gaps up to this size are reproduced with empty lines instead of a
+/// linemarker, like gcc does.
+const MAX_EMPTY_LINES: u32 = 8;
+
+/// Preprocesses `source`, the contents of the file `name`.
+///
+/// `#include` searches the directory of the including file (for the quoted
+/// form only), then `include_dirs`, the system include directories and, as a
+/// last resort, the include directory of gcc. The output contains gcc-style linemarkers, so locations in it can be mapped
+/// back to the files they come from.
+pub fn preprocess(name: &str, source: &str, include_dirs: &[PathBuf]) -> Result<String> {
+    let mut preprocessor = Preprocessor {
+        include_dirs: include_dirs
+            .iter()
+            .cloned()
+            .chain(system_include_dirs())
+            .collect(),
+        macros: HashMap::new(),
+        included_once: HashSet::new(),
+        depth: 0,
+        output: Output {
+            text: String::new(),
+            line: 1,
+            last: None,
+            previous_end: None,
+        },
+    };
+    preprocessor.run(SourceFile::new("<built-in>", PREDEFINED_MACROS))?;
+    preprocessor.output.linemarker(1, name, None);
+    preprocessor.run(SourceFile::new(name, source))?;
+    preprocessor.output.text.push('\n');
+    Ok(preprocessor.output.text)
+}
+
+/// Directories searched for included files after the ones given with `-I`,
+/// in gcc's order. The multiarch triple is taken from `MYC_MULTIARCH` and
+/// defaults to the target myc was built for.
+fn system_include_dirs() -> [PathBuf; 3] {
+    let triple = std::env::var(MULTIARCH_VAR)
+        .unwrap_or_else(|_| format!("{}-{}-gnu", std::env::consts::ARCH, std::env::consts::OS));
+    [
+        PathBuf::from("/usr/local/include"),
+        Path::new("/usr/include").join(triple),
+        PathBuf::from("/usr/include"),
+    ]
+}
+
+/// The include directory of gcc, which holds freestanding headers like
+/// `stddef.h` and `stdarg.h`. It is only looked up once an included file
+/// isn't found anywhere else, and is `None` if gcc isn't installed.
+fn compiler_include_dir() -> Option<&'static Path> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let output = Command::new("gcc")
+            .arg("-print-file-name=include")
+            .output()
+            .ok()?;
+        // gcc prints the bare name when it doesn't know the directory.
+        let dir = PathBuf::from(String::from_utf8(output.stdout).ok()?.trim_end());
+        (output.status.success() && dir.is_absolute() && dir.is_dir()).then_some(dir)
+    })
+    .as_deref()
+}
+
+struct Preprocessor {
+    include_dirs: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    /// Files containing `#pragma once`.
+    included_once: HashSet<PathBuf>,
+    /// Number of `#include`s currently being processed.
+    depth: usize,
+    output: Output,
+}
+
+#[derive(Debug, PartialEq)]
+struct Macro {
+    /// Parameter names of a function-like macro. The variable arguments of a
+    /// variadic macro are the last parameter, `__VA_ARGS__`.
+    params: Option<Vec<String>>,
+    body: Vec<PpToken>,
+}
+
+impl Macro {
+    fn is_variadic(&self) -> bool {
+        self.params
+            .as_ref()
+            .is_some_and(|p| p.last().is_some_and(|p| p == "__VA_ARGS__"))
+    }
+
+    /// Whether `other` is the same definition, which may be repeated
+    /// (C17 6.10.3p2).
+    fn is_same(&self, other: &Macro) -> bool {
+        self.params == other.params
+            && self.body.len() == other.body.len()
+            && self
+                .body
+                .iter()
+                .zip(&other.body)
+                .enumerate()
+                .all(|(i, (a, b))| a.text == b.text && (i == 0 || a.space == b.space))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PpToken {
+    /// The spelling of the token, with line continuations removed and
+    /// digraphs replaced.
+    text: String,
+    /// Location in the file the token was read from, or of the macro
+    /// invocation it was expanded from.
+    span: Span,
+    /// Whether whitespace precedes the token.
+    space: bool,
+    /// Whether the token is the first on its line in the file.
+    line_start: bool,
+    /// The macros whose expansion produced the token, which must not be
+    /// expanded again (C17 6.10.3.4).
+    hidden: HashSet<String>,
+}
+
+impl PpToken {
+    fn is_identifier(&self) -> bool {
+        self.text
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    }
+
+    fn is_expanded(&self) -> bool {
+        !self.hidden.is_empty()
+    }
+}
+
+/// A file being preprocessed.
+struct SourceFile {
+    name: String,
+    text: Rc<str>,
+    /// File name as changed by `#line`.
+    presumed_name: String,
+    /// Difference between the line numbers set by `#line` and the real ones.
+    line_offset: i64,
+    conditionals: Vec<Conditional>,
+}
+
+impl SourceFile {
+    fn new(name: &str, text: &str) -> Self {
+        Self {
+            name: name.into(),
+            text: text.into(),
+            presumed_name: name.into(),
+            line_offset: 0,
+            conditionals: vec![],
+        }
+    }
+
+    fn presumed_line(&self, line: u32) -> u32 {
+        (line as i64 + self.line_offset).max(1) as u32
+    }
+
+    fn error(&self, kind: PreprocessErrorKind, span: Span) -> PreprocessError {
+        PreprocessError {
+            kind,
+            span,
+            file: self.name.clone(),
+            text: self.text.clone(),
+        }
+    }
+
+    /// Splits the file into tokens. The first token to be read is last.
+    fn tokenize(&self) -> Vec<PpToken> {
+        let mut previous_end = 0;
+        let mut tokens = lexer::lex_preprocessing(&self.text)
+            .map(|(token, line_start)| {
+                let space = token.span.start > previous_end;
+                previous_end = token.span.end;
+                PpToken {
+                    text: spelling(&self.text, &token),
+                    span: token.span,
+                    space,
+                    line_start,
+                    hidden: HashSet::new(),
+                }
+            })
+            .collect::<Vec<_>>();
+        tokens.reverse();
+        tokens
+    }
+}
+
+/// An `#if`, `#ifdef` or `#ifndef` whose `#endif` has not been reached yet.
+struct Conditional {
+    directive: String,
+    span: Span,
+    /// Whether one of its groups has been included.
+    taken: bool,
+    seen_else: bool,
+}
+
+impl Preprocessor {
+    fn run(&mut self, mut file: SourceFile) -> Result<()> {
+        let mut tokens = file.tokenize();
+        while let Some(token) = tokens.pop() {
+            if token.line_start && token.text == "#" {
+                self.directive(&mut file, &mut tokens, token)?;
+            } else if let Some(token) = self.expand(&file, token, &mut tokens)? {
+                self.output.token(&token, &file);
+            }
+        }
+        match file.conditionals.last() {
+            Some(c) => Err(file.error(
+                PreprocessErrorKind::UnterminatedConditional(c.directive.clone()),
+                c.span,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Expands `token` if it names a macro, pushing the expansion onto
+    /// `tokens` to be rescanned. Returns the token if it is not expanded.
+    fn expand(
+        &self,
+        file: &SourceFile,
+        token: PpToken,
+        tokens: &mut Vec<PpToken>,
+    ) -> Result<Option<PpToken>> {
+        if !token.is_identifier() || token.hidden.contains(&token.text) {
+            return Ok(Some(token));
+        }
+        let builtin = match token.text.as_str() {
+            "__FILE__" => Some(quote(&file.presumed_name)),
+            "__LINE__" => Some(file.presumed_line(token.span.line).to_string()),
+            _ => None,
+        };
+        if let Some(text) = builtin {
+            let mut hidden = token.hidden.clone();
+            hidden.insert(token.text.clone());
+            tokens.push(PpToken {
+                text,
+                line_start: false,
+                hidden,
+                ..token
+            });
+            return Ok(None);
+        }
+
+        let Some(definition) = self.macros.get(&token.text) else {
+            return Ok(Some(token));
+        };
+        let (expansion, mut hidden) = match &definition.params {
+            None => (
+                self.substitute(file, definition, &[])?,
+                token.hidden.clone(),
+            ),
+            Some(_) => {
+                if tokens.last().is_none_or(|t| t.text != "(") {
+                    return Ok(Some(token));
+                }
+                let (args, close) = self.arguments(file, &token, definition, tokens)?;
+                let hidden = token.hidden.intersection(&close.hidden).cloned().collect();
+                (self.substitute(file, definition, &args)?, hidden)
+            }
+        };
+        hidden.insert(token.text.clone());
+
+        for (i, mut t) in expansion.into_iter().enumerate().rev() {
+            t.hidden.extend(hidden.iter().cloned());
+            t.span = token.span;
+            t.line_start = false;
+            if i == 0 {
+                t.space = token.space;
+            }
+            tokens.push(t);
+        }
+        Ok(None)
+    }
+
+    /// Fully expands `tokens` on their own, as is done for macro arguments and
+    /// the expressions of `#if`.
+    fn expand_all(&self, file: &SourceFile, tokens: Vec<PpToken>) -> Result<Vec<PpToken>> {
+        let mut tokens = tokens.into_iter().rev().collect::<Vec<_>>();
+        let mut expanded = vec![];
+        while let Some(token) = tokens.pop() {
+            if let Some(token) = self.expand(file, token, &mut tokens)? {
+                expanded.push(token);
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Reads the arguments of an invocation of the function-like macro `name`
+    /// up to the closing parenthesis, which is returned as well.
+    fn arguments(
+        &self,
+        file: &SourceFile,
+        name: &PpToken,
+        definition: &Macro,
+        tokens: &mut Vec<PpToken>,
+    ) -> Result<(Vec<Vec<PpToken>>, PpToken)> {
+        let params = definition.params.as_deref().unwrap_or_default();
+        let variadic = definition.is_variadic();
+        tokens.pop();
+
+        let mut args = vec![vec![]];
+        let mut depth = 0;
+        let close = loop {
+            let Some(token) = tokens.pop() else {
+                return Err(file.error(
+                    PreprocessErrorKind::UnterminatedArguments(name.text.clone()),
+                    name.span,
+                ));
+            };
+            match token.text.as_str() {
+                ")" if depth == 0 => break token,
+                // The variable arguments include the commas between them.
+                "," if depth == 0 && !(variadic && args.len() == params.len()) => {
+                    args.push(vec![]);
+                    continue;
+                }
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            args.last_mut()
+                .expect("starts with one argument")
+                .push(token);
+        };
+
+        if params.is_empty() && args.len() == 1 && args[0].is_empty() {
+            args.clear();
+        }
+        if variadic && args.len() + 1 == params.len() {
+            args.push(vec![]);
+        }
+        if args.len() != params.len() {
+            return Err(file.error(
+                PreprocessErrorKind::ArgumentCount {
+                    name: name.text.clone(),
+                    expected: params.len(),
+                    found: args.len(),
+                },
+                name.span,
+            ));
+        }
+        Ok((args, close))
+    }
+
+    /// Replaces the parameters in the body of `definition` with `args` and
+    /// applies the `#` and `##` operators.
+    fn substitute(
+        &self,
+        file: &SourceFile,
+        definition: &Macro,
+        args: &[Vec<PpToken>],
+    ) -> Result<Vec<PpToken>> {
+        let params = definition.params.as_deref().unwrap_or_default();
+        let param = |t: &PpToken| params.iter().position(|p| *p == t.text);
+        let body = &definition.body;
+
+        // Empty arguments next to `##` leave placemarkers, tokens with an
+        // empty spelling, which are removed in the end (C17 6.10.3.3).
+        let mut expansion: Vec<PpToken> = vec![];
+        let mut i = 0;
+        while i < body.len() {
+            let token = &body[i];
+            let next = body.get(i + 1);
+            if definition.params.is_some() && token.text == "#" {
+                let arg = &args[next.and_then(param).expect("checked by `define`")];
+                expansion.push(PpToken {
+                    text: stringize(arg),
+                    ..token.clone()
+                });
+                i += 2;
+            } else if token.text == "##" {
+                let right = next.expect("checked by `define`");
+                let mut right = match param(right) {
+                    Some(p) => args[p].clone().into_iter(),
+                    None => vec![right.clone()].into_iter(),
+                };
+                if let Some(first) = right.next() {
+                    let pasted = match expansion.pop() {
+                        Some(left) => paste(file, left, first)?,
+                        None => first,
+                    };
+                    expansion.push(pasted);
+                }
+                expansion.extend(right);
+                i += 2;
+            } else if let Some(p) = param(token) {
+                // Operands of `##` are not macro-expanded.
+                let mut arg = if next.is_some_and(|t| t.text == "##") {
+                    args[p].clone()
+                } else {
+                    self.expand_all(file, args[p].clone())?
+                };
+                match arg.first_mut() {
+                    Some(first) => first.space = token.space,
+                    None => arg.push(PpToken {
+                        text: String::new(),
+                        ..token.clone()
+                    }),
+                }
+                expansion.extend(arg);
+                i += 1;
+            } else {
+                expansion.push(token.clone());
+                i += 1;
+            }
+        }
+        expansion.retain(|t| !t.text.is_empty());
+        Ok(expansion)
+    }
+
+    fn directive(
+        &mut self,
+        file: &mut SourceFile,
+        tokens: &mut Vec<PpToken>,
+        hash: PpToken,
+    ) -> Result<()> {
+        let line = rest_of_line(tokens);
+        // A `#` on its own is the null directive.
+        let Some((name, args)) = line.split_first() else {
+            return Ok(());
+        };
+        match name.text.as_str() {
+            "define" => self.define(file, name, args),
+            "undef" => {
+                let macro_name = macro_name(file, name, args)?;
+                self.macros.remove(macro_name);
+                Ok(())
+            }
+            "include" => self.include(file, name, args),
+            "if" => {
+                let taken = self.condition(file, name, args)?;
+                enter_conditional(file, tokens, name, taken);
+                Ok(())
+            }
+            "ifdef" | "ifndef" => {
+                let defined = self.is_defined(macro_name(file, name, args)?);
+                enter_conditional(file, tokens, name, defined == (name.text == "ifdef"));
+                Ok(())
+            }
+            "elif" => {
+                let conditional = current_conditional(file, name)?;
+                if conditional.taken {
+                    skip_group(tokens);
+                } else if self.condition(file, name, args)? {
+                    current_conditional(file, name)?.taken = true;
+                } else {
+                    skip_group(tokens);
+                }
+                Ok(())
+            }
+            "else" => {
+                let conditional = current_conditional(file, name)?;
+                conditional.seen_else = true;
+                if conditional.taken {
+                    skip_group(tokens);
+                }
+                conditional.taken = true;
+                Ok(())
+            }
+            "endif" => match file.conditionals.pop() {
+                Some(_) => Ok(()),
+                None => Err(file.error(
+                    PreprocessErrorKind::UnmatchedConditional(name.text.clone()),
+                    name.span,
+                )),
+            },
+            "error" => Err(file.error(PreprocessErrorKind::ErrorDirective(spell(args)), name.span)),
+            "pragma" => {
+                if args.first().is_some_and(|t| t.text == "once") {
+                    self.included_once.insert(canonical(Path::new(&file.name)));
+                }
+                // Other pragmas are ignored.
+                Ok(())
+            }
+            "line" => self.line(file, name, args),
+            // gcc's linemarkers `# 12 "file.c"` work like `#line`.
+            _ if name.text.starts_with(|c: char| c.is_ascii_digit()) => {
+                self.line(file, &hash, &line)
+            }
+            _ => Err(file.error(
+                PreprocessErrorKind::InvalidDirective(name.text.clone()),
+                name.span,
+            )),
+        }
+    }
+
+    fn define(&mut self, file: &SourceFile, directive: &PpToken, args: &[PpToken]) -> Result<()> {
+        let name = macro_name(file, directive, args)?;
+        let rest = &args[1..];
+        let (params, body) = match rest.first() {
+            Some(open) if open.text == "(" && !open.space => {
+                let (params, len) = parameters(file, directive, rest)?;
+                (Some(params), &rest[len..])
+            }
+            _ => (None, rest),
+        };
+
+        let body = body
+            .iter()
+            .map(|t| PpToken {
+                line_start: false,
+                ..t.clone()
+            })
+            .collect::<Vec<_>>();
+        if let Some(t) = [body.first(), body.last()]
+            .into_iter()
+            .flatten()
+            .find(|t| t.text == "##")
+        {
+            return Err(file.error(PreprocessErrorKind::PasteAtEdge, t.span));
+        }
+        if let Some(params) = &params {
+            for (i, t) in body.iter().enumerate() {
+                let next = body.get(i + 1);
+                if t.text == "#" && next.is_none_or(|n| !params.contains(&n.text)) {
+                    return Err(file.error(PreprocessErrorKind::StringizeWithoutParameter, t.span));
+                }
+            }
+        }
+
+        let definition = Macro { params, body };
+        if self
+            .macros
+            .get(name)
+            .is_some_and(|previous| !previous.is_same(&definition))
+        {
+            return Err(file.error(
+                PreprocessErrorKind::MacroRedefined(name.into()),
+                args[0].span,
+            ));
+        }
+        self.macros.insert(name.into(), definition);
+        Ok(())
+    }
+
+    fn include(&mut self, file: &SourceFile, directive: &PpToken, args: &[PpToken]) -> Result<()> {
+        let expanded;
+        let args = match args.first() {
+            Some(t) if t.text.starts_with('"') || t.text == "<" => args,
+            _ => {
+                expanded = self.expand_all(file, args.to_vec())?;
+                &expanded
+            }
+        };
+        let (name, quoted) = match args {
+            [t] if t.text.len() >= 2 && t.text.starts_with('"') && t.text.ends_with('"') => {
+                (t.text[1..t.text.len() - 1].to_string(), true)
+            }
+            [open, name @ .., close] if open.text == "<" && close.text == ">" => {
+                (spell(name), false)
+            }
+            _ => return Err(file.error(PreprocessErrorKind::InvalidInclude, directive.span)),
+        };
+
+        let including_dir = Path::new(&file.name).parent().unwrap_or(Path::new(""));
+        let path = quoted
+            .then(|| including_dir.join(&name))
+            .into_iter()
+            .chain(self.include_dirs.iter().map(|dir| dir.join(&name)))
+            .find(|path| path.is_file())
+            .or_else(|| {
+                compiler_include_dir()
+                    .map(|dir| dir.join(&name))
+                    .filter(|path| path.is_file())
+            })
+            .ok_or_else(|| {
+                file.error(
+                    PreprocessErrorKind::IncludeNotFound(name.clone()),
+                    directive.span,
+                )
+            })?;
+        if self.included_once.contains(&canonical(&path)) {
+            return Ok(());
+        }
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(file.error(PreprocessErrorKind::IncludeTooDeep, directive.span));
+        }
+        let text = std::fs::read_to_string(&path).map_err(|e| {
+            file.error(
+                PreprocessErrorKind::ReadFailed(name, e.to_string()),
+                directive.span,
+            )
+        })?;
+
+        let last_line = args.last().unwrap_or(directive).span.line;
+        let path = path.to_string_lossy();
+        self.output.linemarker(1, &path, Some(1));
+        self.depth += 1;
+        let result = self.run(SourceFile::new(&path, &text));
+        self.depth -= 1;
+        result?;
+        self.output.linemarker(
+            file.presumed_line(last_line) + 1,
+            &file.presumed_name,
+            Some(2),
+        );
+        Ok(())
+    }
+
+    /// Handles `#line 12 "file.c"`, making the next line line 12 of `file.c`.
+    fn line(&self, file: &mut SourceFile, directive: &PpToken, args: &[PpToken]) -> Result<()> {
+        let args = self.expand_all(file, args.to_vec())?;
+        let invalid = || file.error(PreprocessErrorKind::InvalidLineDirective, directive.span);
+        let (line, name) = match args.as_slice() {
+            [line, rest @ ..] => (line, rest.first()),
+            [] => return Err(invalid()),
+        };
+        let line = line
+            .text
+            .parse::<u32>()
+            .ok()
+            .filter(|_| line.text.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(invalid)?;
+        if let Some(name) = name {
+            let Some(Token::StringLiteral(name)) = lexer::lex(&name.text).next().map(|t| t.node)
+            else {
+                return Err(invalid());
+            };
+            file.presumed_name = String::from_utf8_lossy(&name).into_owned();
+        }
+        let last_line = args.last().unwrap_or(directive).span.line;
+        file.line_offset = line as i64 - (last_line as i64 + 1);
+        Ok(())
+    }
+
+    /// Evaluates the controlling expression of `#if` or `#elif`.
+    fn condition(&self, file: &SourceFile, directive: &PpToken, args: &[PpToken]) -> Result<bool> {
+        if args.is_empty() {
+            return Err(file.error(
+                PreprocessErrorKind::MissingExpression(directive.text.clone()),
+                directive.span,
+            ));
+        }
+
+        let mut replaced = vec![];
+        let mut iter = args.iter();
+        while let Some(token) = iter.next() {
+            if token.text != "defined" {
+                replaced.push(token.clone());
+                continue;
+            }
+            let missing_identifier =
+                || file.error(PreprocessErrorKind::DefinedWithoutIdentifier, token.span);
+            let mut name = iter.next();
+            let parenthesized = name.is_some_and(|t| t.text == "(");
+            if parenthesized {
+                name = iter.next();
+            }
+            let name = name
+                .filter(|t| t.is_identifier())
+                .ok_or_else(missing_identifier)?;
+            if parenthesized && iter.next().is_none_or(|t| t.text != ")") {
+                return Err(missing_identifier());
+            }
+            let value = if self.is_defined(&name.text) {
+                "1"
+            } else {
+                "0"
+            };
+            replaced.push(PpToken {
+                text: value.into(),
+                ..token.clone()
+            });
+        }
+
+        // Identifiers left after expansion, including keywords, are zero.
+        let tokens = self
+            .expand_all(file, replaced)?
+            .into_iter()
+            .map(|t| match t.is_identifier() {
+                true => PpToken {
+                    text: "0".into(),
+                    ..t
+                },
+                false => t,
+            })
+            .collect::<Vec<_>>();
+
+        let mut evaluator = Evaluator {
+            file,
+            tokens: &tokens,
+            position: 0,
+            end: directive.span,
+        };
+        let value = evaluator.conditional(true)?;
+        if let Some(t) = tokens.get(evaluator.position) {
+            return Err(file.error(
+                PreprocessErrorKind::InvalidExpressionToken(t.text.clone()),
+                t.span,
+            ));
+        }
+        Ok(value.bits != 0)
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name) || matches!(name, "__FILE__" | "__LINE__")
+    }
+}
+
+/// Removes the tokens up to the end of the line from `tokens`.
+fn rest_of_line(tokens: &mut Vec<PpToken>) -> Vec<PpToken> {
+    let mut line = vec![];
+    while let Some(token) = tokens.pop_if(|t| !t.line_start) {
+        line.push(token);
+    }
+    line
+}
+
+/// Skips tokens up to the `#elif`, `#else` or `#endif` ending the current
+/// group, leaving that directive to be read next.
+fn skip_group(tokens: &mut Vec<PpToken>) {
+    let mut depth = 0;
+    while let Some(token) = tokens.pop() {
+        if !(token.line_start && token.text == "#") {
+            continue;
+        }
+        let Some(name) = tokens.last().filter(|t| !t.line_start) else {
+            continue;
+        };
+        match name.text.as_str() {
+            "if" | "ifdef" | "ifndef" => depth += 1,
+            "elif" | "else" | "endif" if depth == 0 => {
+                tokens.push(token);
+                return;
+            }
+            "endif" => depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+fn enter_conditional(
+    file: &mut SourceFile,
+    tokens: &mut Vec<PpToken>,
+    directive: &PpToken,
+    taken: bool,
+) {
+    file.conditionals.push(Conditional {
+        directive: directive.text.clone(),
+        span: directive.span,
+        taken,
+        seen_else: false,
+    });
+    if !taken {
+        skip_group(tokens);
+    }
+}
+
+/// Returns the innermost conditional for `#elif` or `#else`.
+fn current_conditional<'a>(
+    file: &'a mut SourceFile,
+    directive: &PpToken,
+) -> Result<&'a mut Conditional> {
+    let error = |kind| Err(file.error(kind, directive.span));
+    match file.conditionals.last() {
+        None => error(PreprocessErrorKind::UnmatchedConditional(
+            directive.text.clone(),
+        )),
+        Some(c) if c.seen_else => error(PreprocessErrorKind::DirectiveAfterElse(
+            directive.text.clone(),
+        )),
+        Some(_) => Ok(file.conditionals.last_mut().expect("checked above")),
+    }
+}
+
+/// Returns the name of the macro a `#define`, `#undef`, `#ifdef` or `#ifndef`
+/// refers to.
+fn macro_name<'a>(file: &SourceFile, directive: &PpToken, args: &'a [PpToken]) -> Result<&'a str> {
+    match args.first() {
+        Some(t) if t.text == "defined" => {
+            Err(file.error(PreprocessErrorKind::DefinedAsMacroName, t.span))
+        }
+        Some(t) if t.is_identifier() => Ok(&t.text),
+        Some(t) => Err(file.error(PreprocessErrorKind::MissingMacroName, t.span)),
+        None => Err(file.error(PreprocessErrorKind::MissingMacroName, directive.span)),
+    }
+}
+
+/// Parses the parameter list at the start of `tokens` and returns the
+/// parameter names and the number of tokens it takes up.
+fn parameters(
+    file: &SourceFile,
+    directive: &PpToken,
+    tokens: &[PpToken],
+) -> Result<(Vec<String>, usize)> {
+    let invalid = |span| file.error(PreprocessErrorKind::InvalidParameterList, span);
+    let mut params = vec![];
+    let mut iter = tokens.iter().enumerate().skip(1);
+    let close = loop {
+        let (i, token) = iter.next().ok_or_else(|| invalid(directive.span))?;
+        if token.text == ")" && params.is_empty() {
+            break i;
+        }
+        let variadic = token.text == "...";
+        let param = match token.text.as_str() {
+            "..." => "__VA_ARGS__".to_string(),
+            "__VA_ARGS__" => return Err(invalid(token.span)),
+            _ if token.is_identifier() => token.text.clone(),
+            _ => return Err(invalid(token.span)),
+        };
+        if params.contains(&param) {
+            return Err(file.error(PreprocessErrorKind::DuplicateParameter(param), token.span));
+        }
+        params.push(param);
+        match iter.next() {
+            Some((i, t)) if t.text == ")" => break i,
+            Some((_, t)) if t.text == "," && !variadic => {}
+            Some((_, t)) => return Err(invalid(t.span)),
+            None => return Err(invalid(directive.span)),
+        }
+    };
+    Ok((params, close + 1))
+}
+
+/// Concatenates two tokens with `##`.
+fn paste(file: &SourceFile, left: PpToken, right: PpToken) -> Result<PpToken> {
+    if left.text.is_empty() {
+        return Ok(PpToken {
+            space: left.space,
+            ..right
+        });
+    }
+    if right.text.is_empty() {
+        return Ok(left);
+    }
+    let text = format!("{}{}", left.text, right.text);
+    let mut tokens = lexer::lex_preprocessing(&text);
+    match (tokens.next(), tokens.next()) {
+        (Some((token, _)), None) if token.span.start == 0 && token.span.end == text.len() => {
+            Ok(PpToken {
+                text: spelling(&text, &token),
+                ..left
+            })
+        }
+        _ => Err(file.error(
+            PreprocessErrorKind::InvalidPaste(left.text, right.text),
+            left.span,
+        )),
+    }
+}
+
+/// Applies the `#` operator to a macro argument.
+fn stringize(arg: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (i, token) in arg.iter().enumerate() {
+        if i > 0 && token.space {
+            text.push(' ');
+        }
+        text += &token.text;
+    }
+    if arg.iter().any(|t| t.text.starts_with(['"', '\''])) {
+        return quote(&text);
+    }
+    format!("\"{text}\"")
+}
+
+/// Writes `text` as a string literal.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Joins `tokens` as they were written, separated by single spaces where
+/// there was whitespace.
+fn spell(tokens: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && token.space {
+            text.push(' ');
+        }
+        text += &token.text;
+    }
+    text
+}
+
+/// Returns how `token` is written in `source`, with line continuations
+/// removed and digraphs replaced.
+fn spelling(source: &str, token: &Spanned<Token>) -> String {
+    let text = &source[token.span.start..token.span.end];
+    match token.node {
+        Token::Identifier(_)
+        | Token::Constant(_)
+        | Token::LongConstant(_)
+        | Token::UnsignedConstant(_)
+        | Token::UnsignedLongConstant(_)
+        | Token::FloatConstant(..)
+        | Token::CharLiteral(_)
+        | Token::MultiCharLiteral(_)
+        | Token::StringLiteral(_)
+        | Token::Invalid(_) => text.replace("\\\r\n", "").replace("\\\n", ""),
+        _ if text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+            text.replace("\\\r\n", "").replace("\\\n", "")
+        }
+        ref punctuator => punctuator.to_string(),
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Preprocessed text under construction.
+struct Output {
+    text: String,
+    /// Line number of the line being written in the file it comes from.
+    line: u32,
+    /// Last character written on the current line.
+    last: Option<char>,
+    /// End of the last token written, if it was written as it appears in
+    /// the source.
+    previous_end: Option<usize>,
+}
+
+impl Output {
+    fn linemarker(&mut self, line: u32, file: &str, flag: Option<u8>) {
+        if self.last.is_some() {
+            self.text.push('\n');
+        }
+        self.text += &format!("# {line} {}", quote(file));
+        if let Some(flag) = flag {
+            self.text += &format!(" {flag}");
+        }
+        self.text.push('\n');
+        self.line = line;
+        self.last = None;
+        self.previous_end = None;
+    }
+
+    /// Writes `token` to the line it belongs to, keeping the whitespace
+    /// around tokens from the source so diagnostics can quote their lines.
+    fn token(&mut self, token: &PpToken, file: &SourceFile) {
+        let line = file.presumed_line(token.span.line);
+        if line != self.line {
+            if line > self.line && line - self.line <= MAX_EMPTY_LINES {
+                for _ in self.line..line {
+                    self.text.push('\n');
+                }
+                self.line = line;
+                self.last = None;
+            } else {
+                self.linemarker(line, &file.presumed_name, None);
+            }
+        }
+
+        let first = token.text.chars().next().unwrap_or(' ');
+        match self.last {
+            None => {
+                let line_start = file.text[..token.span.start]
+                    .rfind('\n')
+                    .map_or(0, |i| i + 1);
+                let indent = &file.text[line_start..token.span.start];
+                if indent.bytes().all(|b| b == b' ' || b == b'\t') {
+                    self.text += indent;
+                } else {
+                    self.text += &" ".repeat(token.span.column as usize - 1);
+                }
+            }
+            Some(last) if token.space => match self.previous_end {
+                Some(end) if !token.is_expanded() => {
+                    let gap = &file.text[end..token.span.start];
+                    if gap.bytes().all(|b| b == b' ' || b == b'\t') {
+                        self.text += gap;
+                    } else {
+                        self.text.push(' ');
+                    }
+                }
+                _ if last != ' ' => self.text.push(' '),
+                _ => {}
+            },
+            Some(last) if would_merge(last, first) => self.text.push(' '),
+            Some(_) => {}
+        }
+        self.text += &token.text;
+        self.last = token.text.chars().last();
+        self.previous_end = (!token.is_expanded()).then_some(token.span.end);
+    }
+}
+
+/// Whether writing a token starting with `next` directly after one ending
+/// with `last` could lex differently.
+fn would_merge(last: char, next: char) -> bool {
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let punctuation = |c: char| "+-*/%<>=!&|^#.:".contains(c);
+    (word(last) && word(next)) || (punctuation(last) && punctuation(next))
+}
+
+/// Evaluates `#if` expressions in the types `intmax_t` and `uintmax_t`
+/// (C17 6.10.1p4).
+struct Evaluator<'a> {
+    file: &'a SourceFile,
+    tokens: &'a [PpToken],
+    position: usize,
+    /// Location reported if the expression ends early.
+    end: Span,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    bits: u64,
+    unsigned: bool,
+}
+
+impl Value {
+    fn signed(value: i64) -> Self {
+        Self {
+            bits: value as u64,
+            unsigned: false,
+        }
+    }
+}
+
+impl Evaluator<'_> {
+    /// Evaluates a conditional expression. Division by zero is only an error
+    /// if `live`, as operands that are not evaluated must not fail.
+    fn conditional(&mut self, live: bool) -> Result<Value> {
+        let condition = self.binary(1, live)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.conditional(live && condition.bits != 0)?;
+        self.expect(":")?;
+        let otherwise = self.conditional(live && condition.bits == 0)?;
+        let value = if condition.bits != 0 { then } else { otherwise };
+        Ok(Value {
+            unsigned: then.unsigned || otherwise.unsigned,
+            ..value
+        })
+    }
+
+    fn binary(&mut self, min_precedence: u32, live: bool) -> Result<Value> {
+        let mut left = self.unary(live)?;
+        while let Some(operator) = self.tokens.get(self.position) {
+            let precedence = binary_precedence(&operator.text);
+            if precedence == 0 || precedence < min_precedence {
+                break;
+            }
+            self.position += 1;
+            let right_live = match operator.text.as_str() {
+                "&&" => live && left.bits != 0,
+                "||" => live && left.bits == 0,
+                _ => live,
+            };
+            let right = self.binary(precedence + 1, right_live)?;
+            left = self.apply(operator, left, right, live)?;
+        }
+        Ok(left)
+    }
+
+    fn apply(&self, operator: &PpToken, left: Value, right: Value, live: bool) -> Result<Value> {
+        let unsigned = left.unsigned || right.unsigned;
+        let (l, r) = (left.bits, right.bits);
+        let (sl, sr) = (l as i64, r as i64);
+        let truth = |b: bool| Value::signed(b as i64);
+        let value = |bits: u64| Value { bits, unsigned };
+        let result = match operator.text.as_str() {
+            "*" => value(l.wrapping_mul(r)),
+            "/" | "%" if r == 0 => {
+                if live {
+                    return Err(self
+                        .file
+                        .error(PreprocessErrorKind::DivisionByZero, operator.span));
+                }
+                value(0)
+            }
+            "/" if unsigned => value(l / r),
+            "/" => value(sl.wrapping_div(sr) as u64),
+            "%" if unsigned => value(l % r),
+            "%" => value(sl.wrapping_rem(sr) as u64),
+            "+" => value(l.wrapping_add(r)),
+            "-" => value(l.wrapping_sub(r)),
+            "<<" => Value {
+                bits: l.wrapping_shl(r as u32),
+                ..left
+            },
+            ">>" if left.unsigned => Value {
+                bits: l.wrapping_shr(r as u32),
+                ..left
+            },
+            ">>" => Value::signed(sl.wrapping_shr(r as u32)),
+            "<" => truth(if unsigned { l < r } else { sl < sr }),
+            ">" => truth(if unsigned { l > r } else { sl > sr }),
+            "<=" => truth(if unsigned { l <= r } else { sl <= sr }),
+            ">=" => truth(if unsigned { l >= r } else { sl >= sr }),
+            "==" => truth(l == r),
+            "!=" => truth(l != r),
+            "&" => value(l & r),
+            "^" => value(l ^ r),
+            "|" => value(l | r),
+            "&&" => truth(l != 0 && r != 0),
+            "||" => truth(l != 0 || r != 0),
+            _ => unreachable!("not a binary operator"),
+        };
+        Ok(result)
+    }
+
+    fn unary(&mut self, live: bool) -> Result<Value> {
+        let Some(token) = self.tokens.get(self.position) else {
+            return Err(self
+                .file
+                .error(PreprocessErrorKind::UnexpectedEndOfExpression, self.end));
+        };
+        self.position += 1;
+        let value = match token.text.as_str() {
+            "+" => self.unary(live)?,
+            "-" => {
+                let operand = self.unary(live)?;
+                Value {
+                    bits: operand.bits.wrapping_neg(),
+                    ..operand
+                }
+            }
+            "~" => {
+                let operand = self.unary(live)?;
+                Value {
+                    bits: !operand.bits,
+                    ..operand
+                }
+            }
+            "!" => Value::signed((self.unary(live)?.bits == 0) as i64),
+            "(" => {
+                let value = self.conditional(live)?;
+                self.expect(")")?;
+                value
+            }
+            _ => self.constant(token)?,
+        };
+        Ok(value)
+    }
+
+    fn constant(&self, token: &PpToken) -> Result<Value> {
+        let value = match lexer::lex(&token.text).next().map(|t| t.node) {
+            Some(Token::Constant(n)) => n as u64,
+            Some(Token::LongConstant(n)) => n as u64,
+            Some(Token::UnsignedConstant(n)) => n as u64,
+            Some(Token::UnsignedLongConstant(n)) => n,
+            // Plain `char` is signed.
+            Some(Token::CharLiteral(c)) => return Ok(Value::signed(c as i8 as i64)),
+            Some(Token::MultiCharLiteral(chars)) => {
+                return Ok(Value::signed(lexer::multi_char_value(&chars) as i64));
+            }
+            _ => {
+                return Err(self.file.error(
+                    PreprocessErrorKind::InvalidExpressionToken(token.text.clone()),
+                    token.span,
+                ))
+            }
+        };
+        // All integers are widened to `intmax_t`, so only a suffix or a value
+        // out of its range makes them unsigned.
+        let suffix = token
+            .text
+            .trim_start_matches(|c: char| c.is_ascii_hexdigit() || c == 'x');
+        Ok(Value {
+            bits: value,
+            unsigned: suffix.contains(['u', 'U']) || value > i64::MAX as u64,
+        })
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let found = self
+            .tokens
+            .get(self.position)
+            .is_some_and(|t| t.text == text);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        if self.eat(text) {
+            return Ok(());
+        }
+        let (kind, span) = match self.tokens.get(self.position) {
+            Some(t) => (
+                PreprocessErrorKind::InvalidExpressionToken(t.text.clone()),
+                t.span,
+            ),
+            None => (PreprocessErrorKind::UnexpectedEndOfExpression, self.end),
+        };
+        Err(self.file.error(kind, span))
+    }
+}
+
+fn binary_precedence(operator: &str) -> u32 {
+    match operator {
+        "*" | "/" | "%" => 10,
+        "+" | "-" => 9,
+        "<<" | ">>" => 8,
+        "<" | ">" | "<=" | ">=" => 7,
+        "==" | "!=" => 6,
+        "&" => 5,
+        "^" => 4,
+        "|" => 3,
+        "&&" => 2,
+        "||" => 1,
+        _ => 0,
+    }
+}
+
+pub type Result<T> = std::result::Result<T, PreprocessError>;
+
+/// An error that stopped preprocessing.
+#[derive(Debug)]
+pub struct PreprocessError {
+    pub kind: PreprocessErrorKind,
+    pub span: Span,
+    /// The file the error was found in.
+    pub file: String,
+    /// Text of `file`, to quote in diagnostics.
+    pub text: Rc<str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreprocessErrorKind {
+    InvalidDirective(String),
+    ErrorDirective(String),
+    InvalidInclude,
+    IncludeNotFound(String),
+    IncludeTooDeep,
+    ReadFailed(String, String),
+    MissingMacroName,
+    DefinedAsMacroName,
+    InvalidParameterList,
+    DuplicateParameter(String),
+    MacroRedefined(String),
+    StringizeWithoutParameter,
+    PasteAtEdge,
+    InvalidPaste(String, String),
+    UnterminatedArguments(String),
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    UnterminatedConditional(String),
+    UnmatchedConditional(String),
+    DirectiveAfterElse(String),
+    InvalidLineDirective,
+    MissingExpression(String),
+    DefinedWithoutIdentifier,
+    InvalidExpressionToken(String),
+    UnexpectedEndOfExpression,
+    DivisionByZero,
+}
+
+impl Display for PreprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Error for PreprocessError {}
+
+impl Display for PreprocessErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidDirective(d) => write!(f, "invalid preprocessing directive `#{d}`"),
+            Self::ErrorDirective(message) => write!(f, "#error {message}"),
+            Self::InvalidInclude => write!(f, "`#include` expects \"FILENAME\" or <FILENAME>"),
+            Self::IncludeNotFound(name) => write!(f, "`{name}` not found"),
+            Self::IncludeTooDeep => write!(f, "`#include` nested too deeply"),
+            Self::ReadFailed(name, e) => write!(f, "could not read `{name}`: {e}"),
+            Self::MissingMacroName => write!(f, "macro names must be identifiers"),
+            Self::DefinedAsMacroName => write!(f, "`defined` cannot be used as a macro name"),
+            Self::InvalidParameterList => write!(f, "invalid macro parameter list"),
+            Self::DuplicateParameter(p) => write!(f, "duplicate macro parameter `{p}`"),
+            Self::MacroRedefined(name) => write!(f, "macro `{name}` redefined differently"),
+            Self::StringizeWithoutParameter => {
+                write!(f, "`#` is not followed by a macro parameter")
+            }
+            Self::PasteAtEdge => {
+                write!(f, "`##` cannot appear at either end of a macro expansion")
+            }
+            Self::InvalidPaste(left, right) => write!(
+                f,
+                "pasting `{left}` and `{right}` does not give a valid preprocessing token"
+            ),
+            Self::UnterminatedArguments(name) => {
+                write!(f, "unterminated argument list invoking macro `{name}`")
+            }
+            Self::ArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "macro `{name}` takes {expected} arguments, but {found} were given"
+            ),
+            Self::UnterminatedConditional(d) => write!(f, "unterminated `#{d}`"),
+            Self::UnmatchedConditional(d) => write!(f, "`#{d}` without `#if`"),
+            Self::DirectiveAfterElse(d) => write!(f, "`#{d}` after `#else`"),
+            Self::InvalidLineDirective => {
+                write!(f, "`#line` expects a line number and an optional file name")
+            }
+            Self::MissingExpression(d) => write!(f, "`#{d}` with no expression"),
+            Self::DefinedWithoutIdentifier => {
+                write!(f, "operator `defined` requires an identifier")
+            }
+            Self::InvalidExpressionToken(t) => {
+                write!(f, "token `{t}` is not valid in preprocessor expressions")
+            }
+            Self::UnexpectedEndOfExpression => {
+                write!(f, "unexpected end of preprocessor expression")
+            }
+            Self::DivisionByZero => write!(f, "division by zero in preprocessor expression"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Preprocesses `source` and returns the resulting tokens separated by
+    /// spaces.
+    fn tokens(source: &str) -> String {
+        let output = preprocess("test.c", source, &[]).unwrap();
+        lexer::lex(&output)
+            .map(|t| t.node.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn error(source: &str) -> PreprocessErrorKind {
+        preprocess("test.c", source, &[]).unwrap_err().kind
+    }
+
+    #[test]
+    fn object_like_macros() {
+        let source = "#define ONE 1\n#define TWO (ONE + ONE)\nint x = TWO;";
+        assert_eq!("int x = ( 1 + 1 ) ;", tokens(source));
+    }
+
+    #[test]
+    fn macros_are_not_expanded_recursively() {
+        let source = "#define foo foo + 1\n#define a b\n#define b a\nfoo; a; b;";
+        assert_eq!("foo + 1 ; a ; b ;", tokens(source));
+    }
+
+    #[test]
+    fn function_like_macros() {
+        let source = "#define max(a, b) ((a) > (b) ? (a) : (b))\n\
+                      #define id(x) x\n\
+                      max(f(1, 2), id(3)) id max id(;)";
+        assert_eq!(
+            "( ( f ( 1 , 2 ) ) > ( 3 ) ? ( f ( 1 , 2 ) ) : ( 3 ) ) id max ;",
+            tokens(source)
+        );
+    }
+
+    #[test]
+    fn macro_arguments_across_lines() {
+        let source = "#define add(a, b) a + b\nint x = add(1,\n  2);\nint y;";
+        let output = preprocess("test.c", source, &[]).unwrap();
+        assert_eq!("# 1 \"test.c\"\n\nint x = 1 + 2\n    ;\nint y;\n", output);
+    }
+
+    #[test]
+    fn stringize_and_paste() {
+        let source = "#define str(x) #x\n\
+                      #define cat(a, b) a ## b\n\
+                      str( a  \"b\\n\" 'c' ) cat(x, 1) cat(, y) cat(+, =) cat(1, .5) str()";
+        assert_eq!(r#""a \"b\\n\" \'c\'" x1 y += 1.5 """#, tokens(source));
+    }
+
+    #[test]
+    fn invalid_paste() {
+        let source = "#define cat(a, b) a ## b\ncat(+, /)";
+        assert_eq!(
+            PreprocessErrorKind::InvalidPaste("+".into(), "/".into()),
+            error(source)
+        );
+    }
+
+    #[test]
+    fn variadic_macros() {
+        let source = "#define call(f, ...) f(__VA_ARGS__)\ncall(g, 1, 2) call(h)";
+        assert_eq!("g ( 1 , 2 ) h ( )", tokens(source));
+    }
+
+    #[test]
+    fn argument_count_is_checked() {
+        let source = "#define f(a, b) a\nf(1)";
+        assert_eq!(
+            PreprocessErrorKind::ArgumentCount {
+                name: "f".into(),
+                expected: 2,
+                found: 1
+            },
+            error(source)
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = "#define A 2\n\
+                      #if A == 2 && defined(A) && !defined B\n\
+                      yes1\n\
+                      #else\n\
+                      no1\n\
+                      #endif\n\
+                      #ifdef B\n\
+                      no2\n\
+                      #elif (A << 2) > 7 ? 1 : 0\n\
+                      yes2\n\
+                      #elif 1\n\
+                      no3\n\
+                      #endif\n\
+                      #ifndef A\n\
+                      #if 1\n\
+                      no4\n\
+                      #else\n\
+                      no5\n\
+                      #endif\n\
+                      #else\n\
+                      yes3\n\
+                      #endif";
+        assert_eq!("yes1 yes2 yes3", tokens(source));
+    }
+
+    #[test]
+    fn conditional_expressions_use_intmax() {
+        let source = "#if 0xffffffff > -1 && -1 > 0u && 'a' == 97 && 'ab' == 0x6162 && (1 || 1 / 0)\nyes\n#endif";
+        assert_eq!("yes", tokens(source));
+        assert_eq!(
+            PreprocessErrorKind::DivisionByZero,
+            error("#if 1 / 0\n#endif")
+        );
+        assert_eq!(
+            PreprocessErrorKind::UnexpectedEndOfExpression,
+            error("#if (1\n#endif")
+        );
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        assert_eq!(
+            PreprocessErrorKind::UnterminatedConditional("ifdef".into()),
+            error("#ifdef A\n")
+        );
+        assert_eq!(
+            PreprocessErrorKind::UnmatchedConditional("else".into()),
+            error("#else\n")
+        );
+        assert_eq!(
+            PreprocessErrorKind::DirectiveAfterElse("elif".into()),
+            error("#if 1\n#else\n#elif 1\n#endif")
+        );
+    }
+
+    #[test]
+    fn undef_and_redefinition() {
+        assert_eq!("A", tokens("#define A 1\n#undef A\nA"));
+        assert_eq!("1", tokens("#define A 1\n#define A  1 \nA"));
+        assert_eq!(
+            PreprocessErrorKind::MacroRedefined("A".into()),
+            error("#define A 1\n#define A 2\n")
+        );
+    }
+
+    #[test]
+    fn error_directive() {
+        assert_eq!(
+            PreprocessErrorKind::ErrorDirective("not \"supported\"".into()),
+            error("#if 1\n#error not \"supported\"\n#endif")
+        );
+        assert_eq!("", tokens("#if 0\n#error no\n#endif"));
+    }
+
+    #[test]
+    fn file_and_line() {
+        let source = "__LINE__ __FILE__\n#line 10 \"other.c\"\n__LINE__ __FILE__";
+        assert_eq!(r#"1 "test.c" 10 "other.c""#, tokens(source));
+    }
+
+    #[test]
+    fn output_keeps_lines_and_indentation() {
+        let source = "int main(void) {\n#define X 1\n\n\t  return  X; // done\n}";
+        let output = preprocess("a.c", source, &[]).unwrap();
+        assert_eq!(
+            "# 1 \"a.c\"\nint main(void) {\n\n\n\t  return 1;\n}\n",
+            output
+        );
+    }
+
+    #[test]
+    fn expansions_are_not_glued_together() {
+        assert_eq!("- - 1", tokens("#define NEG -1\n-NEG"));
+    }
+
+    #[test]
+    fn include_with_search_paths() {
+        let dir = std::env::temp_dir().join(format!("myc-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sys")).unwrap();
+        std::fs::write(
+            dir.join("sys/once.h"),
+            "#pragma once\nint once;\n#define INCLUDED 1\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("local.h"), "#include <sys/once.h>\nint local;\n").unwrap();
+
+        let main = dir.join("main.c");
+        let source = "#include \"local.h\"\n#include <sys/once.h>\nint x = INCLUDED;\n";
+        let include_dirs = [dir.clone()];
+        let output = preprocess(&main.to_string_lossy(), source, &include_dirs).unwrap();
+        let tokens = lexer::lex(&output)
+            .map(|t| t.node.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["int", "once", ";", "int", "local", ";", "int", "x", "=", "1", ";"],
+            tokens
+        );
+        let once = dir.join("sys/once.h");
+        assert!(output.contains(&format!("# 1 \"{}\" 1\n", once.display())));
+        assert!(output.contains(&format!("# 2 \"{}\" 2\n", main.display())));
+
+        let missing = preprocess("main.c", "#include \"missing.h\"", &include_dirs);
+        assert_eq!(
+            PreprocessErrorKind::IncludeNotFound("missing.h".into()),
+            missing.unwrap_err().kind
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_from_system_directories() {
+        // `stddef.h` comes from the compiler's include directory.
+        let source = "#include <stddef.h>\nint x = offsetof(struct s, f);\n";
+        let output = preprocess("main.c", source, &[]).unwrap();
+        assert!(output.contains("__builtin_offsetof"), "{output}");
+
+        // `stdio.h` includes `bits/...` headers from the multiarch directory.
+        let output = preprocess("main.c", "#include <stdio.h>\n", &[]).unwrap();
+        assert!(output.contains("putchar"));
+    }
+
+    #[test]
+    fn invalid_directives() {
+        assert_eq!(
+            PreprocessErrorKind::InvalidDirective("foo".into()),
+            error("#foo\n")
+        );
+        assert_eq!(
+            PreprocessErrorKind::StringizeWithoutParameter,
+            error("#define f(x) #y\n")
+        );
+        assert_eq!(
+            PreprocessErrorKind::DefinedAsMacroName,
+            error("#define defined\n")
+        );
+        assert_eq!(
+            "",
+            tokens("#\n#pragma foo\n# 5 \"x.c\"\n#if 0\n#foo\n#endif")
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::ast::{Const, Identifier, Program};
+use crate::lexer::Span;
+
+mod constant;
+mod labels;
+mod loops;
+mod resolve;
+mod typecheck;
+
+pub use typecheck::{IdentifierAttributes, InitialValue, Symbol, SymbolTable};
+
+/// Checks that `program` is semantically valid and returns it with every
+/// local variable renamed to a name unique within its function, every loop
+/// and switch labeled, every label of a labeled statement made unique and
+/// every implicit conversion made explicit, together with the types and
+/// storage of all its identifiers.
+pub fn analyze(program: Program) -> Result<(Program, SymbolTable), SemanticError> {
+    let program = resolve::resolve(program)?;
+    let (program, symbols) = typecheck::typecheck(program)?;
+    let program = loops::label_loops(program)?;
+    Ok((labels::resolve_labels(program)?, symbols))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SemanticError {
+    DuplicateDeclaration(Identifier, Span),
+    UndeclaredVariable(Identifier, Span),
+    UndeclaredFunction(Identifier, Span),
+    NestedFunctionDefinition(Identifier, Span),
+    StaticLocalFunction(Identifier, Span),
+    ForInitStorageClass(Identifier, Span),
+    ConflictingDeclaration(Identifier, Span),
+    ConflictingLinkage(Identifier, Span),
+    Redefinition(Identifier, Span),
+    NonConstantInitializer(Span),
+    ExternInitializer(Identifier, Span),
+    /// A call of the named function with the number of parameters it expects
+    /// and the number of arguments it got.
+    WrongArgumentCount(Identifier, usize, usize, Span),
+    FunctionUsedAsVariable(Identifier, Span),
+    VariableUsedAsFunction(Identifier, Span),
+    InvalidLvalue(Span),
+    UnnamedParameter(Span),
+    BreakOutsideLoop(Span),
+    ContinueOutsideLoop(Span),
+    CaseOutsideSwitch(Span),
+    DefaultOutsideSwitch(Span),
+    DuplicateCase(Const, Span),
+    DuplicateDefault(Span),
+    NonConstantCase(Span),
+    DuplicateLabel(Identifier, Span),
+    UndefinedLabel(Identifier, Span),
+}
+
+impl SemanticError {
+    /// Location in the source at which the error was detected.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::DuplicateDeclaration(_, span)
+            | Self::UndeclaredVariable(_, span)
+            | Self::UndeclaredFunction(_, span)
+            | Self::NestedFunctionDefinition(_, span)
+            | Self::StaticLocalFunction(_, span)
+            | Self::ForInitStorageClass(_, span)
+            | Self::ConflictingDeclaration(_, span)
+            | Self::ConflictingLinkage(_, span)
+            | Self::Redefinition(_, span)
+            | Self::NonConstantInitializer(span)
+            | Self::ExternInitializer(_, span)
+            | Self::WrongArgumentCount(.., span)
+            | Self::FunctionUsedAsVariable(_, span)
+            | Self::VariableUsedAsFunction(_, span)
+            | Self::InvalidLvalue(span)
+            | Self::UnnamedParameter(span)
+            | Self::BreakOutsideLoop(span)
+            | Self::ContinueOutsideLoop(span)
+            | Self::CaseOutsideSwitch(span)
+            | Self::DefaultOutsideSwitch(span)
+            | Self::DuplicateCase(_, span)
+            | Self::DuplicateDefault(span)
+            | Self::NonConstantCase(span)
+            | Self::DuplicateLabel(_, span)
+            | Self::UndefinedLabel(_, span) => *span,
+        }
+    }
+}
+
+impl Display for SemanticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateDeclaration(name, _) => write!(f, "redeclaration of '{name}'"),
+            Self::UndeclaredVariable(name, _) => write!(f, "'{name}' undeclared"),
+            Self::UndeclaredFunction(name, _) => {
+                write!(f, "implicit declaration of function '{name}'")
+            }
+            Self::NestedFunctionDefinition(name, _) => {
+                write!(f, "function '{name}' defined inside another function")
+            }
+            Self::StaticLocalFunction(name, _) => {
+                write!(f, "invalid storage class for function '{name}'")
+            }
+            Self::ForInitStorageClass(name, _) => write!(
+                f,
+                "declaration of '{name}' with a storage class in 'for' loop initial declaration"
+            ),
+            Self::ConflictingDeclaration(name, _) => write!(f, "conflicting types for '{name}'"),
+            Self::ConflictingLinkage(name, _) => {
+                write!(f, "'{name}' redeclared with a different linkage")
+            }
+            Self::Redefinition(name, _) => write!(f, "redefinition of '{name}'"),
+            Self::NonConstantInitializer(_) => write!(f, "initializer element is not constant"),
+            Self::ExternInitializer(name, _) => {
+                write!(f, "'{name}' has both 'extern' and initializer")
+            }
+            Self::WrongArgumentCount(name, expected, found, _) => {
+                let amount = if found > expected { "many" } else { "few" };
+                write!(
+                    f,
+                    "too {amount} arguments to function '{name}'; expected {expected}, have {found}"
+                )
+            }
+            Self::FunctionUsedAsVariable(name, _) => {
+                write!(f, "function '{name}' used as a variable")
+            }
+            Self::VariableUsedAsFunction(name, _) => {
+                write!(f, "called object '{name}' is not a function")
+            }
+            Self::InvalidLvalue(_) => write!(f, "lvalue required as left operand of assignment"),
+            Self::UnnamedParameter(_) => write!(f, "parameter name omitted"),
+            Self::BreakOutsideLoop(_) => write!(f, "break statement not within loop or switch"),
+            Self::ContinueOutsideLoop(_) => write!(f, "continue statement not within a loop"),
+            Self::CaseOutsideSwitch(_) => write!(f, "case label not within a switch statement"),
+            Self::DefaultOutsideSwitch(_) => {
+                write!(f, "'default' label not within a switch statement")
+            }
+            Self::DuplicateCase(n, _) => write!(f, "duplicate case value {n}"),
+            Self::DuplicateDefault(_) => write!(f, "multiple default labels in one switch"),
+            Self::NonConstantCase(_) => {
+                write!(f, "case label does not reduce to an integer constant")
+            }
+            Self::DuplicateLabel(label, _) => write!(f, "duplicate label '{label}'"),
+            Self::UndefinedLabel(label, _) => write!(f, "label '{label}' used but not defined"),
+        }
+    }
+}
+
+impl Error for SemanticError {}
@@ -0,0 +1,166 @@
+use super::typecheck::common_type;
+use crate::ast::{BinaryOperation, Const, Expression, UnaryOperation};
+
+/// Evaluates an integer constant expression (C17 6.6), returning `None` if
+/// `expr` is not one or its value is undefined, e.g. on division by zero.
+///
+/// The operands are converted like at run time and signed overflow wraps.
+pub fn evaluate(expr: &Expression) -> Option<Const> {
+    match expr {
+        Expression::Constant(c) => Some(*c),
+        Expression::Cast(ty, exp) => Some(evaluate(exp)?.convert(ty)),
+        Expression::Unary(op, exp) => {
+            let c = evaluate(exp)?;
+            Some(match op {
+                UnaryOperation::Complement => Const::from_bits(!c.bits(), &c.ty()),
+                UnaryOperation::Negate => Const::from_bits(c.bits().wrapping_neg(), &c.ty()),
+                UnaryOperation::Not => Const::Int(c.is_zero() as i32),
+            })
+        }
+        // The right operand is not evaluated if the left decides the result,
+        // so it does not have to be constant then.
+        Expression::Binary(BinaryOperation::And, left, right) => match evaluate(left)?.is_zero() {
+            true => Some(Const::Int(0)),
+            false => Some(Const::Int(!evaluate(right)?.is_zero() as i32)),
+        },
+        Expression::Binary(BinaryOperation::Or, left, right) => match evaluate(left)?.is_zero() {
+            true => Some(Const::Int(!evaluate(right)?.is_zero() as i32)),
+            false => Some(Const::Int(1)),
+        },
+        Expression::Binary(op, left, right) => binary(op, evaluate(left)?, evaluate(right)?),
+        Expression::Conditional(condition, then, otherwise) => {
+            let (taken, other) = match evaluate(condition)?.is_zero() {
+                true => (otherwise, then),
+                false => (then, otherwise),
+            };
+            let c = evaluate(taken)?;
+            // The result has the common type of both branches.
+            Some(match evaluate(other) {
+                Some(other) => c.convert(&common_type(&c.ty(), &other.ty())),
+                None => c,
+            })
+        }
+        Expression::Var(..) | Expression::Assignment(..) | Expression::FunctionCall(..) => None,
+    }
+}
+
+fn binary(op: &BinaryOperation, left: Const, right: Const) -> Option<Const> {
+    if matches!(op, BinaryOperation::ShiftLeft | BinaryOperation::ShiftRight) {
+        return shift(op, left, right);
+    }
+
+    let ty = common_type(&left.ty(), &right.ty());
+    let (left, right) = (left.convert(&ty), right.convert(&ty));
+    let bits = match op {
+        BinaryOperation::Add => left.bits().wrapping_add(right.bits()),
+        BinaryOperation::Subtract => left.bits().wrapping_sub(right.bits()),
+        BinaryOperation::Multiply => left.bits().wrapping_mul(right.bits()),
+        BinaryOperation::Divide => left.value().checked_div(right.value())? as u64,
+        BinaryOperation::Remainder => left.value().checked_rem(right.value())? as u64,
+        BinaryOperation::BitwiseAnd => left.bits() & right.bits(),
+        BinaryOperation::BitwiseOr => left.bits() | right.bits(),
+        BinaryOperation::BitwiseXor => left.bits() ^ right.bits(),
+        BinaryOperation::Equal => return Some(Const::Int((left == right) as i32)),
+        BinaryOperation::NotEqual => return Some(Const::Int((left != right) as i32)),
+        BinaryOperation::LessThan => {
+            return Some(Const::Int((left.value() < right.value()) as i32))
+        }
+        BinaryOperation::LessOrEqual => {
+            return Some(Const::Int((left.value() <= right.value()) as i32))
+        }
+        BinaryOperation::GreaterThan => {
+            return Some(Const::Int((left.value() > right.value()) as i32))
+        }
+        BinaryOperation::GreaterOrEqual => {
+            return Some(Const::Int((left.value() >= right.value()) as i32))
+        }
+        BinaryOperation::ShiftLeft | BinaryOperation::ShiftRight => unreachable!("handled above"),
+        BinaryOperation::And | BinaryOperation::Or => unreachable!("evaluated lazily"),
+    };
+    Some(Const::from_bits(bits, &ty))
+}
+
+/// Shifts `left`, whose type is the type of the result, by `right`, which
+/// must be less than the width of that type.
+fn shift(op: &BinaryOperation, left: Const, right: Const) -> Option<Const> {
+    let ty = left.ty();
+    let count = u32::try_from(right.value())
+        .ok()
+        .filter(|&count| count < 8 * ty.size() as u32)?;
+    let bits = match op {
+        BinaryOperation::ShiftLeft => left.bits() << count,
+        // Shifting the mathematical value keeps the sign of signed types.
+        _ => (left.value() >> count) as u64,
+    };
+    Some(Const::from_bits(bits, &ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, lexer, parser};
+
+    fn evaluate_source(expr: &str) -> Option<Const> {
+        let source = format!("int main(void) {{ return {expr}; }}");
+        let program = parser::parse(lexer::lex(&source)).unwrap();
+        let [ast::Declaration::Function(ast::FunctionDeclaration {
+            body: Some(body), ..
+        })] = &program.declarations[..]
+        else {
+            unreachable!();
+        };
+        let [ast::BlockItem::Statement(ast::Statement::Return(expr))] = &body.0[..] else {
+            unreachable!();
+        };
+        evaluate(expr)
+    }
+
+    #[test]
+    fn evaluate_constant_expressions() {
+        assert_eq!(evaluate_source("1 + 2 * 3"), Some(Const::Int(7)));
+        assert_eq!(evaluate_source("-(1 << 4) | ~0 & 3"), Some(Const::Int(-13)));
+        assert_eq!(
+            evaluate_source("7 / 2 - 7 % 2 + (3 > 2) + !5"),
+            Some(Const::Int(3))
+        );
+        assert_eq!(evaluate_source("1 ? 2 : 3 / 0"), Some(Const::Int(2)));
+        assert_eq!(evaluate_source("0 && a || 4"), Some(Const::Int(1)));
+        assert_eq!(
+            evaluate_source("2147483647 + 1"),
+            Some(Const::Int(i32::MIN))
+        );
+    }
+
+    #[test]
+    fn evaluate_with_conversions() {
+        assert_eq!(
+            evaluate_source("2147483647 + 1l"),
+            Some(Const::Long(2147483648))
+        );
+        assert_eq!(evaluate_source("-1 < 0u"), Some(Const::Int(0)));
+        assert_eq!(evaluate_source("-1 > 0l"), Some(Const::Int(0)));
+        assert_eq!(evaluate_source("4294967295u + 1"), Some(Const::UInt(0)));
+        assert_eq!(
+            evaluate_source("(unsigned long) -1"),
+            Some(Const::ULong(u64::MAX))
+        );
+        assert_eq!(evaluate_source("(int) 4294967298l"), Some(Const::Int(2)));
+        assert_eq!(evaluate_source("-8l >> 1"), Some(Const::Long(-4)));
+        assert_eq!(
+            evaluate_source("4294967288u >> 1"),
+            Some(Const::UInt(2147483644))
+        );
+        assert_eq!(evaluate_source("1l << 40"), Some(Const::Long(1 << 40)));
+        assert_eq!(evaluate_source("1 ? -1 : 0u"), Some(Const::UInt(u32::MAX)));
+    }
+
+    #[test]
+    fn reject_non_constant_expressions() {
+        assert_eq!(evaluate_source("a + 1"), None);
+        assert_eq!(evaluate_source("1 || a = 2"), None);
+        assert_eq!(evaluate_source("1 / 0"), None);
+        assert_eq!(evaluate_source("1 << 32"), None);
+        assert_eq!(evaluate_source("1 << -1"), None);
+        assert_eq!(evaluate_source("1l << 64"), None);
+    }
+}
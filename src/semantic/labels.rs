@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use super::SemanticError;
+use crate::ast::{
+    Block, BlockItem, Declaration, FunctionDeclaration, Identifier, Program, Statement,
+};
+
+/// Checks that every label is defined once and every `goto` jumps to a label
+/// of its function. Labels are renamed to `function.label`, so they are
+/// unique in the whole program.
+pub fn resolve_labels(program: Program) -> Result<Program, SemanticError> {
+    let declarations = program
+        .declarations
+        .into_iter()
+        .map(|declaration| match declaration {
+            Declaration::Function(f) => Ok(Declaration::Function(function(f)?)),
+            declaration => Ok(declaration),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Program { declarations })
+}
+
+fn function(f: FunctionDeclaration) -> Result<FunctionDeclaration, SemanticError> {
+    let Some(body) = f.body else {
+        return Ok(f);
+    };
+
+    let mut labels = HashMap::new();
+    for item in &body.0 {
+        if let BlockItem::Statement(stmt) = item {
+            collect(stmt, &f.name, &mut labels)?;
+        }
+    }
+
+    Ok(FunctionDeclaration {
+        body: Some(block(body, &labels)?),
+        ..f
+    })
+}
+
+/// Adds the labels defined in `stmt` to `labels`, mapping them to their new
+/// names.
+fn collect(
+    stmt: &Statement,
+    function: &str,
+    labels: &mut HashMap<Identifier, Identifier>,
+) -> Result<(), SemanticError> {
+    match stmt {
+        Statement::Labeled(label, body, span) => {
+            if labels.contains_key(label) {
+                return Err(SemanticError::DuplicateLabel(label.clone(), *span));
+            }
+            labels.insert(label.clone(), format!("{function}.{label}"));
+            collect(body, function, labels)
+        }
+        Statement::If(_, then, otherwise) => {
+            collect(then, function, labels)?;
+            match otherwise {
+                Some(otherwise) => collect(otherwise, function, labels),
+                None => Ok(()),
+            }
+        }
+        Statement::Compound(block) => block.0.iter().try_for_each(|item| match item {
+            BlockItem::Statement(stmt) => collect(stmt, function, labels),
+            BlockItem::Declaration(_) => Ok(()),
+        }),
+        Statement::While { body, .. }
+        | Statement::DoWhile { body, .. }
+        | Statement::For { body, .. }
+        | Statement::Switch { body, .. }
+        | Statement::Case { body, .. }
+        | Statement::Default { body, .. } => collect(body, function, labels),
+        Statement::Return(_)
+        | Statement::Expression(_)
+        | Statement::Break(..)
+        | Statement::Continue(..)
+        | Statement::Goto(..)
+        | Statement::Null => Ok(()),
+    }
+}
+
+fn block(block: Block, labels: &HashMap<Identifier, Identifier>) -> Result<Block, SemanticError> {
+    let items = block
+        .0
+        .into_iter()
+        .map(|item| match item {
+            BlockItem::Statement(stmt) => Ok(BlockItem::Statement(statement(stmt, labels)?)),
+            declaration => Ok(declaration),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Block(items))
+}
+
+fn statement(
+    stmt: Statement,
+    labels: &HashMap<Identifier, Identifier>,
+) -> Result<Statement, SemanticError> {
+    let body = |body: Box<Statement>| statement(*body, labels).map(Box::new);
+    Ok(match stmt {
+        Statement::Labeled(label, stmt, span) => {
+            Statement::Labeled(labels[&label].clone(), body(stmt)?, span)
+        }
+        Statement::Goto(label, span) => match labels.get(&label) {
+            Some(label) => Statement::Goto(label.clone(), span),
+            None => return Err(SemanticError::UndefinedLabel(label, span)),
+        },
+        Statement::If(condition, then, otherwise) => {
+            Statement::If(condition, body(then)?, otherwise.map(body).transpose()?)
+        }
+        Statement::Compound(b) => Statement::Compound(block(b, labels)?),
+        Statement::While {
+            condition,
+            body: stmt,
+            label,
+        } => Statement::While {
+            condition,
+            body: body(stmt)?,
+            label,
+        },
+        Statement::DoWhile {
+            body: stmt,
+            condition,
+            label,
+        } => Statement::DoWhile {
+            body: body(stmt)?,
+            condition,
+            label,
+        },
+        Statement::For {
+            init,
+            condition,
+            post,
+            body: stmt,
+            label,
+        } => Statement::For {
+            init,
+            condition,
+            post,
+            body: body(stmt)?,
+            label,
+        },
+        Statement::Switch {
+            condition,
+            body: stmt,
+            cases,
+            label,
+        } => Statement::Switch {
+            condition,
+            body: body(stmt)?,
+            cases,
+            label,
+        },
+        Statement::Case {
+            value,
+            body: stmt,
+            label,
+            span,
+        } => Statement::Case {
+            value,
+            body: body(stmt)?,
+            label,
+            span,
+        },
+        Statement::Default {
+            body: stmt,
+            label,
+            span,
+        } => Statement::Default {
+            body: body(stmt)?,
+            label,
+            span,
+        },
+        stmt @ (Statement::Return(_)
+        | Statement::Expression(_)
+        | Statement::Break(..)
+        | Statement::Continue(..)
+        | Statement::Null) => stmt,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn resolve_source(source: &str) -> Result<Vec<BlockItem>, SemanticError> {
+        let program = parser::parse(lexer::lex(source)).unwrap();
+        let mut program = resolve_labels(program)?;
+        match program.declarations.remove(0) {
+            Declaration::Function(FunctionDeclaration {
+                body: Some(body), ..
+            }) => Ok(body.0),
+            declaration => panic!("not a function definition: {declaration:?}"),
+        }
+    }
+
+    #[test]
+    fn rename_labels_and_gotos() {
+        let body = resolve_source(
+            "int main(void) { goto end; while (1) { again: if (1) goto again; } end: return 0; }",
+        )
+        .unwrap();
+        let [BlockItem::Statement(Statement::Goto(forward, _)), BlockItem::Statement(Statement::While {
+            body: loop_body, ..
+        }), BlockItem::Statement(Statement::Labeled(end, ..))] = &body[..]
+        else {
+            panic!("unexpected body {body:?}");
+        };
+        let Statement::Compound(Block(items)) = &**loop_body else {
+            panic!("unexpected loop body {loop_body:?}");
+        };
+        let [BlockItem::Statement(Statement::Labeled(again, stmt, _))] = &items[..] else {
+            panic!("unexpected loop body {items:?}");
+        };
+
+        assert_eq!(forward, "main.end");
+        assert_eq!(end, "main.end");
+        assert_eq!(again, "main.again");
+        assert!(matches!(&**stmt, Statement::If(_, then, None)
+            if matches!(&**then, Statement::Goto(l, _) if l == "main.again")));
+    }
+
+    #[test]
+    fn labels_and_variables_have_separate_namespaces() {
+        resolve_source("int main(void) { int a; a: a = 1; goto a; }").unwrap();
+    }
+
+    #[test]
+    fn duplicate_label() {
+        let err = resolve_source("int main(void) { a: ; {\n  a: ; } }").unwrap_err();
+        assert!(matches!(&err, SemanticError::DuplicateLabel(l, _) if l == "a"));
+        assert_eq!((2, 3), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn undefined_label() {
+        let err = resolve_source("int main(void) { goto nowhere; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::UndefinedLabel(l, _) if l == "nowhere"));
+        assert_eq!((1, 23), (err.span().line, err.span().column));
+    }
+}
@@ -0,0 +1,374 @@
+use super::{constant, SemanticError};
+use crate::ast::{
+    Block, BlockItem, Const, Declaration, Expression, FunctionDeclaration, Identifier, Program,
+    Statement, SwitchCase,
+};
+use crate::lexer::Span;
+
+/// Gives every loop and switch a unique label and attaches it to the `break`
+/// and `continue` statements that refer to it. The `case` and `default`
+/// labels are collected in their switch.
+pub fn label_loops(program: Program) -> Result<Program, SemanticError> {
+    let mut labeler = LoopLabeler::default();
+    let declarations = program
+        .declarations
+        .into_iter()
+        .map(|declaration| match declaration {
+            Declaration::Function(f) => Ok(Declaration::Function(labeler.function(f)?)),
+            declaration => Ok(declaration),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Program { declarations })
+}
+
+/// Labels of the statements `break` and `continue` jump out of.
+#[derive(Clone, Copy, Default)]
+struct Targets<'a> {
+    break_label: Option<&'a Identifier>,
+    continue_label: Option<&'a Identifier>,
+}
+
+#[derive(Default)]
+struct LoopLabeler {
+    counter: u64,
+    /// Cases of the switches being labeled, innermost switch last.
+    switches: Vec<Vec<SwitchCase>>,
+}
+
+impl LoopLabeler {
+    fn function(&mut self, f: FunctionDeclaration) -> Result<FunctionDeclaration, SemanticError> {
+        Ok(FunctionDeclaration {
+            body: f
+                .body
+                .map(|body| self.block(body, Targets::default()))
+                .transpose()?,
+            ..f
+        })
+    }
+
+    fn block(&mut self, block: Block, targets: Targets) -> Result<Block, SemanticError> {
+        let items = block
+            .0
+            .into_iter()
+            .map(|item| match item {
+                BlockItem::Statement(stmt) => {
+                    Ok(BlockItem::Statement(self.statement(stmt, targets)?))
+                }
+                declaration => Ok(declaration),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Block(items))
+    }
+
+    fn statement(&mut self, stmt: Statement, targets: Targets) -> Result<Statement, SemanticError> {
+        Ok(match stmt {
+            Statement::Break(_, span) => match targets.break_label {
+                Some(label) => Statement::Break(Some(label.clone()), span),
+                None => return Err(SemanticError::BreakOutsideLoop(span)),
+            },
+            Statement::Continue(_, span) => match targets.continue_label {
+                Some(label) => Statement::Continue(Some(label.clone()), span),
+                None => return Err(SemanticError::ContinueOutsideLoop(span)),
+            },
+            Statement::If(condition, then, otherwise) => Statement::If(
+                condition,
+                Box::new(self.statement(*then, targets)?),
+                otherwise
+                    .map(|otherwise| self.statement(*otherwise, targets).map(Box::new))
+                    .transpose()?,
+            ),
+            Statement::Compound(block) => Statement::Compound(self.block(block, targets)?),
+            Statement::While {
+                condition, body, ..
+            } => {
+                let label = self.make_label("loop");
+                Statement::While {
+                    condition,
+                    body: Box::new(self.statement(*body, Self::loop_targets(&label))?),
+                    label: Some(label),
+                }
+            }
+            Statement::DoWhile {
+                body, condition, ..
+            } => {
+                let label = self.make_label("loop");
+                Statement::DoWhile {
+                    body: Box::new(self.statement(*body, Self::loop_targets(&label))?),
+                    condition,
+                    label: Some(label),
+                }
+            }
+            Statement::For {
+                init,
+                condition,
+                post,
+                body,
+                ..
+            } => {
+                let label = self.make_label("loop");
+                Statement::For {
+                    init,
+                    condition,
+                    post,
+                    body: Box::new(self.statement(*body, Self::loop_targets(&label))?),
+                    label: Some(label),
+                }
+            }
+            Statement::Switch {
+                condition, body, ..
+            } => {
+                let label = self.make_label("switch");
+                let targets = Targets {
+                    break_label: Some(&label),
+                    ..targets
+                };
+                self.switches.push(vec![]);
+                let body = self.statement(*body, targets);
+                let cases = self.switches.pop().expect("pushed above");
+                Statement::Switch {
+                    condition,
+                    body: Box::new(body?),
+                    cases,
+                    label: Some(label),
+                }
+            }
+            Statement::Case {
+                value, body, span, ..
+            } => {
+                let Some(c) = constant::evaluate(&value) else {
+                    return Err(SemanticError::NonConstantCase(span));
+                };
+                let label = self.add_case(Some(c), span)?;
+                Statement::Case {
+                    value: Expression::Constant(c),
+                    body: Box::new(self.statement(*body, targets)?),
+                    label: Some(label),
+                    span,
+                }
+            }
+            Statement::Default { body, span, .. } => {
+                let label = self.add_case(None, span)?;
+                Statement::Default {
+                    body: Box::new(self.statement(*body, targets)?),
+                    label: Some(label),
+                    span,
+                }
+            }
+            Statement::Labeled(label, body, span) => {
+                Statement::Labeled(label, Box::new(self.statement(*body, targets)?), span)
+            }
+            stmt @ (Statement::Return(_)
+            | Statement::Expression(_)
+            | Statement::Goto(..)
+            | Statement::Null) => stmt,
+        })
+    }
+
+    fn loop_targets(label: &Identifier) -> Targets<'_> {
+        Targets {
+            break_label: Some(label),
+            continue_label: Some(label),
+        }
+    }
+
+    /// Records a `case` label with `value`, or the `default` label, in the
+    /// innermost switch and returns the label to jump to.
+    fn add_case(&mut self, value: Option<Const>, span: Span) -> Result<Identifier, SemanticError> {
+        let label = self.make_label(match value {
+            Some(_) => "case",
+            None => "default",
+        });
+        let Some(cases) = self.switches.last_mut() else {
+            return Err(match value {
+                Some(_) => SemanticError::CaseOutsideSwitch(span),
+                None => SemanticError::DefaultOutsideSwitch(span),
+            });
+        };
+        if cases.iter().any(|case| case.value == value) {
+            return Err(match value {
+                Some(c) => SemanticError::DuplicateCase(c, span),
+                None => SemanticError::DuplicateDefault(span),
+            });
+        }
+        cases.push(SwitchCase {
+            value,
+            label: label.clone(),
+        });
+        Ok(label)
+    }
+
+    fn make_label(&mut self, prefix: &str) -> Identifier {
+        let c = self.counter;
+        self.counter += 1;
+        format!("{prefix}.{c}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn label_source(source: &str) -> Result<Vec<BlockItem>, SemanticError> {
+        let program = parser::parse(lexer::lex(source)).unwrap();
+        let mut program = label_loops(program)?;
+        match program.declarations.remove(0) {
+            Declaration::Function(FunctionDeclaration {
+                body: Some(body), ..
+            }) => Ok(body.0),
+            declaration => panic!("not a function definition: {declaration:?}"),
+        }
+    }
+
+    #[test]
+    fn attach_break_and_continue_to_innermost_loop() {
+        let body = label_source(
+            "int main(void) { while (1) { do break; while (1); continue; } for (;;) break; }",
+        )
+        .unwrap();
+        let [BlockItem::Statement(Statement::While {
+            body: outer_body,
+            label: Some(outer),
+            ..
+        }), BlockItem::Statement(Statement::For {
+            body: for_body,
+            label: Some(for_label),
+            ..
+        })] = &body[..]
+        else {
+            panic!("unexpected body {body:?}");
+        };
+        let Statement::Compound(Block(items)) = &**outer_body else {
+            panic!("unexpected loop body {outer_body:?}");
+        };
+        let [BlockItem::Statement(Statement::DoWhile {
+            body: inner_body,
+            label: Some(inner),
+            ..
+        }), BlockItem::Statement(Statement::Continue(Some(continue_label), _))] = &items[..]
+        else {
+            panic!("unexpected loop body {items:?}");
+        };
+
+        assert_eq!(outer, "loop.0");
+        assert_eq!(inner, "loop.1");
+        assert_eq!(for_label, "loop.2");
+        assert_eq!(continue_label, outer);
+        assert!(matches!(&**inner_body, Statement::Break(Some(l), _) if l == inner));
+        assert!(matches!(&**for_body, Statement::Break(Some(l), _) if l == for_label));
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        let err = label_source("int main(void) {\n  if (1) break;\n}").unwrap_err();
+        assert!(matches!(err, SemanticError::BreakOutsideLoop(_)));
+        assert_eq!((2, 10), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn continue_outside_loop() {
+        let err = label_source("int main(void) { { continue; } }").unwrap_err();
+        assert!(matches!(err, SemanticError::ContinueOutsideLoop(_)));
+    }
+
+    #[test]
+    fn collect_switch_cases() {
+        let body = label_source(
+            "int main(void) { while (1) switch (1) { case 1 + 1: break; default: continue; case 3: ; } }",
+        )
+        .unwrap();
+        let [BlockItem::Statement(Statement::While {
+            body: while_body,
+            label: Some(loop_label),
+            ..
+        })] = &body[..]
+        else {
+            panic!("unexpected body {body:?}");
+        };
+        let Statement::Switch {
+            body: switch_body,
+            cases,
+            label: Some(switch_label),
+            ..
+        } = &**while_body
+        else {
+            panic!("unexpected loop body {while_body:?}");
+        };
+        let Statement::Compound(Block(items)) = &**switch_body else {
+            panic!("unexpected switch body {switch_body:?}");
+        };
+
+        assert_eq!(switch_label, "switch.1");
+        assert_eq!(
+            cases,
+            &vec![
+                SwitchCase {
+                    value: Some(Const::Int(2)),
+                    label: "case.2".into()
+                },
+                SwitchCase {
+                    value: None,
+                    label: "default.3".into()
+                },
+                SwitchCase {
+                    value: Some(Const::Int(3)),
+                    label: "case.4".into()
+                },
+            ]
+        );
+        assert!(matches!(
+            &items[..],
+            [
+                BlockItem::Statement(Statement::Case {
+                    value: Expression::Constant(Const::Int(2)),
+                    body: first,
+                    ..
+                }),
+                BlockItem::Statement(Statement::Default { body: second, .. }),
+                BlockItem::Statement(Statement::Case { .. }),
+            ] if matches!(&**first, Statement::Break(Some(l), _) if l == switch_label)
+                && matches!(&**second, Statement::Continue(Some(l), _) if l == loop_label)
+        ));
+    }
+
+    #[test]
+    fn nested_switch_cases_belong_to_innermost_switch() {
+        label_source(
+            "int main(void) { switch (1) { case 1: switch (2) { case 1: ; } default: ; } }",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn duplicate_case() {
+        let err =
+            label_source("int main(void) { switch (1) { case 4: case 2 * 2: ; } }").unwrap_err();
+        assert_eq!(err, SemanticError::DuplicateCase(Const::Int(4), err.span()));
+        assert_eq!((1, 39), (err.span().line, err.span().column));
+
+        let err =
+            label_source("int main(void) { switch (1) { default: default: ; } }").unwrap_err();
+        assert!(matches!(err, SemanticError::DuplicateDefault(_)));
+    }
+
+    #[test]
+    fn case_outside_switch() {
+        let err = label_source("int main(void) { while (1) case 1: ; }").unwrap_err();
+        assert!(matches!(err, SemanticError::CaseOutsideSwitch(_)));
+
+        let err = label_source("int main(void) { default: ; }").unwrap_err();
+        assert!(matches!(err, SemanticError::DefaultOutsideSwitch(_)));
+    }
+
+    #[test]
+    fn non_constant_case() {
+        let err = label_source("int main(void) { switch (1) { case a: ; } }").unwrap_err();
+        assert!(matches!(err, SemanticError::NonConstantCase(_)));
+    }
+
+    #[test]
+    fn continue_in_switch_outside_loop() {
+        let err = label_source("int main(void) { switch (1) { case 1: continue; } }").unwrap_err();
+        assert!(matches!(err, SemanticError::ContinueOutsideLoop(_)));
+    }
+}
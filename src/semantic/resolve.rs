@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+
+use super::SemanticError;
+use crate::ast::{
+    Block, BlockItem, Declaration, Expression, ForInit, FunctionDeclaration, Identifier, Parameter,
+    Program, Statement, StorageClass, VariableDeclaration,
+};
+use crate::lexer::Span;
+
+/// Renames every local variable to `name.N`, so later stages don't have to
+/// care about scopes. Functions and variables with linkage keep their names.
+pub fn resolve(program: Program) -> Result<Program, SemanticError> {
+    let mut resolver = Resolver {
+        scopes: vec![HashMap::new()],
+        counter: 0,
+    };
+    let declarations = program
+        .declarations
+        .into_iter()
+        .map(|declaration| match declaration {
+            Declaration::Function(f) => Ok(Declaration::Function(resolver.function(f)?)),
+            Declaration::Variable(declaration) => Ok(Declaration::Variable(
+                resolver.file_scope_variable_declaration(declaration)?,
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Program { declarations })
+}
+
+struct Entry {
+    unique_name: Identifier,
+    has_linkage: bool,
+}
+
+struct Resolver {
+    /// Visible identifiers, starting with the file scope and ending with the
+    /// innermost block.
+    scopes: Vec<HashMap<Identifier, Entry>>,
+    counter: u64,
+}
+
+impl Resolver {
+    fn function(&mut self, f: FunctionDeclaration) -> Result<FunctionDeclaration, SemanticError> {
+        if self.scopes.len() > 1 {
+            if f.body.is_some() {
+                return Err(SemanticError::NestedFunctionDefinition(f.name, f.span));
+            }
+            if f.storage_class == Some(StorageClass::Static) {
+                return Err(SemanticError::StaticLocalFunction(f.name, f.span));
+            }
+        }
+        let name = self.declare_with_linkage(f.name, f.span)?;
+        let f = FunctionDeclaration { name, ..f };
+
+        // The parameters share their scope with the outermost block of the
+        // body.
+        self.scopes.push(HashMap::new());
+        let function = self.function_scope(f);
+        self.scopes.pop();
+        function
+    }
+
+    fn function_scope(
+        &mut self,
+        f: FunctionDeclaration,
+    ) -> Result<FunctionDeclaration, SemanticError> {
+        let params = f
+            .params
+            .into_iter()
+            .map(|param| {
+                let name = match param.name {
+                    Some(name) => Some(self.declare_variable(name, param.span)?),
+                    None if f.body.is_some() => {
+                        return Err(SemanticError::UnnamedParameter(param.span));
+                    }
+                    None => None,
+                };
+                Ok(Parameter { name, ..param })
+            })
+            .collect::<Result<_, _>>()?;
+        let body = match f.body {
+            Some(body) => Some(Block(
+                body.0
+                    .into_iter()
+                    .map(|item| self.block_item(item))
+                    .collect::<Result<_, _>>()?,
+            )),
+            None => None,
+        };
+        Ok(FunctionDeclaration { params, body, ..f })
+    }
+
+    fn block(&mut self, block: Block) -> Result<Block, SemanticError> {
+        self.scopes.push(HashMap::new());
+        let items = block
+            .0
+            .into_iter()
+            .map(|item| self.block_item(item))
+            .collect::<Result<_, _>>();
+        self.scopes.pop();
+        Ok(Block(items?))
+    }
+
+    fn block_item(&mut self, item: BlockItem) -> Result<BlockItem, SemanticError> {
+        Ok(match item {
+            BlockItem::Statement(stmt) => BlockItem::Statement(self.statement(stmt)?),
+            BlockItem::Declaration(Declaration::Variable(declaration)) => BlockItem::Declaration(
+                Declaration::Variable(self.variable_declaration(declaration)?),
+            ),
+            BlockItem::Declaration(Declaration::Function(declaration)) => {
+                BlockItem::Declaration(Declaration::Function(self.function(declaration)?))
+            }
+        })
+    }
+
+    fn file_scope_variable_declaration(
+        &mut self,
+        declaration: VariableDeclaration,
+    ) -> Result<VariableDeclaration, SemanticError> {
+        let name = self.declare_with_linkage(declaration.name, declaration.span)?;
+        let init = self.optional_expression(declaration.init)?;
+        Ok(VariableDeclaration {
+            name,
+            init,
+            ..declaration
+        })
+    }
+
+    fn variable_declaration(
+        &mut self,
+        declaration: VariableDeclaration,
+    ) -> Result<VariableDeclaration, SemanticError> {
+        let name = match declaration.storage_class {
+            Some(StorageClass::Extern) => {
+                self.declare_with_linkage(declaration.name, declaration.span)?
+            }
+            _ => self.declare_variable(declaration.name, declaration.span)?,
+        };
+        // The variable is in scope in its own initializer.
+        let init = self.optional_expression(declaration.init)?;
+        Ok(VariableDeclaration {
+            name,
+            init,
+            ..declaration
+        })
+    }
+
+    /// Adds a function or a variable with linkage to the innermost scope. It
+    /// keeps its name, so all declarations of it refer to the same entity.
+    fn declare_with_linkage(
+        &mut self,
+        name: Identifier,
+        span: Span,
+    ) -> Result<Identifier, SemanticError> {
+        let scope = self.scopes.last_mut().expect("at least the file scope");
+        if scope.get(&name).is_some_and(|entry| !entry.has_linkage) {
+            return Err(SemanticError::DuplicateDeclaration(name, span));
+        }
+        scope.insert(
+            name.clone(),
+            Entry {
+                unique_name: name.clone(),
+                has_linkage: true,
+            },
+        );
+        Ok(name)
+    }
+
+    /// Adds a local variable to the innermost scope and returns its unique
+    /// name.
+    fn declare_variable(
+        &mut self,
+        name: Identifier,
+        span: Span,
+    ) -> Result<Identifier, SemanticError> {
+        let scope = self.scopes.last_mut().expect("inside a block");
+        if scope.contains_key(&name) {
+            return Err(SemanticError::DuplicateDeclaration(name, span));
+        }
+
+        let unique_name = format!("{}.{}", name, self.counter);
+        self.counter += 1;
+        scope.insert(
+            name,
+            Entry {
+                unique_name: unique_name.clone(),
+                has_linkage: false,
+            },
+        );
+        Ok(unique_name)
+    }
+
+    fn statement(&mut self, stmt: Statement) -> Result<Statement, SemanticError> {
+        Ok(match stmt {
+            Statement::Return(expr) => Statement::Return(self.expression(expr)?),
+            Statement::Expression(expr) => Statement::Expression(self.expression(expr)?),
+            Statement::If(condition, then, otherwise) => Statement::If(
+                self.expression(condition)?,
+                Box::new(self.statement(*then)?),
+                otherwise
+                    .map(|otherwise| self.statement(*otherwise).map(Box::new))
+                    .transpose()?,
+            ),
+            Statement::Compound(block) => Statement::Compound(self.block(block)?),
+            Statement::Labeled(label, body, span) => {
+                Statement::Labeled(label, Box::new(self.statement(*body)?), span)
+            }
+            stmt @ (Statement::Break(..) | Statement::Continue(..) | Statement::Goto(..)) => stmt,
+            Statement::While {
+                condition,
+                body,
+                label,
+            } => Statement::While {
+                condition: self.expression(condition)?,
+                body: Box::new(self.statement(*body)?),
+                label,
+            },
+            Statement::DoWhile {
+                body,
+                condition,
+                label,
+            } => Statement::DoWhile {
+                body: Box::new(self.statement(*body)?),
+                condition: self.expression(condition)?,
+                label,
+            },
+            Statement::Switch {
+                condition,
+                body,
+                cases,
+                label,
+            } => Statement::Switch {
+                condition: self.expression(condition)?,
+                body: Box::new(self.statement(*body)?),
+                cases,
+                label,
+            },
+            Statement::Case {
+                value,
+                body,
+                label,
+                span,
+            } => Statement::Case {
+                value: self.expression(value)?,
+                body: Box::new(self.statement(*body)?),
+                label,
+                span,
+            },
+            Statement::Default { body, label, span } => Statement::Default {
+                body: Box::new(self.statement(*body)?),
+                label,
+                span,
+            },
+            Statement::For {
+                init,
+                condition,
+                post,
+                body,
+                label,
+            } => {
+                // A declaration in the header is scoped to the loop.
+                self.scopes.push(HashMap::new());
+                let stmt = self.for_statement(init, condition, post, *body, label);
+                self.scopes.pop();
+                stmt?
+            }
+            Statement::Null => Statement::Null,
+        })
+    }
+
+    fn for_statement(
+        &mut self,
+        init: ForInit,
+        condition: Option<Expression>,
+        post: Option<Expression>,
+        body: Statement,
+        label: Option<Identifier>,
+    ) -> Result<Statement, SemanticError> {
+        let init = match init {
+            ForInit::Declaration(declaration) if declaration.storage_class.is_some() => {
+                return Err(SemanticError::ForInitStorageClass(
+                    declaration.name,
+                    declaration.span,
+                ));
+            }
+            ForInit::Declaration(declaration) => {
+                ForInit::Declaration(Box::new(self.variable_declaration(*declaration)?))
+            }
+            ForInit::Expression(expr) => ForInit::Expression(self.optional_expression(expr)?),
+        };
+        Ok(Statement::For {
+            init,
+            condition: self.optional_expression(condition)?,
+            post: self.optional_expression(post)?,
+            body: Box::new(self.statement(body)?),
+            label,
+        })
+    }
+
+    fn optional_expression(
+        &mut self,
+        expr: Option<Expression>,
+    ) -> Result<Option<Expression>, SemanticError> {
+        expr.map(|expr| self.expression(expr)).transpose()
+    }
+
+    fn expression(&mut self, expr: Expression) -> Result<Expression, SemanticError> {
+        Ok(match expr {
+            Expression::Constant(n) => Expression::Constant(n),
+            Expression::Var(name, span) => match self.lookup(&name) {
+                Some(unique_name) => Expression::Var(unique_name, span),
+                None => return Err(SemanticError::UndeclaredVariable(name, span)),
+            },
+            Expression::Cast(ty, exp) => Expression::Cast(ty, Box::new(self.expression(*exp)?)),
+            Expression::Unary(op, exp) => Expression::Unary(op, Box::new(self.expression(*exp)?)),
+            Expression::Binary(op, left, right) => Expression::Binary(
+                op,
+                Box::new(self.expression(*left)?),
+                Box::new(self.expression(*right)?),
+            ),
+            Expression::Assignment(left, right, span) => {
+                if !matches!(*left, Expression::Var(..)) {
+                    return Err(SemanticError::InvalidLvalue(span));
+                }
+                Expression::Assignment(
+                    Box::new(self.expression(*left)?),
+                    Box::new(self.expression(*right)?),
+                    span,
+                )
+            }
+            Expression::Conditional(condition, then, otherwise) => Expression::Conditional(
+                Box::new(self.expression(*condition)?),
+                Box::new(self.expression(*then)?),
+                Box::new(self.expression(*otherwise)?),
+            ),
+            Expression::FunctionCall(name, args, span) => {
+                let Some(name) = self.lookup(&name) else {
+                    return Err(SemanticError::UndeclaredFunction(name, span));
+                };
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.expression(arg))
+                    .collect::<Result<_, _>>()?;
+                Expression::FunctionCall(name, args, span)
+            }
+        })
+    }
+
+    /// Returns the unique name of the innermost visible identifier `name`.
+    fn lookup(&self, name: &Identifier) -> Option<Identifier> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map(|entry| entry.unique_name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn resolve_source(source: &str) -> Result<Vec<BlockItem>, SemanticError> {
+        let program = parser::parse(lexer::lex(source)).unwrap();
+        let mut program = resolve(program)?;
+        match program.declarations.remove(0) {
+            Declaration::Function(FunctionDeclaration {
+                body: Some(body), ..
+            }) => Ok(body.0),
+            declaration => panic!("not a function definition: {declaration:?}"),
+        }
+    }
+
+    #[test]
+    fn rename_variables() {
+        let body = resolve_source("int main(void) { int a = 1; int b = a; return b; }").unwrap();
+        let [BlockItem::Declaration(Declaration::Variable(a)), BlockItem::Declaration(Declaration::Variable(b)), BlockItem::Statement(ret)] =
+            &body[..]
+        else {
+            panic!("unexpected body {body:?}");
+        };
+        assert_eq!(a.name, "a.0");
+        assert_eq!(b.name, "b.1");
+        assert!(matches!(&b.init, Some(Expression::Var(name, _)) if name == "a.0"));
+        assert!(matches!(ret, Statement::Return(Expression::Var(name, _)) if name == "b.1"));
+    }
+
+    #[test]
+    fn variable_in_scope_in_own_initializer() {
+        let body = resolve_source("int main(void) { int a = a; }").unwrap();
+        assert!(matches!(
+            &body[..],
+            [BlockItem::Declaration(Declaration::Variable(VariableDeclaration { init: Some(Expression::Var(name, _)), .. }))]
+                if name == "a.0"
+        ));
+    }
+
+    #[test]
+    fn shadow_variable_in_inner_scope() {
+        let body = resolve_source("int main(void) { int a = 1; { int a = a; a = 2; } return a; }")
+            .unwrap();
+        let [BlockItem::Declaration(Declaration::Variable(outer)), BlockItem::Statement(Statement::Compound(inner)), BlockItem::Statement(ret)] =
+            &body[..]
+        else {
+            panic!("unexpected body {body:?}");
+        };
+        let [BlockItem::Declaration(Declaration::Variable(shadow)), BlockItem::Statement(Statement::Expression(assignment))] =
+            &inner.0[..]
+        else {
+            panic!("unexpected block {inner:?}");
+        };
+
+        assert_eq!(outer.name, "a.0");
+        assert_eq!(shadow.name, "a.1");
+        // The initializer already refers to the new variable.
+        assert!(matches!(&shadow.init, Some(Expression::Var(name, _)) if name == "a.1"));
+        assert!(matches!(assignment, Expression::Assignment(left, ..)
+            if matches!(&**left, Expression::Var(name, _) if name == "a.1")));
+        assert!(matches!(ret, Statement::Return(Expression::Var(name, _)) if name == "a.0"));
+    }
+
+    #[test]
+    fn variable_out_of_scope_after_block() {
+        let err = resolve_source("int main(void) { { int a = 1; } return a; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::UndeclaredVariable(name, _) if name == "a"));
+    }
+
+    #[test]
+    fn undeclared_variable() {
+        let err = resolve_source("int main(void) { int a = 1;\n  return b; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::UndeclaredVariable(name, _) if name == "b"));
+        assert_eq!((2, 10), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn use_before_declaration() {
+        let err = resolve_source("int main(void) { a = 1; int a; }").unwrap_err();
+        assert!(matches!(err, SemanticError::UndeclaredVariable(..)));
+    }
+
+    #[test]
+    fn duplicate_declaration() {
+        let err = resolve_source("int main(void) { int a; int a = 2; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::DuplicateDeclaration(name, _) if name == "a"));
+        assert_eq!((1, 29), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn invalid_lvalue() {
+        let err = resolve_source("int main(void) { int a; a + 1 = 2; }").unwrap_err();
+        assert!(matches!(err, SemanticError::InvalidLvalue(_)));
+        assert_eq!((1, 31), (err.span().line, err.span().column));
+
+        let err = resolve_source("int main(void) { 2 = 3; }").unwrap_err();
+        assert!(matches!(err, SemanticError::InvalidLvalue(_)));
+    }
+
+    #[test]
+    fn for_init_declaration_is_scoped_to_loop() {
+        let body = resolve_source(
+            "int main(void) { int i = 0; for (int i = 0; i < 3; i = i + 1) i; return i; }",
+        )
+        .unwrap();
+        let [_, BlockItem::Statement(Statement::For {
+            init: ForInit::Declaration(declaration),
+            post: Some(Expression::Assignment(left, ..)),
+            ..
+        }), BlockItem::Statement(Statement::Return(Expression::Var(ret, _)))] = &body[..]
+        else {
+            panic!("unexpected body {body:?}");
+        };
+        assert_eq!(declaration.name, "i.1");
+        assert!(matches!(&**left, Expression::Var(name, _) if name == "i.1"));
+        assert_eq!(ret, "i.0");
+
+        // The header shares its scope with the body, but the body is a
+        // block of its own.
+        resolve_source("int main(void) { for (int i = 0;;) { int i; } }").unwrap();
+        let err = resolve_source("int main(void) { for (int i = 0;;) i; return i; }").unwrap_err();
+        assert!(matches!(err, SemanticError::UndeclaredVariable(..)));
+    }
+
+    #[test]
+    fn rename_parameters_and_keep_function_names() {
+        let mut program = parser::parse(lexer::lex(
+            "int f(int a, int b) { return a; }\nint main(void) { int a = 1; return f(a, 2); }",
+        ))
+        .unwrap();
+        program = resolve(program).unwrap();
+        let [Declaration::Function(f), Declaration::Function(main)] = &program.declarations[..]
+        else {
+            panic!("unexpected declarations {:?}", program.declarations);
+        };
+        let params = f
+            .params
+            .iter()
+            .map(|p| p.name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(params, [Some("a.0"), Some("b.1")]);
+        assert!(matches!(
+            &main.body.as_ref().unwrap().0[..],
+            [_, BlockItem::Statement(Statement::Return(Expression::FunctionCall(name, args, _)))]
+                if name == "f" && matches!(&args[0], Expression::Var(a, _) if a == "a.2")
+        ));
+    }
+
+    #[test]
+    fn parameters_share_scope_with_body() {
+        let err = resolve_source("int main(int a) { int a; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::DuplicateDeclaration(name, _) if name == "a"));
+        let err = resolve_source("int main(int a, int a) { }").unwrap_err();
+        assert!(matches!(&err, SemanticError::DuplicateDeclaration(name, _) if name == "a"));
+    }
+
+    #[test]
+    fn unnamed_parameters() {
+        resolve_source("int main(void) { int f(int, long b); return f(1, 2); }").unwrap();
+        let err = resolve_source("int f(int a,\n      int) { return a; }").unwrap_err();
+        assert!(matches!(err, SemanticError::UnnamedParameter(_)));
+        assert_eq!((2, 7), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn local_function_declarations() {
+        resolve_source("int main(void) { int f(int a); return f(1); }").unwrap();
+        let err = resolve_source("int main(void) { int f = 1; int f(void); }").unwrap_err();
+        assert!(matches!(&err, SemanticError::DuplicateDeclaration(name, _) if name == "f"));
+        let err = resolve_source("int main(void) { { int f(void); } return f(); }").unwrap_err();
+        assert!(matches!(&err, SemanticError::UndeclaredFunction(name, _) if name == "f"));
+    }
+
+    #[test]
+    fn nested_function_definition() {
+        let err = resolve_source("int main(void) {\n  int f(void) { return 1; } }").unwrap_err();
+        assert!(matches!(&err, SemanticError::NestedFunctionDefinition(name, _) if name == "f"));
+        assert_eq!((2, 7), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn undeclared_function() {
+        let err = resolve_source("int main(void) { return f(1); }").unwrap_err();
+        assert!(matches!(&err, SemanticError::UndeclaredFunction(name, _) if name == "f"));
+        assert_eq!((1, 25), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn variables_with_linkage_keep_their_names() {
+        let body =
+            resolve_source("int main(void) { static int a; extern int b; return a + b; }").unwrap();
+        let [BlockItem::Declaration(Declaration::Variable(a)), BlockItem::Declaration(Declaration::Variable(b)), _] =
+            &body[..]
+        else {
+            panic!("unexpected body {body:?}");
+        };
+        assert_eq!(a.name, "a.0");
+        assert_eq!(b.name, "b");
+
+        resolve_source("int main(void) { extern int a; extern int a; return a; }").unwrap();
+        let err = resolve_source("int main(void) { int a; extern int a; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::DuplicateDeclaration(name, _) if name == "a"));
+        let err = resolve_source("int main(void) { extern int a; int a; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::DuplicateDeclaration(name, _) if name == "a"));
+    }
+
+    #[test]
+    fn invalid_storage_classes() {
+        let err = resolve_source("int main(void) { static int f(void); }").unwrap_err();
+        assert!(matches!(&err, SemanticError::StaticLocalFunction(name, _) if name == "f"));
+        let err = resolve_source("int main(void) { for (static int i = 0;;) ; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::ForInitStorageClass(name, _) if name == "i"));
+    }
+}
@@ -0,0 +1,829 @@
+use std::collections::HashMap;
+
+use super::{constant, SemanticError};
+use crate::ast::{
+    BinaryOperation, Block, BlockItem, Const, Declaration, Expression, ForInit,
+    FunctionDeclaration, Identifier, Program, Statement, StorageClass, Type, UnaryOperation,
+    VariableDeclaration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentifierAttributes {
+    /// `global` is whether the function has external linkage.
+    Function {
+        defined: bool,
+        global: bool,
+    },
+    /// A variable with static storage duration.
+    Static {
+        init: InitialValue,
+        global: bool,
+    },
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitialValue {
+    /// A declaration without initializer and `extern`, which defines the
+    /// variable as 0 unless another declaration initializes it.
+    Tentative,
+    /// The initial value, already converted to the type of the variable.
+    Initial(Const),
+    NoInitializer,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub ty: Type,
+    pub attributes: IdentifierAttributes,
+}
+
+/// Types of all identifiers of a program, by their unique name.
+pub type SymbolTable = HashMap<Identifier, Symbol>;
+
+/// Checks that every function and variable with linkage is declared with the
+/// same type and linkage everywhere and defined at most once, that functions
+/// are called with the right number of arguments and that functions and
+/// variables aren't used as each other. Expects the identifiers to be
+/// resolved already.
+///
+/// Returns the program with every implicit conversion made explicit by a
+/// cast, together with the types of all its identifiers.
+pub fn typecheck(program: Program) -> Result<(Program, SymbolTable), SemanticError> {
+    let mut checker = TypeChecker::default();
+    let declarations = program
+        .declarations
+        .into_iter()
+        .map(|declaration| {
+            Ok(match declaration {
+                Declaration::Function(f) => Declaration::Function(checker.function(f)?),
+                Declaration::Variable(declaration) => {
+                    Declaration::Variable(checker.file_scope_variable_declaration(declaration)?)
+                }
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((Program { declarations }, checker.symbols))
+}
+
+/// The type both operands of an arithmetic operator are converted to (C17
+/// 6.3.1.8).
+pub(super) fn common_type(left: &Type, right: &Type) -> Type {
+    if left == right {
+        return left.clone();
+    }
+    // Of two types with the same size the unsigned one can hold more values.
+    if left.size() == right.size() {
+        return match left.is_signed() {
+            true => right.clone(),
+            false => left.clone(),
+        };
+    }
+    match left.size() > right.size() {
+        true => left.clone(),
+        false => right.clone(),
+    }
+}
+
+#[derive(Default)]
+struct TypeChecker {
+    symbols: SymbolTable,
+    /// Return type of the function whose body is being checked.
+    return_type: Option<Type>,
+    /// Types of the conditions of the enclosing switch statements, to which
+    /// their `case` values are converted.
+    switch_types: Vec<Type>,
+}
+
+impl TypeChecker {
+    fn function(&mut self, f: FunctionDeclaration) -> Result<FunctionDeclaration, SemanticError> {
+        let ty = Type::Function {
+            params: f.params.iter().map(|param| param.ty.clone()).collect(),
+            ret: Box::new(f.return_type.clone()),
+        };
+        let mut defined = f.body.is_some();
+        let mut global = f.storage_class != Some(StorageClass::Static);
+        if let Some(previous) = self.symbols.get(&f.name) {
+            let IdentifierAttributes::Function {
+                defined: previously_defined,
+                global: previously_global,
+            } = previous.attributes
+            else {
+                return Err(SemanticError::ConflictingDeclaration(f.name, f.span));
+            };
+            if previous.ty != ty {
+                return Err(SemanticError::ConflictingDeclaration(f.name, f.span));
+            }
+            if previously_defined && defined {
+                return Err(SemanticError::Redefinition(f.name, f.span));
+            }
+            if previously_global && !global {
+                return Err(SemanticError::ConflictingLinkage(f.name, f.span));
+            }
+            // Without `static` a function keeps the linkage it was declared with.
+            global = previously_global;
+            defined |= previously_defined;
+        }
+        self.symbols.insert(
+            f.name.clone(),
+            Symbol {
+                ty,
+                attributes: IdentifierAttributes::Function { defined, global },
+            },
+        );
+
+        for param in &f.params {
+            if let Some(name) = &param.name {
+                self.declare_local_variable(name, &param.ty);
+            }
+        }
+        let body = match f.body {
+            Some(body) => {
+                self.return_type = Some(f.return_type.clone());
+                Some(self.block(body)?)
+            }
+            None => None,
+        };
+        Ok(FunctionDeclaration { body, ..f })
+    }
+
+    fn file_scope_variable_declaration(
+        &mut self,
+        declaration: VariableDeclaration,
+    ) -> Result<VariableDeclaration, SemanticError> {
+        let mut init = match (&declaration.init, declaration.storage_class) {
+            (Some(expr), _) => InitialValue::Initial(static_initializer(expr, &declaration)?),
+            (None, Some(StorageClass::Extern)) => InitialValue::NoInitializer,
+            (None, _) => InitialValue::Tentative,
+        };
+        let mut global = declaration.storage_class != Some(StorageClass::Static);
+        if let Some(previous) = self.symbols.get(&declaration.name) {
+            let IdentifierAttributes::Static {
+                init: previous_init,
+                global: previously_global,
+            } = previous.attributes
+            else {
+                return Err(SemanticError::ConflictingDeclaration(
+                    declaration.name,
+                    declaration.span,
+                ));
+            };
+            if previous.ty != declaration.ty {
+                return Err(SemanticError::ConflictingDeclaration(
+                    declaration.name,
+                    declaration.span,
+                ));
+            }
+            // `extern` refers to the previous declaration, whatever its linkage.
+            if declaration.storage_class == Some(StorageClass::Extern) {
+                global = previously_global;
+            } else if previously_global != global {
+                return Err(SemanticError::ConflictingLinkage(
+                    declaration.name,
+                    declaration.span,
+                ));
+            }
+            init = match (previous_init, init) {
+                (InitialValue::Initial(_), InitialValue::Initial(_)) => {
+                    return Err(SemanticError::Redefinition(
+                        declaration.name,
+                        declaration.span,
+                    ))
+                }
+                (InitialValue::Initial(c), _) | (_, InitialValue::Initial(c)) => {
+                    InitialValue::Initial(c)
+                }
+                (InitialValue::Tentative, _) | (_, InitialValue::Tentative) => {
+                    InitialValue::Tentative
+                }
+                _ => InitialValue::NoInitializer,
+            };
+        }
+        self.symbols.insert(
+            declaration.name.clone(),
+            Symbol {
+                ty: declaration.ty.clone(),
+                attributes: IdentifierAttributes::Static { init, global },
+            },
+        );
+        Ok(declaration)
+    }
+
+    fn declare_local_variable(&mut self, name: &Identifier, ty: &Type) {
+        self.symbols.insert(
+            name.clone(),
+            Symbol {
+                ty: ty.clone(),
+                attributes: IdentifierAttributes::Local,
+            },
+        );
+    }
+
+    fn block(&mut self, block: Block) -> Result<Block, SemanticError> {
+        let items = block
+            .0
+            .into_iter()
+            .map(|item| {
+                Ok(match item {
+                    BlockItem::Statement(stmt) => BlockItem::Statement(self.statement(stmt)?),
+                    BlockItem::Declaration(Declaration::Variable(declaration)) => {
+                        BlockItem::Declaration(Declaration::Variable(
+                            self.variable_declaration(declaration)?,
+                        ))
+                    }
+                    BlockItem::Declaration(Declaration::Function(declaration)) => {
+                        BlockItem::Declaration(Declaration::Function(self.function(declaration)?))
+                    }
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Block(items))
+    }
+
+    fn variable_declaration(
+        &mut self,
+        declaration: VariableDeclaration,
+    ) -> Result<VariableDeclaration, SemanticError> {
+        let Some(storage_class) = declaration.storage_class else {
+            self.declare_local_variable(&declaration.name, &declaration.ty);
+            let init = match declaration.init {
+                Some(expr) => Some(self.converted_expression(expr, &declaration.ty)?),
+                None => None,
+            };
+            return Ok(VariableDeclaration {
+                init,
+                ..declaration
+            });
+        };
+        let attributes = match (storage_class, &declaration.init) {
+            (StorageClass::Extern, Some(_)) => {
+                return Err(SemanticError::ExternInitializer(
+                    declaration.name,
+                    declaration.span,
+                ))
+            }
+            (StorageClass::Extern, None) => match self.symbols.get(&declaration.name) {
+                Some(previous) if previous.ty != declaration.ty => {
+                    return Err(SemanticError::ConflictingDeclaration(
+                        declaration.name,
+                        declaration.span,
+                    ))
+                }
+                // The variable is defined elsewhere.
+                Some(_) => return Ok(declaration),
+                None => IdentifierAttributes::Static {
+                    init: InitialValue::NoInitializer,
+                    global: true,
+                },
+            },
+            (StorageClass::Static, init) => {
+                let init = match init {
+                    Some(expr) => static_initializer(expr, &declaration)?,
+                    None => Const::Int(0).convert(&declaration.ty),
+                };
+                IdentifierAttributes::Static {
+                    init: InitialValue::Initial(init),
+                    global: false,
+                }
+            }
+        };
+        self.symbols.insert(
+            declaration.name.clone(),
+            Symbol {
+                ty: declaration.ty.clone(),
+                attributes,
+            },
+        );
+        Ok(declaration)
+    }
+
+    fn statement(&mut self, stmt: Statement) -> Result<Statement, SemanticError> {
+        Ok(match stmt {
+            Statement::Return(expr) => {
+                let ty = self.return_type.clone().expect("inside a function body");
+                Statement::Return(self.converted_expression(expr, &ty)?)
+            }
+            Statement::Expression(expr) => Statement::Expression(self.expression(expr)?.0),
+            Statement::If(condition, then, otherwise) => Statement::If(
+                self.expression(condition)?.0,
+                Box::new(self.statement(*then)?),
+                match otherwise {
+                    Some(otherwise) => Some(Box::new(self.statement(*otherwise)?)),
+                    None => None,
+                },
+            ),
+            Statement::Compound(body) => Statement::Compound(self.block(body)?),
+            Statement::While {
+                condition,
+                body,
+                label,
+            } => Statement::While {
+                condition: self.expression(condition)?.0,
+                body: Box::new(self.statement(*body)?),
+                label,
+            },
+            Statement::DoWhile {
+                body,
+                condition,
+                label,
+            } => Statement::DoWhile {
+                body: Box::new(self.statement(*body)?),
+                condition: self.expression(condition)?.0,
+                label,
+            },
+            Statement::For {
+                init,
+                condition,
+                post,
+                body,
+                label,
+            } => Statement::For {
+                init: match init {
+                    ForInit::Declaration(declaration) => {
+                        ForInit::Declaration(Box::new(self.variable_declaration(*declaration)?))
+                    }
+                    ForInit::Expression(expr) => {
+                        ForInit::Expression(self.optional_expression(expr)?)
+                    }
+                },
+                condition: self.optional_expression(condition)?,
+                post: self.optional_expression(post)?,
+                body: Box::new(self.statement(*body)?),
+                label,
+            },
+            Statement::Switch {
+                condition,
+                body,
+                cases,
+                label,
+            } => {
+                let (condition, ty) = self.expression(condition)?;
+                self.switch_types.push(ty);
+                let body = self.statement(*body);
+                self.switch_types.pop();
+                Statement::Switch {
+                    condition,
+                    body: Box::new(body?),
+                    cases,
+                    label,
+                }
+            }
+            Statement::Case {
+                value,
+                body,
+                label,
+                span,
+            } => {
+                let (value, ty) = self.expression(value)?;
+                // A case outside of a switch is reported once labels are
+                // assigned.
+                let value = match self.switch_types.last() {
+                    Some(switch_type) => convert_to(value, &ty, switch_type),
+                    None => value,
+                };
+                Statement::Case {
+                    value,
+                    body: Box::new(self.statement(*body)?),
+                    label,
+                    span,
+                }
+            }
+            Statement::Default { body, label, span } => Statement::Default {
+                body: Box::new(self.statement(*body)?),
+                label,
+                span,
+            },
+            Statement::Labeled(label, body, span) => {
+                Statement::Labeled(label, Box::new(self.statement(*body)?), span)
+            }
+            stmt @ (Statement::Break(..)
+            | Statement::Continue(..)
+            | Statement::Goto(..)
+            | Statement::Null) => stmt,
+        })
+    }
+
+    fn optional_expression(
+        &mut self,
+        expr: Option<Expression>,
+    ) -> Result<Option<Expression>, SemanticError> {
+        expr.map(|expr| Ok(self.expression(expr)?.0)).transpose()
+    }
+
+    /// Checks `expr` and converts it to `ty` as if by assignment.
+    fn converted_expression(
+        &mut self,
+        expr: Expression,
+        ty: &Type,
+    ) -> Result<Expression, SemanticError> {
+        let (expr, expr_ty) = self.expression(expr)?;
+        Ok(convert_to(expr, &expr_ty, ty))
+    }
+
+    /// Checks `expr` and returns it with its operands converted as the
+    /// operators require, together with its type.
+    fn expression(&mut self, expr: Expression) -> Result<(Expression, Type), SemanticError> {
+        Ok(match expr {
+            Expression::Constant(c) => (Expression::Constant(c), c.ty()),
+            Expression::Var(name, span) => match self.symbols[&name].ty.clone() {
+                Type::Function { .. } => {
+                    return Err(SemanticError::FunctionUsedAsVariable(name, span))
+                }
+                ty => (Expression::Var(name, span), ty),
+            },
+            Expression::Cast(ty, expr) => {
+                let (expr, _) = self.expression(*expr)?;
+                (Expression::Cast(ty.clone(), Box::new(expr)), ty)
+            }
+            Expression::Unary(op, expr) => {
+                let (expr, ty) = self.expression(*expr)?;
+                let ty = match op {
+                    UnaryOperation::Not => Type::Int,
+                    UnaryOperation::Complement | UnaryOperation::Negate => ty,
+                };
+                (Expression::Unary(op, Box::new(expr)), ty)
+            }
+            Expression::Binary(op @ (BinaryOperation::And | BinaryOperation::Or), left, right) => {
+                let (left, _) = self.expression(*left)?;
+                let (right, _) = self.expression(*right)?;
+                (
+                    Expression::Binary(op, Box::new(left), Box::new(right)),
+                    Type::Int,
+                )
+            }
+            // The result of a shift has the type of its left operand.
+            Expression::Binary(
+                op @ (BinaryOperation::ShiftLeft | BinaryOperation::ShiftRight),
+                left,
+                right,
+            ) => {
+                let (left, ty) = self.expression(*left)?;
+                let right = self.converted_expression(*right, &ty)?;
+                (Expression::Binary(op, Box::new(left), Box::new(right)), ty)
+            }
+            Expression::Binary(op, left, right) => {
+                let (left, left_ty) = self.expression(*left)?;
+                let (right, right_ty) = self.expression(*right)?;
+                let common = common_type(&left_ty, &right_ty);
+                let left = convert_to(left, &left_ty, &common);
+                let right = convert_to(right, &right_ty, &common);
+                let ty = match op {
+                    BinaryOperation::Equal
+                    | BinaryOperation::NotEqual
+                    | BinaryOperation::LessThan
+                    | BinaryOperation::LessOrEqual
+                    | BinaryOperation::GreaterThan
+                    | BinaryOperation::GreaterOrEqual => Type::Int,
+                    _ => common,
+                };
+                (Expression::Binary(op, Box::new(left), Box::new(right)), ty)
+            }
+            Expression::Assignment(left, right, span) => {
+                let (left, ty) = self.expression(*left)?;
+                let right = self.converted_expression(*right, &ty)?;
+                (
+                    Expression::Assignment(Box::new(left), Box::new(right), span),
+                    ty,
+                )
+            }
+            Expression::Conditional(condition, then, otherwise) => {
+                let (condition, _) = self.expression(*condition)?;
+                let (then, then_ty) = self.expression(*then)?;
+                let (otherwise, otherwise_ty) = self.expression(*otherwise)?;
+                let ty = common_type(&then_ty, &otherwise_ty);
+                (
+                    Expression::Conditional(
+                        Box::new(condition),
+                        Box::new(convert_to(then, &then_ty, &ty)),
+                        Box::new(convert_to(otherwise, &otherwise_ty, &ty)),
+                    ),
+                    ty,
+                )
+            }
+            Expression::FunctionCall(name, args, span) => {
+                let (params, ret) = match self.symbols[&name].ty.clone() {
+                    Type::Function { params, .. } if params.len() != args.len() => {
+                        return Err(SemanticError::WrongArgumentCount(
+                            name,
+                            params.len(),
+                            args.len(),
+                            span,
+                        ))
+                    }
+                    Type::Function { params, ret } => (params, *ret),
+                    _ => {
+                        return Err(SemanticError::VariableUsedAsFunction(
+                            source_name(&name),
+                            span,
+                        ))
+                    }
+                };
+                let args = args
+                    .into_iter()
+                    .zip(&params)
+                    .map(|(arg, ty)| self.converted_expression(arg, ty))
+                    .collect::<Result<_, _>>()?;
+                (Expression::FunctionCall(name, args, span), ret)
+            }
+        })
+    }
+}
+
+/// Evaluates the initializer of a variable with static storage duration and
+/// converts it to the variable's type.
+fn static_initializer(
+    expr: &Expression,
+    declaration: &VariableDeclaration,
+) -> Result<Const, SemanticError> {
+    constant::evaluate(expr)
+        .map(|c| c.convert(&declaration.ty))
+        .ok_or(SemanticError::NonConstantInitializer(declaration.span))
+}
+
+/// Wraps `expr` of type `from` in a cast to `to` unless the types are equal.
+fn convert_to(expr: Expression, from: &Type, to: &Type) -> Expression {
+    match from == to {
+        true => expr,
+        false => Expression::Cast(to.clone(), Box::new(expr)),
+    }
+}
+
+/// Name of a variable before `resolve` made it unique.
+fn source_name(unique_name: &str) -> Identifier {
+    match unique_name.rsplit_once('.') {
+        Some((name, _)) => name.into(),
+        None => unique_name.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser, semantic::resolve};
+
+    fn typecheck_source(source: &str) -> Result<SymbolTable, SemanticError> {
+        let program = parser::parse(lexer::lex(source)).unwrap();
+        Ok(typecheck(resolve::resolve(program)?)?.1)
+    }
+
+    /// The expression returned by the last statement of `main` in `source`.
+    fn returned_expression(source: &str) -> Expression {
+        let program = parser::parse(lexer::lex(source)).unwrap();
+        let (mut program, _) = typecheck(resolve::resolve(program).unwrap()).unwrap();
+        let Some(Declaration::Function(FunctionDeclaration {
+            body: Some(body), ..
+        })) = program.declarations.pop()
+        else {
+            panic!("expected a function definition");
+        };
+        match body.0.into_iter().last() {
+            Some(BlockItem::Statement(Statement::Return(expr))) => expr,
+            item => panic!("expected a return statement: {item:?}"),
+        }
+    }
+
+    #[test]
+    fn record_function_signatures() {
+        let symbols = typecheck_source(
+            "int f(int a, int b);\nint main(void) { int x = f(1, 2); return x; }\nint f(int a, int b) { return a + b; }",
+        )
+        .unwrap();
+        assert_eq!(
+            symbols["f"],
+            Symbol {
+                ty: Type::Function {
+                    params: vec![Type::Int, Type::Int],
+                    ret: Box::new(Type::Int),
+                },
+                attributes: IdentifierAttributes::Function {
+                    defined: true,
+                    global: true,
+                },
+            }
+        );
+        assert_eq!(
+            symbols["main"].ty,
+            Type::Function {
+                params: vec![],
+                ret: Box::new(Type::Int),
+            }
+        );
+        assert_eq!(symbols["x.2"].ty, Type::Int);
+    }
+
+    #[test]
+    fn wrong_argument_count() {
+        let err =
+            typecheck_source("int f(int a);\nint main(void) { return f(1, 2); }").unwrap_err();
+        assert_eq!(
+            err,
+            SemanticError::WrongArgumentCount("f".into(), 1, 2, err.span())
+        );
+        assert_eq!((2, 25), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn conflicting_declarations() {
+        let err = typecheck_source("int f(int a);\nint main(void) { int f(void); return 0; }")
+            .unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingDeclaration(name, _) if name == "f"));
+        assert_eq!((2, 22), (err.span().line, err.span().column));
+    }
+
+    #[test]
+    fn function_defined_twice() {
+        let err =
+            typecheck_source("int f(void) { return 1; }\nint f(void);\nint f(void) { return 2; }")
+                .unwrap_err();
+        assert!(matches!(&err, SemanticError::Redefinition(name, _) if name == "f"));
+        assert_eq!(err.span().line, 3);
+    }
+
+    #[test]
+    fn function_used_as_variable() {
+        let err = typecheck_source("int f(void);\nint main(void) { return f + 1; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::FunctionUsedAsVariable(name, _) if name == "f"));
+        let err = typecheck_source("int main(void) { main = 1; }").unwrap_err();
+        assert!(matches!(err, SemanticError::FunctionUsedAsVariable(..)));
+    }
+
+    #[test]
+    fn variable_used_as_function() {
+        let err = typecheck_source("int main(void) { int f = 1; return f(); }").unwrap_err();
+        assert!(matches!(&err, SemanticError::VariableUsedAsFunction(name, _) if name == "f"));
+    }
+
+    #[test]
+    fn combine_file_scope_declarations() {
+        let symbols = typecheck_source(
+            "int a;\nextern int a;\nint a = 3;\nstatic int b;\nextern int b;\nextern int c;\nint d;\nint d;",
+        )
+        .unwrap();
+        let attributes = |name: &str| symbols[name].attributes;
+        assert_eq!(
+            attributes("a"),
+            IdentifierAttributes::Static {
+                init: InitialValue::Initial(Const::Int(3)),
+                global: true,
+            }
+        );
+        assert_eq!(
+            attributes("b"),
+            IdentifierAttributes::Static {
+                init: InitialValue::Tentative,
+                global: false,
+            }
+        );
+        assert_eq!(
+            attributes("c"),
+            IdentifierAttributes::Static {
+                init: InitialValue::NoInitializer,
+                global: true,
+            }
+        );
+        assert_eq!(
+            attributes("d"),
+            IdentifierAttributes::Static {
+                init: InitialValue::Tentative,
+                global: true,
+            }
+        );
+    }
+
+    #[test]
+    fn local_storage_classes() {
+        let symbols = typecheck_source(
+            "int main(void) { static int a; static int b = 1 + 2; extern int c; int d; }",
+        )
+        .unwrap();
+        let attributes = |name: &str| symbols[name].attributes;
+        assert_eq!(
+            attributes("a.0"),
+            IdentifierAttributes::Static {
+                init: InitialValue::Initial(Const::Int(0)),
+                global: false,
+            }
+        );
+        assert_eq!(
+            attributes("b.1"),
+            IdentifierAttributes::Static {
+                init: InitialValue::Initial(Const::Int(3)),
+                global: false,
+            }
+        );
+        assert_eq!(
+            attributes("c"),
+            IdentifierAttributes::Static {
+                init: InitialValue::NoInitializer,
+                global: true,
+            }
+        );
+        assert_eq!(attributes("d.2"), IdentifierAttributes::Local);
+    }
+
+    #[test]
+    fn static_functions() {
+        let symbols = typecheck_source("static int f(void);\nint f(void) { return 0; }").unwrap();
+        assert_eq!(
+            symbols["f"].attributes,
+            IdentifierAttributes::Function {
+                defined: true,
+                global: false,
+            }
+        );
+        let err = typecheck_source("int f(void);\nstatic int f(void);").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingLinkage(name, _) if name == "f"));
+    }
+
+    #[test]
+    fn invalid_variable_declarations() {
+        let err = typecheck_source("int a = 1;\nint a = 2;").unwrap_err();
+        assert!(matches!(&err, SemanticError::Redefinition(name, _) if name == "a"));
+        assert_eq!(err.span().line, 2);
+
+        let err = typecheck_source("static int a;\nint a;").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingLinkage(name, _) if name == "a"));
+
+        let err = typecheck_source("int a;\nint a(void);").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingDeclaration(name, _) if name == "a"));
+        let err = typecheck_source("int a(void);\nint main(void) { extern int a; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingDeclaration(name, _) if name == "a"));
+
+        let err = typecheck_source("int a;\nint b = a;").unwrap_err();
+        assert!(matches!(err, SemanticError::NonConstantInitializer(_)));
+        let err = typecheck_source("int main(int a) { static int b = a; }").unwrap_err();
+        assert!(matches!(err, SemanticError::NonConstantInitializer(_)));
+
+        let err = typecheck_source("int main(void) { extern int a = 1; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::ExternInitializer(name, _) if name == "a"));
+    }
+
+    /// Renders `expr` without spans, with every cast spelled out.
+    fn render(expr: &Expression) -> String {
+        match expr {
+            Expression::Constant(c) => c.to_string(),
+            Expression::Var(name, _) => name.clone(),
+            Expression::Cast(ty, inner) => format!("({ty:?}){}", render(inner)),
+            Expression::Unary(op, inner) => format!("{op:?}({})", render(inner)),
+            Expression::Binary(op, left, right) => {
+                format!("{op:?}({}, {})", render(left), render(right))
+            }
+            Expression::FunctionCall(name, args, _) => {
+                let args: Vec<_> = args.iter().map(render).collect();
+                format!("{name}({})", args.join(", "))
+            }
+            expr => panic!("unexpected expression {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn implicit_conversions_become_casts() {
+        let returned = |source: &str| render(&returned_expression(source));
+        assert_eq!(
+            returned("int main(void) { long l = 1; int i = 2; return l + i; }"),
+            "(Int)Add(l.0, (Long)i.1)"
+        );
+        assert_eq!(
+            returned("int main(void) { unsigned u = 1; return u < -1; }"),
+            "LessThan(u.0, (UInt)Negate(1))"
+        );
+        // A shift has the type of its left operand.
+        assert_eq!(
+            returned("long main(void) { int i = 1; return i << 2l; }"),
+            "(Long)ShiftLeft(i.0, (Int)2)"
+        );
+        assert_eq!(
+            returned("long f(long a, unsigned b);\nint main(void) { return f(1, 2); }"),
+            "(Int)f((Long)1, (UInt)2)"
+        );
+    }
+
+    #[test]
+    fn static_initializers_are_converted() {
+        let symbols = typecheck_source(
+            "long a = 2147483647 + 1l;\nunsigned b = -1;\nint c = 4294967298l;\nunsigned long d;",
+        )
+        .unwrap();
+        let init = |name: &str| match symbols[name].attributes {
+            IdentifierAttributes::Static { init, .. } => init,
+            attributes => panic!("unexpected attributes {attributes:?}"),
+        };
+        assert_eq!(init("a"), InitialValue::Initial(Const::Long(2147483648)));
+        assert_eq!(init("b"), InitialValue::Initial(Const::UInt(u32::MAX)));
+        assert_eq!(init("c"), InitialValue::Initial(Const::Int(2)));
+        assert_eq!(init("d"), InitialValue::Tentative);
+        assert_eq!(symbols["d"].ty, Type::ULong);
+    }
+
+    #[test]
+    fn conflicting_types() {
+        let err = typecheck_source("int f(long a);\nint f(int a);").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingDeclaration(name, _) if name == "f"));
+        let err = typecheck_source("int f(void);\nlong f(void);").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingDeclaration(name, _) if name == "f"));
+        let err = typecheck_source("long a;\nunsigned long a;").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingDeclaration(name, _) if name == "a"));
+        let err = typecheck_source("int a;\nint main(void) { extern long a; }").unwrap_err();
+        assert!(matches!(&err, SemanticError::ConflictingDeclaration(name, _) if name == "a"));
+    }
+}
@@ -1,17 +1,33 @@
-use crate::ast;
+use std::cmp::Ordering;
+
+use crate::ast::{self, Const, Type};
+use crate::semantic::{IdentifierAttributes, InitialValue, Symbol, SymbolTable};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Program {
-    pub function: Function,
+    pub functions: Vec<Function>,
+    pub static_variables: Vec<StaticVariable>,
 }
 
 pub type Identifier = String;
 #[derive(Debug, PartialEq, Eq)]
 pub struct Function {
     pub name: Identifier,
+    /// Whether the function is visible to other object files.
+    pub global: bool,
+    pub params: Vec<Identifier>,
     pub body: Vec<Instruction>,
 }
 
+/// A variable with static storage duration defined in this program.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StaticVariable {
+    pub name: Identifier,
+    pub global: bool,
+    /// The initial value, whose type is the type of the variable.
+    pub init: Const,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Instruction {
     Return(Value),
@@ -26,11 +42,52 @@ pub enum Instruction {
         right: Value,
         dst: Value,
     },
+    /// Converts `src` to the larger type of `dst`, keeping its sign.
+    SignExtend {
+        src: Value,
+        dst: Value,
+    },
+    /// Converts the unsigned `src` to the larger type of `dst`.
+    ZeroExtend {
+        src: Value,
+        dst: Value,
+    },
+    /// Converts `src` to the smaller type of `dst` by dropping its high bits.
+    Truncate {
+        src: Value,
+        dst: Value,
+    },
+    Copy {
+        src: Value,
+        dst: Value,
+    },
+    Jump(Identifier),
+    JumpIfZero {
+        condition: Value,
+        target: Identifier,
+    },
+    JumpIfNotZero {
+        condition: Value,
+        target: Identifier,
+    },
+    Label(Identifier),
+    /// Jumps to the label of the case matching `condition`, or to `default`
+    /// if there is none.
+    Switch {
+        condition: Value,
+        cases: Vec<(Const, Identifier)>,
+        default: Identifier,
+    },
+    FunCall {
+        name: Identifier,
+        args: Vec<Value>,
+        dst: Value,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Value {
-    Constant(i32),
+    Constant(Const),
     Var(Identifier),
 }
 
@@ -38,6 +95,7 @@ pub enum Value {
 pub enum UnaryOperator {
     Complement,
     Negate,
+    Not,
 }
 
 impl From<ast::UnaryOperation> for UnaryOperator {
@@ -45,6 +103,7 @@ impl From<ast::UnaryOperation> for UnaryOperator {
         match value {
             ast::UnaryOperation::Complement => Self::Complement,
             ast::UnaryOperation::Negate => Self::Negate,
+            ast::UnaryOperation::Not => Self::Not,
         }
     }
 }
@@ -56,6 +115,17 @@ pub enum BinaryOperator {
     Multiply,
     Divide,
     Remainder,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
 }
 
 impl From<ast::BinaryOperation> for BinaryOperator {
@@ -66,35 +136,145 @@ impl From<ast::BinaryOperation> for BinaryOperator {
             ast::BinaryOperation::Multiply => Self::Multiply,
             ast::BinaryOperation::Divide => Self::Divide,
             ast::BinaryOperation::Remainder => Self::Remainder,
+            ast::BinaryOperation::BitwiseAnd => Self::BitwiseAnd,
+            ast::BinaryOperation::BitwiseOr => Self::BitwiseOr,
+            ast::BinaryOperation::BitwiseXor => Self::BitwiseXor,
+            ast::BinaryOperation::ShiftLeft => Self::ShiftLeft,
+            ast::BinaryOperation::ShiftRight => Self::ShiftRight,
+            ast::BinaryOperation::Equal => Self::Equal,
+            ast::BinaryOperation::NotEqual => Self::NotEqual,
+            ast::BinaryOperation::LessThan => Self::LessThan,
+            ast::BinaryOperation::LessOrEqual => Self::LessOrEqual,
+            ast::BinaryOperation::GreaterThan => Self::GreaterThan,
+            ast::BinaryOperation::GreaterOrEqual => Self::GreaterOrEqual,
+            ast::BinaryOperation::And | ast::BinaryOperation::Or => {
+                unreachable!("short-circuiting operators are lowered to jumps")
+            }
         }
     }
 }
 
-pub fn tacky(ast: ast::Program) -> Program {
-    let mut t = TackyGen::new();
+/// Lowers `ast` to TACKY, adding the temporaries it introduces to `symbols`.
+pub fn tacky(ast: ast::Program, symbols: &mut SymbolTable) -> Program {
+    let mut t = TackyGen::new(symbols);
     t.program(ast)
 }
 
-pub struct TackyGen {
+/// Returns the variables with static storage duration this program defines,
+/// sorted by name.
+fn static_variables(symbols: &SymbolTable) -> Vec<StaticVariable> {
+    let mut variables = symbols
+        .iter()
+        .filter_map(|(name, symbol)| {
+            let IdentifierAttributes::Static { init, global } = symbol.attributes else {
+                return None;
+            };
+            let init = match init {
+                InitialValue::Initial(c) => c,
+                InitialValue::Tentative => Const::Int(0).convert(&symbol.ty),
+                // Defined in another object file.
+                InitialValue::NoInitializer => return None,
+            };
+            Some(StaticVariable {
+                name: name.clone(),
+                global,
+                init,
+            })
+        })
+        .collect::<Vec<_>>();
+    variables.sort_by(|a, b| a.name.cmp(&b.name));
+    variables
+}
+
+pub struct TackyGen<'a> {
     counter: u64,
+    symbols: &'a mut SymbolTable,
 }
 
-impl TackyGen {
-    fn new() -> Self {
-        Self { counter: 0 }
+impl<'a> TackyGen<'a> {
+    fn new(symbols: &'a mut SymbolTable) -> Self {
+        Self {
+            counter: 0,
+            symbols,
+        }
     }
 
     fn program(&mut self, ast: ast::Program) -> Program {
+        // Declarations without a body don't generate any code, and the
+        // variables are taken from the symbol table, which knows how all
+        // their declarations combine.
+        let functions = ast
+            .declarations
+            .into_iter()
+            .filter_map(|declaration| match declaration {
+                ast::Declaration::Function(f) => {
+                    let global = matches!(
+                        self.symbols[&f.name].attributes,
+                        IdentifierAttributes::Function { global: true, .. }
+                    );
+                    self.function(f, global)
+                }
+                ast::Declaration::Variable(_) => None,
+            })
+            .collect();
         Program {
-            function: self.function(ast.function_definition),
+            functions,
+            static_variables: static_variables(self.symbols),
         }
     }
 
-    fn function(&mut self, f: ast::FunctionDefinition) -> Function {
-        Function {
+    fn function(&mut self, f: ast::FunctionDeclaration, global: bool) -> Option<Function> {
+        let mut body = self.block(f.body?);
+        // Reaching the `}` of `main` returns 0 (C17 5.1.2.2.3). For other
+        // functions using the value is undefined, so returning 0 is fine too.
+        body.push(Instruction::Return(Value::Constant(
+            Const::Int(0).convert(&f.return_type),
+        )));
+        Some(Function {
             name: f.name,
-            body: self.instructions(f.body),
+            global,
+            params: f
+                .params
+                .into_iter()
+                .map(|param| param.name.expect("named by the semantic analysis"))
+                .collect(),
+            body,
+        })
+    }
+
+    fn block(&mut self, block: ast::Block) -> Vec<Instruction> {
+        block
+            .0
+            .into_iter()
+            .flat_map(|item| self.block_item(item))
+            .collect()
+    }
+
+    fn block_item(&mut self, item: ast::BlockItem) -> Vec<Instruction> {
+        match item {
+            ast::BlockItem::Statement(stmt) => self.instructions(stmt),
+            ast::BlockItem::Declaration(ast::Declaration::Variable(declaration)) => {
+                self.declaration(declaration)
+            }
+            // Local function declarations only matter to the semantic analysis.
+            ast::BlockItem::Declaration(ast::Declaration::Function(_)) => vec![],
+        }
+    }
+
+    fn declaration(&mut self, declaration: ast::VariableDeclaration) -> Vec<Instruction> {
+        let mut instructions = vec![];
+        // Variables with a storage class are not initialized at run time.
+        if declaration.storage_class.is_some() {
+            return instructions;
         }
+        if let Some(init) = declaration.init {
+            let src = self.expression(init, &mut instructions);
+            instructions.push(Instruction::Copy {
+                src,
+                dst: Value::Var(declaration.name),
+            });
+        }
+        instructions
     }
 
     fn instructions(&mut self, stmt: ast::Statement) -> Vec<Instruction> {
@@ -108,15 +288,186 @@ impl TackyGen {
                 is.push(i);
                 is
             }
+            ast::Statement::Expression(expr) => {
+                let mut instructions = vec![];
+                self.expression(expr, &mut instructions);
+                instructions
+            }
+            ast::Statement::If(condition, then, otherwise) => {
+                let mut instructions = vec![];
+                let condition = self.expression(condition, &mut instructions);
+                let else_label = self.make_label("else");
+                let end_label = self.make_label("if_end");
+                instructions.push(Instruction::JumpIfZero {
+                    condition,
+                    target: else_label.clone(),
+                });
+                instructions.append(&mut self.instructions(*then));
+                match otherwise {
+                    Some(otherwise) => {
+                        instructions.push(Instruction::Jump(end_label.clone()));
+                        instructions.push(Instruction::Label(else_label));
+                        instructions.append(&mut self.instructions(*otherwise));
+                        instructions.push(Instruction::Label(end_label));
+                    }
+                    None => instructions.push(Instruction::Label(else_label)),
+                }
+                instructions
+            }
+            ast::Statement::Compound(block) => self.block(block),
+            ast::Statement::Break(label, _) => {
+                vec![Instruction::Jump(break_label(&expect_label(label)))]
+            }
+            ast::Statement::Continue(label, _) => {
+                vec![Instruction::Jump(continue_label(&expect_label(label)))]
+            }
+            ast::Statement::While {
+                condition,
+                body,
+                label,
+            } => {
+                let label = expect_label(label);
+                let mut instructions = vec![Instruction::Label(continue_label(&label))];
+                let condition = self.expression(condition, &mut instructions);
+                instructions.push(Instruction::JumpIfZero {
+                    condition,
+                    target: break_label(&label),
+                });
+                instructions.append(&mut self.instructions(*body));
+                instructions.push(Instruction::Jump(continue_label(&label)));
+                instructions.push(Instruction::Label(break_label(&label)));
+                instructions
+            }
+            ast::Statement::DoWhile {
+                body,
+                condition,
+                label,
+            } => {
+                let label = expect_label(label);
+                let mut instructions = vec![Instruction::Label(start_label(&label))];
+                instructions.append(&mut self.instructions(*body));
+                instructions.push(Instruction::Label(continue_label(&label)));
+                let condition = self.expression(condition, &mut instructions);
+                instructions.push(Instruction::JumpIfNotZero {
+                    condition,
+                    target: start_label(&label),
+                });
+                instructions.push(Instruction::Label(break_label(&label)));
+                instructions
+            }
+            ast::Statement::Switch {
+                condition,
+                body,
+                cases,
+                label,
+            } => {
+                let label = expect_label(label);
+                let mut instructions = vec![];
+                let condition = self.expression(condition, &mut instructions);
+                let mut default = break_label(&label);
+                let cases = cases
+                    .into_iter()
+                    .filter_map(|case| match case.value {
+                        Some(c) => Some((c, case.label)),
+                        None => {
+                            default = case.label;
+                            None
+                        }
+                    })
+                    .collect();
+                instructions.push(Instruction::Switch {
+                    condition,
+                    cases,
+                    default,
+                });
+                instructions.append(&mut self.instructions(*body));
+                instructions.push(Instruction::Label(break_label(&label)));
+                instructions
+            }
+            ast::Statement::Case { body, label, .. }
+            | ast::Statement::Default { body, label, .. } => {
+                let mut instructions = vec![Instruction::Label(expect_label(label))];
+                instructions.append(&mut self.instructions(*body));
+                instructions
+            }
+            ast::Statement::For {
+                init,
+                condition,
+                post,
+                body,
+                label,
+            } => {
+                let label = expect_label(label);
+                let mut instructions = match init {
+                    ast::ForInit::Declaration(declaration) => self.declaration(*declaration),
+                    ast::ForInit::Expression(expr) => {
+                        let mut instructions = vec![];
+                        if let Some(expr) = expr {
+                            self.expression(expr, &mut instructions);
+                        }
+                        instructions
+                    }
+                };
+                instructions.push(Instruction::Label(start_label(&label)));
+                // A missing condition is always true.
+                if let Some(condition) = condition {
+                    let condition = self.expression(condition, &mut instructions);
+                    instructions.push(Instruction::JumpIfZero {
+                        condition,
+                        target: break_label(&label),
+                    });
+                }
+                instructions.append(&mut self.instructions(*body));
+                instructions.push(Instruction::Label(continue_label(&label)));
+                if let Some(post) = post {
+                    self.expression(post, &mut instructions);
+                }
+                instructions.push(Instruction::Jump(start_label(&label)));
+                instructions.push(Instruction::Label(break_label(&label)));
+                instructions
+            }
+            ast::Statement::Labeled(label, body, _) => {
+                let mut instructions = vec![Instruction::Label(label)];
+                instructions.append(&mut self.instructions(*body));
+                instructions
+            }
+            ast::Statement::Goto(label, _) => vec![Instruction::Jump(label)],
+            ast::Statement::Null => vec![],
         }
     }
 
     fn expression(&mut self, expr: ast::Expression, instructions: &mut Vec<Instruction>) -> Value {
         match expr {
-            ast::Expression::Constant(n) => Value::Constant(n),
+            ast::Expression::Constant(c) => Value::Constant(c),
+            ast::Expression::Var(name, _) => Value::Var(name),
+            ast::Expression::Cast(ty, exp) => {
+                let src = self.expression(*exp, instructions);
+                let src_ty = self.value_type(&src);
+                if src_ty == ty {
+                    return src;
+                }
+                let dst = self.make_temporary(ty.clone());
+                let conversion: fn(Value, Value) -> Instruction =
+                    match ty.size().cmp(&src_ty.size()) {
+                        // Types of the same size only differ in how their bits
+                        // are read.
+                        Ordering::Equal => |src, dst| Instruction::Copy { src, dst },
+                        Ordering::Less => |src, dst| Instruction::Truncate { src, dst },
+                        Ordering::Greater if src_ty.is_signed() => {
+                            |src, dst| Instruction::SignExtend { src, dst }
+                        }
+                        Ordering::Greater => |src, dst| Instruction::ZeroExtend { src, dst },
+                    };
+                instructions.push(conversion(src, dst.clone()));
+                dst
+            }
             ast::Expression::Unary(op, exp) => {
                 let src = self.expression(*exp, instructions);
-                let dst = self.make_temporary();
+                let ty = match op {
+                    ast::UnaryOperation::Not => Type::Int,
+                    _ => self.value_type(&src),
+                };
+                let dst = self.make_temporary(ty);
                 let instruction = Instruction::Unary {
                     operator: op.into(),
                     src,
@@ -125,10 +476,24 @@ impl TackyGen {
                 instructions.push(instruction);
                 dst
             }
+            ast::Expression::Binary(
+                op @ (ast::BinaryOperation::And | ast::BinaryOperation::Or),
+                left,
+                right,
+            ) => self.short_circuit(op, *left, *right, instructions),
             ast::Expression::Binary(op, left, right) => {
                 let left = self.expression(*left, instructions);
                 let right = self.expression(*right, instructions);
-                let dst = self.make_temporary();
+                let ty = match op {
+                    ast::BinaryOperation::Equal
+                    | ast::BinaryOperation::NotEqual
+                    | ast::BinaryOperation::LessThan
+                    | ast::BinaryOperation::LessOrEqual
+                    | ast::BinaryOperation::GreaterThan
+                    | ast::BinaryOperation::GreaterOrEqual => Type::Int,
+                    _ => self.value_type(&left),
+                };
+                let dst = self.make_temporary(ty);
                 let instruction = Instruction::Binary {
                     operator: op.into(),
                     left,
@@ -138,34 +503,197 @@ impl TackyGen {
                 instructions.push(instruction);
                 dst
             }
+            ast::Expression::Assignment(left, right, _) => {
+                let ast::Expression::Var(name, _) = *left else {
+                    unreachable!("resolution only accepts variables as lvalues");
+                };
+                let src = self.expression(*right, instructions);
+                let dst = Value::Var(name);
+                instructions.push(Instruction::Copy {
+                    src,
+                    dst: dst.clone(),
+                });
+                dst
+            }
+            ast::Expression::Conditional(condition, then, otherwise) => {
+                let condition = self.expression(*condition, instructions);
+                let else_label = self.make_label("cond_else");
+                let end_label = self.make_label("cond_end");
+                // Both branches have the type of the result, so the temporary
+                // can only be declared once the first one is lowered.
+                let dst = self.make_label(".tmp");
+                instructions.push(Instruction::JumpIfZero {
+                    condition,
+                    target: else_label.clone(),
+                });
+                let src = self.expression(*then, instructions);
+                let dst = self.declare_temporary(dst, self.value_type(&src));
+                instructions.push(Instruction::Copy {
+                    src,
+                    dst: dst.clone(),
+                });
+                instructions.push(Instruction::Jump(end_label.clone()));
+                instructions.push(Instruction::Label(else_label));
+                let src = self.expression(*otherwise, instructions);
+                instructions.push(Instruction::Copy {
+                    src,
+                    dst: dst.clone(),
+                });
+                instructions.push(Instruction::Label(end_label));
+                dst
+            }
+            ast::Expression::FunctionCall(name, args, _) => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.expression(arg, instructions))
+                    .collect();
+                let Type::Function { ret, .. } = &self.symbols[&name].ty else {
+                    unreachable!("the type checker only accepts calls of functions");
+                };
+                let dst = self.make_temporary((**ret).clone());
+                instructions.push(Instruction::FunCall {
+                    name,
+                    args,
+                    dst: dst.clone(),
+                });
+                dst
+            }
         }
     }
 
-    fn make_temporary(&mut self) -> Value {
+    /// Lowers `&&` and `||`, evaluating `right` only if `left` does not
+    /// already decide the result.
+    fn short_circuit(
+        &mut self,
+        op: ast::BinaryOperation,
+        left: ast::Expression,
+        right: ast::Expression,
+        instructions: &mut Vec<Instruction>,
+    ) -> Value {
+        let is_and = op == ast::BinaryOperation::And;
+        let (short, other, prefix) = match is_and {
+            true => (Const::Int(0), Const::Int(1), "and"),
+            false => (Const::Int(1), Const::Int(0), "or"),
+        };
+        let short_label = self.make_label(&format!("{prefix}_short"));
+        let end_label = self.make_label(&format!("{prefix}_end"));
+        let jump = |condition| match is_and {
+            true => Instruction::JumpIfZero {
+                condition,
+                target: short_label.clone(),
+            },
+            false => Instruction::JumpIfNotZero {
+                condition,
+                target: short_label.clone(),
+            },
+        };
+
+        let left = self.expression(left, instructions);
+        instructions.push(jump(left));
+        let right = self.expression(right, instructions);
+        instructions.push(jump(right));
+
+        let dst = self.make_temporary(Type::Int);
+        instructions.extend([
+            Instruction::Copy {
+                src: Value::Constant(other),
+                dst: dst.clone(),
+            },
+            Instruction::Jump(end_label.clone()),
+            Instruction::Label(short_label),
+            Instruction::Copy {
+                src: Value::Constant(short),
+                dst: dst.clone(),
+            },
+            Instruction::Label(end_label),
+        ]);
+        dst
+    }
+
+    fn make_label(&mut self, prefix: &str) -> Identifier {
         let c = self.counter;
         self.counter += 1;
-        Value::Var(format!("__tmp.{c}"))
+        format!("{prefix}.{c}")
+    }
+
+    /// Temporaries start with a `.`, so they are distinct from the `name.N`
+    /// of every renamed local.
+    fn make_temporary(&mut self, ty: Type) -> Value {
+        let name = self.make_label(".tmp");
+        self.declare_temporary(name, ty)
     }
+
+    /// Adds the temporary `name` of type `ty` to the symbol table.
+    fn declare_temporary(&mut self, name: Identifier, ty: Type) -> Value {
+        self.symbols.insert(
+            name.clone(),
+            Symbol {
+                ty,
+                attributes: IdentifierAttributes::Local,
+            },
+        );
+        Value::Var(name)
+    }
+
+    fn value_type(&self, value: &Value) -> Type {
+        match value {
+            Value::Constant(c) => c.ty(),
+            Value::Var(name) => self.symbols[name].ty.clone(),
+        }
+    }
+}
+
+fn expect_label(label: Option<Identifier>) -> Identifier {
+    label.expect("labeled by the semantic analysis")
+}
+
+fn start_label(label: &str) -> Identifier {
+    format!("start_{label}")
+}
+
+fn continue_label(label: &str) -> Identifier {
+    format!("continue_{label}")
+}
+
+fn break_label(label: &str) -> Identifier {
+    format!("break_{label}")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::{self, Span};
+    use crate::{parser, semantic};
+
+    fn int_locals(names: &[&str]) -> SymbolTable {
+        names
+            .iter()
+            .map(|name| {
+                let symbol = Symbol {
+                    ty: Type::Int,
+                    attributes: IdentifierAttributes::Local,
+                };
+                (name.to_string(), symbol)
+            })
+            .collect()
+    }
 
     #[test]
     fn tacky_constant() {
-        let mut t = TackyGen::new();
-        let stmt = ast::Statement::Return(ast::Expression::Constant(3));
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::Return(ast::Expression::Constant(Const::Int(3)));
         let i = t.instructions(stmt);
-        assert_eq!(i, vec![Instruction::Return(Value::Constant(3))])
+        assert_eq!(i, vec![Instruction::Return(Value::Constant(Const::Int(3)))])
     }
 
     #[test]
     fn tacky_single_unary() {
-        let mut t = TackyGen::new();
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
         let stmt = ast::Statement::Return(ast::Expression::Unary(
             ast::UnaryOperation::Complement,
-            Box::new(ast::Expression::Constant(2)),
+            Box::new(ast::Expression::Constant(Const::Int(2))),
         ));
         let i = t.instructions(stmt);
         assert_eq!(
@@ -173,24 +701,25 @@ mod tests {
             vec![
                 Instruction::Unary {
                     operator: UnaryOperator::Complement,
-                    src: Value::Constant(2),
-                    dst: Value::Var("__tmp.0".into())
+                    src: Value::Constant(Const::Int(2)),
+                    dst: Value::Var(".tmp.0".into())
                 },
-                Instruction::Return(Value::Var("__tmp.0".into()))
+                Instruction::Return(Value::Var(".tmp.0".into()))
             ]
         )
     }
 
     #[test]
     fn tacky_nested_unary() {
-        let mut t = TackyGen::new();
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
         let stmt = ast::Statement::Return(ast::Expression::Unary(
             ast::UnaryOperation::Negate,
             Box::new(ast::Expression::Unary(
                 ast::UnaryOperation::Complement,
                 Box::new(ast::Expression::Unary(
                     ast::UnaryOperation::Negate,
-                    Box::new(ast::Expression::Constant(8)),
+                    Box::new(ast::Expression::Constant(Const::Int(8))),
                 )),
             )),
         ));
@@ -200,31 +729,32 @@ mod tests {
             vec![
                 Instruction::Unary {
                     operator: UnaryOperator::Negate,
-                    src: Value::Constant(8),
-                    dst: Value::Var("__tmp.0".into())
+                    src: Value::Constant(Const::Int(8)),
+                    dst: Value::Var(".tmp.0".into())
                 },
                 Instruction::Unary {
                     operator: UnaryOperator::Complement,
-                    src: Value::Var("__tmp.0".into()),
-                    dst: Value::Var("__tmp.1".into())
+                    src: Value::Var(".tmp.0".into()),
+                    dst: Value::Var(".tmp.1".into())
                 },
                 Instruction::Unary {
                     operator: UnaryOperator::Negate,
-                    src: Value::Var("__tmp.1".into()),
-                    dst: Value::Var("__tmp.2".into())
+                    src: Value::Var(".tmp.1".into()),
+                    dst: Value::Var(".tmp.2".into())
                 },
-                Instruction::Return(Value::Var("__tmp.2".into()))
+                Instruction::Return(Value::Var(".tmp.2".into()))
             ]
         )
     }
 
     #[test]
     fn tacky_single_binary() {
-        let mut t = TackyGen::new();
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
         let stmt = ast::Statement::Return(ast::Expression::Binary(
             ast::BinaryOperation::Subtract,
-            Box::new(ast::Expression::Constant(1)),
-            Box::new(ast::Expression::Constant(2)),
+            Box::new(ast::Expression::Constant(Const::Int(1))),
+            Box::new(ast::Expression::Constant(Const::Int(2))),
         ));
         let i = t.instructions(stmt);
         assert_eq!(
@@ -232,26 +762,27 @@ mod tests {
             vec![
                 Instruction::Binary {
                     operator: BinaryOperator::Subtract,
-                    left: Value::Constant(1),
-                    right: Value::Constant(2),
-                    dst: Value::Var("__tmp.0".into())
+                    left: Value::Constant(Const::Int(1)),
+                    right: Value::Constant(Const::Int(2)),
+                    dst: Value::Var(".tmp.0".into())
                 },
-                Instruction::Return(Value::Var("__tmp.0".into()))
+                Instruction::Return(Value::Var(".tmp.0".into()))
             ]
         )
     }
 
     #[test]
     fn tacky_nested_binary() {
-        let mut t = TackyGen::new();
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
         let stmt = ast::Statement::Return(ast::Expression::Binary(
             ast::BinaryOperation::Subtract,
             Box::new(ast::Expression::Binary(
                 ast::BinaryOperation::Subtract,
-                Box::new(ast::Expression::Constant(1)),
-                Box::new(ast::Expression::Constant(2)),
+                Box::new(ast::Expression::Constant(Const::Int(1))),
+                Box::new(ast::Expression::Constant(Const::Int(2))),
             )),
-            Box::new(ast::Expression::Constant(3)),
+            Box::new(ast::Expression::Constant(Const::Int(3))),
         ));
         let i = t.instructions(stmt);
         assert_eq!(
@@ -259,32 +790,33 @@ mod tests {
             vec![
                 Instruction::Binary {
                     operator: BinaryOperator::Subtract,
-                    left: Value::Constant(1),
-                    right: Value::Constant(2),
-                    dst: Value::Var("__tmp.0".into())
+                    left: Value::Constant(Const::Int(1)),
+                    right: Value::Constant(Const::Int(2)),
+                    dst: Value::Var(".tmp.0".into())
                 },
                 Instruction::Binary {
                     operator: BinaryOperator::Subtract,
-                    left: Value::Var("__tmp.0".into()),
-                    right: Value::Constant(3),
-                    dst: Value::Var("__tmp.1".into())
+                    left: Value::Var(".tmp.0".into()),
+                    right: Value::Constant(Const::Int(3)),
+                    dst: Value::Var(".tmp.1".into())
                 },
-                Instruction::Return(Value::Var("__tmp.1".into()))
+                Instruction::Return(Value::Var(".tmp.1".into()))
             ]
         )
     }
 
     #[test]
     fn tacky_nested_precedence_binary() {
-        let mut t = TackyGen::new();
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
         let stmt = ast::Statement::Return(ast::Expression::Binary(
             ast::BinaryOperation::Subtract,
             Box::new(ast::Expression::Binary(
                 ast::BinaryOperation::Multiply,
-                Box::new(ast::Expression::Constant(2)),
-                Box::new(ast::Expression::Constant(3)),
+                Box::new(ast::Expression::Constant(Const::Int(2))),
+                Box::new(ast::Expression::Constant(Const::Int(3))),
             )),
-            Box::new(ast::Expression::Constant(1)),
+            Box::new(ast::Expression::Constant(Const::Int(1))),
         ));
         let i = t.instructions(stmt);
         assert_eq!(
@@ -292,18 +824,472 @@ mod tests {
             vec![
                 Instruction::Binary {
                     operator: BinaryOperator::Multiply,
-                    left: Value::Constant(2),
-                    right: Value::Constant(3),
-                    dst: Value::Var("__tmp.0".into())
+                    left: Value::Constant(Const::Int(2)),
+                    right: Value::Constant(Const::Int(3)),
+                    dst: Value::Var(".tmp.0".into())
                 },
                 Instruction::Binary {
                     operator: BinaryOperator::Subtract,
-                    left: Value::Var("__tmp.0".into()),
-                    right: Value::Constant(1),
-                    dst: Value::Var("__tmp.1".into())
+                    left: Value::Var(".tmp.0".into()),
+                    right: Value::Constant(Const::Int(1)),
+                    dst: Value::Var(".tmp.1".into())
                 },
-                Instruction::Return(Value::Var("__tmp.1".into()))
+                Instruction::Return(Value::Var(".tmp.1".into()))
             ]
         )
     }
+
+    #[test]
+    fn tacky_declarations_and_assignments() {
+        let mut symbols = int_locals(&["a", "b"]);
+        let mut t = TackyGen::new(&mut symbols);
+        let function = ast::FunctionDeclaration {
+            name: "main".into(),
+            return_type: Type::Int,
+            params: vec![],
+            body: Some(ast::Block(vec![
+                ast::BlockItem::Declaration(ast::Declaration::Variable(ast::VariableDeclaration {
+                    name: "a".into(),
+                    ty: Type::Int,
+                    init: Some(ast::Expression::Constant(Const::Int(1))),
+                    storage_class: None,
+                    span: Span::default(),
+                })),
+                ast::BlockItem::Declaration(ast::Declaration::Variable(ast::VariableDeclaration {
+                    name: "b".into(),
+                    ty: Type::Int,
+                    init: None,
+                    storage_class: None,
+                    span: Span::default(),
+                })),
+                ast::BlockItem::Statement(ast::Statement::Expression(ast::Expression::Assignment(
+                    Box::new(ast::Expression::Var("b".into(), Span::default())),
+                    Box::new(ast::Expression::Unary(
+                        ast::UnaryOperation::Negate,
+                        Box::new(ast::Expression::Var("a".into(), Span::default())),
+                    )),
+                    Span::default(),
+                ))),
+                ast::BlockItem::Statement(ast::Statement::Null),
+            ])),
+            storage_class: None,
+            span: Span::default(),
+        };
+        let f = t.function(function, true).unwrap();
+        assert_eq!(
+            f.body,
+            vec![
+                Instruction::Copy {
+                    src: Value::Constant(Const::Int(1)),
+                    dst: Value::Var("a".into())
+                },
+                Instruction::Unary {
+                    operator: UnaryOperator::Negate,
+                    src: Value::Var("a".into()),
+                    dst: Value::Var(".tmp.0".into())
+                },
+                Instruction::Copy {
+                    src: Value::Var(".tmp.0".into()),
+                    dst: Value::Var("b".into())
+                },
+                Instruction::Return(Value::Constant(Const::Int(0)))
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_short_circuit_and() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::Return(ast::Expression::Binary(
+            ast::BinaryOperation::And,
+            Box::new(ast::Expression::Constant(Const::Int(1))),
+            Box::new(ast::Expression::Binary(
+                ast::BinaryOperation::LessThan,
+                Box::new(ast::Expression::Constant(Const::Int(2))),
+                Box::new(ast::Expression::Constant(Const::Int(3))),
+            )),
+        ));
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::JumpIfZero {
+                    condition: Value::Constant(Const::Int(1)),
+                    target: "and_short.0".into()
+                },
+                Instruction::Binary {
+                    operator: BinaryOperator::LessThan,
+                    left: Value::Constant(Const::Int(2)),
+                    right: Value::Constant(Const::Int(3)),
+                    dst: Value::Var(".tmp.2".into())
+                },
+                Instruction::JumpIfZero {
+                    condition: Value::Var(".tmp.2".into()),
+                    target: "and_short.0".into()
+                },
+                Instruction::Copy {
+                    src: Value::Constant(Const::Int(1)),
+                    dst: Value::Var(".tmp.3".into())
+                },
+                Instruction::Jump("and_end.1".into()),
+                Instruction::Label("and_short.0".into()),
+                Instruction::Copy {
+                    src: Value::Constant(Const::Int(0)),
+                    dst: Value::Var(".tmp.3".into())
+                },
+                Instruction::Label("and_end.1".into()),
+                Instruction::Return(Value::Var(".tmp.3".into()))
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_short_circuit_or() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::Return(ast::Expression::Binary(
+            ast::BinaryOperation::Or,
+            Box::new(ast::Expression::Constant(Const::Int(0))),
+            Box::new(ast::Expression::Constant(Const::Int(2))),
+        ));
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::JumpIfNotZero {
+                    condition: Value::Constant(Const::Int(0)),
+                    target: "or_short.0".into()
+                },
+                Instruction::JumpIfNotZero {
+                    condition: Value::Constant(Const::Int(2)),
+                    target: "or_short.0".into()
+                },
+                Instruction::Copy {
+                    src: Value::Constant(Const::Int(0)),
+                    dst: Value::Var(".tmp.2".into())
+                },
+                Instruction::Jump("or_end.1".into()),
+                Instruction::Label("or_short.0".into()),
+                Instruction::Copy {
+                    src: Value::Constant(Const::Int(1)),
+                    dst: Value::Var(".tmp.2".into())
+                },
+                Instruction::Label("or_end.1".into()),
+                Instruction::Return(Value::Var(".tmp.2".into()))
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_if_else() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::If(
+            ast::Expression::Constant(Const::Int(1)),
+            Box::new(ast::Statement::Return(ast::Expression::Constant(
+                Const::Int(2),
+            ))),
+            Some(Box::new(ast::Statement::Return(ast::Expression::Constant(
+                Const::Int(3),
+            )))),
+        );
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::JumpIfZero {
+                    condition: Value::Constant(Const::Int(1)),
+                    target: "else.0".into()
+                },
+                Instruction::Return(Value::Constant(Const::Int(2))),
+                Instruction::Jump("if_end.1".into()),
+                Instruction::Label("else.0".into()),
+                Instruction::Return(Value::Constant(Const::Int(3))),
+                Instruction::Label("if_end.1".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_if_without_else() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::If(
+            ast::Expression::Constant(Const::Int(1)),
+            Box::new(ast::Statement::Return(ast::Expression::Constant(
+                Const::Int(2),
+            ))),
+            None,
+        );
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::JumpIfZero {
+                    condition: Value::Constant(Const::Int(1)),
+                    target: "else.0".into()
+                },
+                Instruction::Return(Value::Constant(Const::Int(2))),
+                Instruction::Label("else.0".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_conditional() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::Return(ast::Expression::Conditional(
+            Box::new(ast::Expression::Constant(Const::Int(1))),
+            Box::new(ast::Expression::Constant(Const::Int(2))),
+            Box::new(ast::Expression::Constant(Const::Int(3))),
+        ));
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::JumpIfZero {
+                    condition: Value::Constant(Const::Int(1)),
+                    target: "cond_else.0".into()
+                },
+                Instruction::Copy {
+                    src: Value::Constant(Const::Int(2)),
+                    dst: Value::Var(".tmp.2".into())
+                },
+                Instruction::Jump("cond_end.1".into()),
+                Instruction::Label("cond_else.0".into()),
+                Instruction::Copy {
+                    src: Value::Constant(Const::Int(3)),
+                    dst: Value::Var(".tmp.2".into())
+                },
+                Instruction::Label("cond_end.1".into()),
+                Instruction::Return(Value::Var(".tmp.2".into()))
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_while_loop() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::While {
+            condition: ast::Expression::Constant(Const::Int(1)),
+            body: Box::new(ast::Statement::Break(
+                Some("loop.0".into()),
+                Span::default(),
+            )),
+            label: Some("loop.0".into()),
+        };
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Label("continue_loop.0".into()),
+                Instruction::JumpIfZero {
+                    condition: Value::Constant(Const::Int(1)),
+                    target: "break_loop.0".into()
+                },
+                Instruction::Jump("break_loop.0".into()),
+                Instruction::Jump("continue_loop.0".into()),
+                Instruction::Label("break_loop.0".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_for_loop() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let stmt = ast::Statement::For {
+            init: ast::ForInit::Expression(None),
+            condition: None,
+            post: Some(ast::Expression::Constant(Const::Int(2))),
+            body: Box::new(ast::Statement::Continue(
+                Some("loop.0".into()),
+                Span::default(),
+            )),
+            label: Some("loop.0".into()),
+        };
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Label("start_loop.0".into()),
+                Instruction::Jump("continue_loop.0".into()),
+                Instruction::Label("continue_loop.0".into()),
+                Instruction::Jump("start_loop.0".into()),
+                Instruction::Label("break_loop.0".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn tacky_switch() {
+        let mut symbols = SymbolTable::new();
+        let mut t = TackyGen::new(&mut symbols);
+        let case = |value: Option<i32>, label: &str| ast::SwitchCase {
+            value: value.map(Const::Int),
+            label: label.into(),
+        };
+        let stmt = ast::Statement::Switch {
+            condition: ast::Expression::Constant(Const::Int(1)),
+            body: Box::new(ast::Statement::Case {
+                value: ast::Expression::Constant(Const::Int(2)),
+                body: Box::new(ast::Statement::Null),
+                label: Some("case.1".into()),
+                span: Span::default(),
+            }),
+            cases: vec![case(Some(2), "case.1"), case(Some(5), "case.2")],
+            label: Some("switch.0".into()),
+        };
+        let i = t.instructions(stmt);
+        assert_eq!(
+            i,
+            vec![
+                Instruction::Switch {
+                    condition: Value::Constant(Const::Int(1)),
+                    cases: vec![
+                        (Const::Int(2), "case.1".into()),
+                        (Const::Int(5), "case.2".into())
+                    ],
+                    default: "break_switch.0".into(),
+                },
+                Instruction::Label("case.1".into()),
+                Instruction::Label("break_switch.0".into()),
+            ]
+        )
+    }
+
+    fn tacky_source(source: &str) -> Program {
+        let program = parser::parse(lexer::lex(source)).unwrap();
+        let (program, mut symbols) = semantic::analyze(program).unwrap();
+        tacky(program, &mut symbols)
+    }
+
+    #[test]
+    fn temporaries_are_distinct_from_locals() {
+        let program = tacky_source(
+            "int main(void) { int __tmp = 5; int tmp = 2; return (tmp + 1) * __tmp; }",
+        );
+        assert_eq!(
+            program.functions[0].body[2..5],
+            [
+                Instruction::Binary {
+                    operator: BinaryOperator::Add,
+                    left: Value::Var("tmp.1".into()),
+                    right: Value::Constant(Const::Int(1)),
+                    dst: Value::Var(".tmp.0".into())
+                },
+                Instruction::Binary {
+                    operator: BinaryOperator::Multiply,
+                    left: Value::Var(".tmp.0".into()),
+                    right: Value::Var("__tmp.0".into()),
+                    dst: Value::Var(".tmp.1".into())
+                },
+                Instruction::Return(Value::Var(".tmp.1".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn tacky_function_calls() {
+        let program = tacky_source("int f(int a);\nint main(void) { return f(1 + 2); }");
+        // Only definitions are emitted.
+        let [main] = &program.functions[..] else {
+            panic!("unexpected functions {:?}", program.functions);
+        };
+        assert_eq!(
+            main.body[..2],
+            [
+                Instruction::Binary {
+                    operator: BinaryOperator::Add,
+                    left: Value::Constant(Const::Int(1)),
+                    right: Value::Constant(Const::Int(2)),
+                    dst: Value::Var(".tmp.0".into())
+                },
+                Instruction::FunCall {
+                    name: "f".into(),
+                    args: vec![Value::Var(".tmp.0".into())],
+                    dst: Value::Var(".tmp.1".into())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tacky_static_variables() {
+        let program = tacky_source(
+            "static int a;\nint b = 3;\nextern int c;\nint main(void) { static int d = 2; extern int e; int f = 1; return d + f; }\nstatic int a = 5;\nint e;",
+        );
+        assert_eq!(
+            program.static_variables,
+            [
+                StaticVariable {
+                    name: "a".into(),
+                    global: false,
+                    init: Const::Int(5)
+                },
+                StaticVariable {
+                    name: "b".into(),
+                    global: true,
+                    init: Const::Int(3)
+                },
+                StaticVariable {
+                    name: "d.0".into(),
+                    global: false,
+                    init: Const::Int(2)
+                },
+                StaticVariable {
+                    name: "e".into(),
+                    global: true,
+                    init: Const::Int(0)
+                },
+            ]
+        );
+        // Only `f` is initialized at run time.
+        assert_eq!(
+            program.functions[0].body[0],
+            Instruction::Copy {
+                src: Value::Constant(Const::Int(1)),
+                dst: Value::Var("f.1".into())
+            }
+        );
+        assert!(program.functions[0].global);
+    }
+
+    #[test]
+    fn tacky_conversions() {
+        let source = "long main(void) { int i = -1; unsigned u = 2; long l = i; unsigned long ul = u; return (int) l + (unsigned) ul; }";
+        let program = parser::parse(lexer::lex(source)).unwrap();
+        let (program, mut symbols) = semantic::analyze(program).unwrap();
+        let program = tacky(program, &mut symbols);
+        let conversions = program.functions[0]
+            .body
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::SignExtend { src, dst } => Some(("sign", src, dst)),
+                Instruction::ZeroExtend { src, dst } => Some(("zero", src, dst)),
+                Instruction::Truncate { src, dst } => Some(("truncate", src, dst)),
+                _ => None,
+            })
+            .map(|(kind, src, dst)| {
+                let Value::Var(dst) = dst else {
+                    panic!("conversion into a constant");
+                };
+                (kind, src.clone(), symbols[dst].ty.clone())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            conversions,
+            [
+                ("sign", Value::Var("i.0".into()), Type::Long),
+                ("zero", Value::Var("u.1".into()), Type::ULong),
+                ("truncate", Value::Var("l.2".into()), Type::Int),
+                ("truncate", Value::Var("ul.3".into()), Type::UInt),
+                // The unsigned sum is zero-extended to the return type.
+                ("zero", Value::Var(".tmp.7".into()), Type::Long),
+            ]
+        );
+    }
 }